@@ -8,8 +8,17 @@
 #![deny(clippy::all)]
 #![warn(rust_2018_idioms)]
 
-use std::{collections::HashMap, ops::Deref};
-
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    net::IpAddr,
+    ops::Deref,
+    path::{Component, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use ipnet::IpNet;
+use schemars::{gen::SchemaGenerator, gen::SchemaSettings, schema::Schema, JsonSchema};
 use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 use url::Url;
 
@@ -22,8 +31,42 @@ pub const CONFIG_TEMPLATE: &str = r#"## Configuration for a WASI application in
 #      "--argument2=foo"
 # ]
 
+## Exported function called instead of the default function, e.g. of a WASI reactor
+# entrypoint = "run"
+
 ## Steward
-# steward = "https://attest.profian.com"
+# steward = "https://attest.profian.com" # or a list of URLs tried in order
+# steward_shuffle = true # try the list of URLs in random order
+# steward_pin = "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=" # SHA-256 of the Steward's public key
+# steward_retries = 3 # retry failed attestations with exponential backoff
+# steward_backoff_ms = 100 # wait 100ms before the first retry
+# steward_max_backoff_ms = 2000 # wait at most 2s between retries
+# steward_deadline_secs = 60 # give up attesting after a minute
+# key_algorithm = "P-384" # algorithm of the workload identity key
+# subject = "CN=example.com" # subject of the workload identity certificate
+# san = ["example.com", "192.0.2.1"] # DNS names and IP addresses of the workload
+# attestation_cache_size = 16 # reuse the reports of the last 16 distinct nonces
+
+## Fuel budget limiting the amount of instructions executed
+# fuel = 1000000000
+
+## Limit of the size of each linear memory in bytes
+# memory_limit_bytes = 268435456
+
+## Time reported by the system clock in deterministic executions
+# deterministic_time_secs = 1672531200
+
+## Optimization level of the compiler, trading execution speed for faster compilation
+# cranelift_opt_level = "none"
+
+## Ed25519 public key, which must have signed the Wasm module
+# module_signing_key = "11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=" # base64-encoded
+
+## Imports of host functions the application must not use
+# denied_imports = ["host::attestation_report"] # or a whole module, e.g. "host"
+
+## Host environment variables passed through to the application
+# env_passthrough = ["LANG"]
 
 ## Environment variables
 # [env]
@@ -44,16 +87,34 @@ kind = "stderr"
 # [[files]]
 # name = "listen"
 # kind = "listen"
-# prot = "tls" # or prot = "tcp"
+# prot = "tls" # or prot = "tcp" or prot = "udp"
 # port = 12345
 
 ## An outgoing connected socket
 # [[files]]
 # name = "stream"
 # kind = "connect"
-# prot = "tls" # or prot = "tcp"
+# prot = "tls" # or prot = "tcp" or prot = "udp"
 # host = "localhost"
 # port = 23456
+
+## A pre-opened host directory
+# [[files]]
+# name = "/data"
+# kind = "dir"
+# host_path = "/var/lib/data"
+# read_only = true
+
+## An anonymous pipe, the first file of a pair is the read end and the second one the write end
+# [[files]]
+# name = "rx"
+# kind = "pipe"
+# pair = 0
+#
+# [[files]]
+# name = "tx"
+# kind = "pipe"
+# pair = 0
 "#;
 
 const fn default_tcp_port() -> u16 {
@@ -68,7 +129,7 @@ fn default_addr() -> String {
     "::".into()
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, JsonSchema)]
 /// Name assigned to a file descriptor
 ///
 /// This is used to export the `FD_NAMES` environment variable,
@@ -115,14 +176,111 @@ impl<'de> Deserialize<'de> for FileName {
     }
 }
 
+/// Subject alternative name of the workload identity certificate
+///
+/// IP addresses are distinguished from DNS names by their syntax.
+/// DNS names may start with a `*.` wildcard label.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SubjectAltName {
+    /// DNS name
+    Dns(String),
+
+    /// IP address
+    Ip(IpAddr),
+}
+
+impl TryFrom<String> for SubjectAltName {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        if let Ok(ip) = name.parse() {
+            return Ok(Self::Ip(ip));
+        }
+
+        let labels = name.strip_prefix("*.").unwrap_or(&name);
+        let valid = labels.len() <= 253
+            && labels.split('.').all(|label| {
+                (1..=63).contains(&label.len())
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            });
+        if valid {
+            Ok(Self::Dns(name))
+        } else {
+            Err(format!(
+                "`{name}` is neither a valid DNS name nor an IP address"
+            ))
+        }
+    }
+}
+
+impl TryFrom<&str> for SubjectAltName {
+    type Error = <SubjectAltName as TryFrom<String>>::Error;
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        String::from(name).try_into()
+    }
+}
+
+impl fmt::Display for SubjectAltName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dns(name) => f.write_str(name),
+            Self::Ip(ip) => ip.fmt(f),
+        }
+    }
+}
+
+impl From<SubjectAltName> for String {
+    fn from(name: SubjectAltName) -> Self {
+        name.to_string()
+    }
+}
+
+impl JsonSchema for SubjectAltName {
+    fn schema_name() -> String {
+        "SubjectAltName".into()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+/// Single value or array of values
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    /// Single value
+    One(T),
+
+    /// Array of values
+    Many(Vec<T>),
+}
+
+fn deserialize_one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => Ok(vec![one]),
+        OneOrMany::Many(many) => Ok(many),
+    }
+}
+
 /// The configuration for an Enarx WASI application
 ///
-/// This struct can be used with any serde deserializer.
+/// This struct can be used with any serde deserializer,
+/// [Config::from_toml_str] and [Config::to_toml_string] handle the canonical TOML format.
 ///
 /// # Examples
 ///
 /// ```
-/// extern crate toml;
 /// use enarx_config::Config;
 /// const CONFIG: &str = r#"
 /// [[files]]
@@ -132,17 +290,123 @@ impl<'de> Deserialize<'de> for FileName {
 /// port = 12345
 /// "#;
 ///
-/// let config: Config = toml::from_str(CONFIG).unwrap();
+/// let config = Config::from_toml_str(CONFIG).unwrap();
+/// assert_eq!(Config::from_toml_str(&config.to_toml_string().unwrap()).unwrap(), config);
 /// ```
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
-    /// An optional Steward URL
+    /// Steward URLs, which are tried in order until attestation succeeds
+    ///
+    /// A single URL string is accepted as well.
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    #[schemars(with = "OneOrMany<Url>")]
+    pub steward: Vec<Url>,
+
+    /// Pins of the TLS certificate of the Steward
+    ///
+    /// Each pin is the base64-encoded SHA-256 digest of the DER-encoded `SubjectPublicKeyInfo`
+    /// of a certificate. If any pins are given, a certificate in the chain presented by the
+    /// Steward must match one of them. A single pin string is accepted as well.
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    #[schemars(with = "OneOrMany<String>")]
+    pub steward_pin: Vec<String>,
+
+    /// Try the Steward URLs in random order to spread the load across them
+    #[serde(default)]
+    pub steward_shuffle: bool,
+
+    /// Number of retries of a failed attestation to each Steward URL
+    #[serde(default)]
+    pub steward_retries: u32,
+
+    /// Delay in milliseconds before the first retry of a failed attestation
+    ///
+    /// The delay doubles after every retry up to `steward_max_backoff_ms`.
     #[serde(default)]
-    pub steward: Option<Url>,
+    pub steward_backoff_ms: Option<u64>,
+
+    /// Maximum delay in milliseconds between retries of a failed attestation
+    #[serde(default)]
+    pub steward_max_backoff_ms: Option<u64>,
+
+    /// Maximum total time in seconds spent attesting to the Steward URLs including retries
+    #[serde(default)]
+    pub steward_deadline_secs: Option<u64>,
+
+    /// Algorithm of the key of the workload identity
+    ///
+    /// If not specified, the algorithm is chosen according to the platform.
+    #[serde(default)]
+    pub key_algorithm: Option<KeyAlgorithm>,
+
+    /// Distinguished name of the subject of the workload identity certificate, e.g. `CN=host`
+    #[serde(default)]
+    pub subject: Option<String>,
+
+    /// Subject alternative names of the workload identity certificate
+    #[serde(default)]
+    pub san: Vec<SubjectAltName>,
+
+    /// Validity period in seconds of the self-signed certificate used without a Steward
+    ///
+    /// If not specified, the certificate is valid for 24 hours.
+    #[serde(default)]
+    pub self_signed_cert_validity_secs: Option<u64>,
+
+    /// Number of attestation reports cached by their nonce during an execution
+    ///
+    /// Caching is disabled by default, as reports may embed a timestamp or counter.
+    #[serde(default)]
+    pub attestation_cache_size: usize,
 
     /// The arguments to provide to the application
     #[serde(default)]
-    pub args: Vec<String>,
+    pub args: Vec<Value>,
+
+    /// Name of the exported function to call instead of the default function
+    ///
+    /// The default function is the export named `""` or `_start`. A WASI reactor module,
+    /// which exports `_initialize` instead, is initialized before the function is called.
+    #[serde(default)]
+    pub entrypoint: Option<String>,
+
+    /// An optional fuel budget limiting the amount of instructions the application may execute
+    #[serde(default)]
+    pub fuel: Option<u64>,
+
+    /// An optional limit of the size of each linear memory of the application in bytes
+    #[serde(default, alias = "max_memory")]
+    pub memory_limit_bytes: Option<u64>,
+
+    /// Time in seconds since the Unix epoch reported by the system clock in deterministic
+    /// executions, which defaults to the Unix epoch
+    #[serde(default)]
+    pub deterministic_time_secs: Option<u64>,
+
+    /// An optional optimization level of the compiler of the Wasm module
+    ///
+    /// If not specified, the module is optimized for speed.
+    #[serde(default)]
+    pub cranelift_opt_level: Option<OptLevel>,
+
+    /// An optional base64-encoded Ed25519 public key, which must have signed the Wasm module
+    ///
+    /// If specified, the application is only executed with a valid signature of its Wasm module.
+    #[serde(default)]
+    pub module_signing_key: Option<String>,
+
+    /// Imports of the runtime the application must not use
+    ///
+    /// Each entry is either a module name, e.g. `host`, or a module name and a function name
+    /// separated by `::`, e.g. `host::attestation_report`.
+    #[serde(default)]
+    pub denied_imports: Vec<String>,
+
+    /// Names of host environment variables to pass through to the application
+    ///
+    /// Variables not set on the host are skipped. Entries of `env` take precedence.
+    #[serde(default)]
+    pub env_passthrough: Vec<String>,
 
     /// The array of pre-opened file descriptors
     #[serde(default)]
@@ -150,7 +414,7 @@ pub struct Config {
 
     /// The environment variables to provide to the application
     #[serde(default)]
-    pub env: HashMap<String, String>,
+    pub env: HashMap<String, Value>,
 }
 
 impl Default for Config {
@@ -164,30 +428,283 @@ impl Default for Config {
         Self {
             env: HashMap::new(),
             args: vec![],
+            entrypoint: None,
             files,
-            steward: None, // TODO: Default to a deployed Steward instance
+            steward: vec![], // TODO: Default to a deployed Steward instance
+            steward_pin: vec![],
+            steward_shuffle: false,
+            steward_retries: 0,
+            steward_backoff_ms: None,
+            steward_max_backoff_ms: None,
+            steward_deadline_secs: None,
+            key_algorithm: None,
+            subject: None,
+            san: vec![],
+            self_signed_cert_validity_secs: None,
+            attestation_cache_size: 0,
+            fuel: None,
+            memory_limit_bytes: None,
+            deterministic_time_secs: None,
+            cranelift_opt_level: None,
+            module_signing_key: None,
+            denied_imports: vec![],
+            env_passthrough: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// Parses a configuration in the TOML format of `Enarx.toml`
+    pub fn from_toml_str(s: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Parses a configuration like [Config::from_toml_str] and interpolates host variables
+    ///
+    /// References to variables in the strings of `args`, `env` and `steward` are substituted
+    /// with the values returned by `var`, see the `Enarx.toml` documentation for the syntax.
+    /// `var` must return `None` for the variables, which may not be referenced. The
+    /// `env_passthrough` of the configuration itself does not allow referencing a variable.
+    pub fn from_toml_str_interpolated(
+        s: &str,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<Self> {
+        let mut value: toml::Value = toml::from_str(s)?;
+        if let Some(table) = value.as_table_mut() {
+            for key in ["args", "env", "steward"] {
+                let strings: Box<dyn Iterator<Item = &mut toml::Value>> = match table.get_mut(key) {
+                    Some(toml::Value::Array(values)) => Box::new(values.iter_mut()),
+                    Some(toml::Value::Table(values)) => {
+                        Box::new(values.iter_mut().map(|(_, value)| value))
+                    }
+                    Some(value) => Box::new(std::iter::once(value)),
+                    None => continue,
+                };
+                for value in strings {
+                    if let toml::Value::String(s) = value {
+                        *s = interpolate(s, &var)
+                            .with_context(|| format!("failed to interpolate `{key}`"))?;
+                    }
+                }
+            }
+        }
+        Ok(value.try_into()?)
+    }
+
+    /// Returns the JSON Schema of `Enarx.toml`, e.g. for completion and validation in editors
+    ///
+    /// Optional fields are marked as `nullable`.
+    pub fn json_schema() -> serde_json::Value {
+        let schema = SchemaSettings::draft07()
+            .with(|settings| settings.option_nullable = true)
+            .into_generator()
+            .into_root_schema_for::<Self>();
+        serde_json::to_value(schema).expect("failed to serialize JSON Schema")
+    }
+
+    /// Serializes the configuration in the TOML format of `Enarx.toml`
+    ///
+    /// Arrays mixing inline values and files, e.g. in `args`, cannot be serialized.
+    pub fn to_toml_string(&self) -> anyhow::Result<String> {
+        // TOML requires values to precede tables, which the fields of `Config` do not,
+        // so order them by serializing a `toml::Value` instead of the struct itself.
+        let value = toml::Value::try_from(self)?;
+        anyhow::ensure!(
+            !has_mixed_array(&value),
+            "arrays mixing inline values and files cannot be serialized"
+        );
+        Ok(toml::to_string(&value)?)
+    }
+}
+
+/// Checks for arrays mixing tables and other values, which `toml` serializes to invalid TOML
+fn has_mixed_array(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::Array(values) => {
+            let tables = values.iter().filter(|v| v.is_table()).count();
+            (tables > 0 && tables < values.len()) || values.iter().any(has_mixed_array)
+        }
+        toml::Value::Table(table) => table.values().any(has_mixed_array),
+        _ => false,
+    }
+}
+
+/// Substitutes `${VAR}` and `${VAR:-fallback}` in `s` with the value of `VAR` returned by `var`
+///
+/// The fallback is used, if `VAR` is unset. `$${` escapes a literal `${`.
+fn interpolate(s: &str, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some(tail) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix("${") {
+            let end = tail
+                .find('}')
+                .with_context(|| format!("unterminated variable reference in `{s}`"))?;
+            let (name, fallback) = match tail[..end].split_once(":-") {
+                Some((name, fallback)) => (name, Some(fallback)),
+                None => (&tail[..end], None),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                bail!("invalid variable name `{name}` in `{s}`");
+            }
+            match var(name).or_else(|| fallback.map(String::from)) {
+                Some(value) => out.push_str(&value),
+                None => bail!("variable `{name}` is not set"),
+            }
+            rest = &tail[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Value of an argument or environment variable
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Value {
+    /// Inline string
+    Inline(String),
+
+    /// Contents of a host file
+    File {
+        /// Absolute path of the host file
+        file: PathBuf,
+
+        /// Trim leading and trailing whitespace of the contents
+        #[serde(default)]
+        trim: bool,
+    },
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::Inline(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::Inline(value.into())
+    }
+}
+
+impl Value {
+    /// Returns the inline string or reads the contents of the host file
+    ///
+    /// The path of the host file must be absolute and must not contain `..` components.
+    pub fn resolve(&self) -> io::Result<String> {
+        match self {
+            Self::Inline(value) => Ok(value.clone()),
+            Self::File { file, trim } => {
+                if !file.is_absolute() || file.components().any(|c| c == Component::ParentDir) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("path `{}` must be absolute and normalized", file.display()),
+                    ));
+                }
+                let value = fs::read_to_string(file)?;
+                if *trim {
+                    Ok(value.trim().into())
+                } else {
+                    Ok(value)
+                }
+            }
         }
     }
 }
 
+/// Capability of a file descriptor, which permits a group of WASI functions on it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FileCap {
+    /// Read data with `fd_read` or `sock_recv` and accept connections with `sock_accept`
+    Read,
+
+    /// Write data with `fd_write` or `sock_send`
+    Write,
+
+    /// Change the offset of a file with `fd_seek`
+    Seek,
+
+    /// Query the offset of a file with `fd_tell`
+    Tell,
+
+    /// Wait for the file descriptor to become ready with `poll_oneoff`
+    Poll,
+
+    /// Synchronize the data of a file with `fd_datasync`
+    Datasync,
+
+    /// Synchronize the data and metadata of a file with `fd_sync`
+    Sync,
+
+    /// Announce the access pattern of a file with `fd_advise`
+    Advise,
+
+    /// Allocate space for a file with `fd_allocate`
+    Allocate,
+
+    /// Change the flags of the file descriptor, e.g. `O_NONBLOCK`, with `fd_fdstat_set_flags`
+    FdstatSetFlags,
+
+    /// Query the attributes of a file with `fd_filestat_get`
+    FilestatGet,
+
+    /// Truncate or extend a file with `fd_filestat_set_size`
+    FilestatSetSize,
+
+    /// Change the timestamps of a file with `fd_filestat_set_times`
+    FilestatSetTimes,
+}
+
 /// `/dev/null` file descriptor
-#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct NullFile {
     /// Name assigned to the file descriptor
     name: Option<FileName>,
+
+    /// Capabilities the file descriptor is restricted to, all of its kind if not set
+    caps: Option<Vec<FileCap>>,
+}
+
+/// File descriptor capturing the written data in memory
+///
+/// The captured data is returned to the caller of the runtime,
+/// e.g. to capture the output of an application embedded in a library.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureFile {
+    /// Name assigned to the file descriptor
+    name: Option<FileName>,
+
+    /// Capabilities the file descriptor is restricted to, all of its kind if not set
+    caps: Option<Vec<FileCap>>,
 }
 
 /// Standard I/O file descriptor
-#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct StdioFile {
     /// Name assigned to the file descriptor
     name: Option<FileName>,
+
+    /// Capabilities the file descriptor is restricted to, all of its kind if not set
+    caps: Option<Vec<FileCap>>,
 }
 
 /// File descriptor of a listen socket
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+// Listen files are only parsed once, so the size of the TLS variant does not matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "prot", deny_unknown_fields)]
 pub enum ListenFile {
     /// TLS listen socket
@@ -196,6 +713,9 @@ pub enum ListenFile {
         /// Name assigned to the file descriptor
         name: FileName,
 
+        /// Capabilities the file descriptor is restricted to, all of its kind if not set
+        caps: Option<Vec<FileCap>>,
+
         /// Address to listen on
         #[serde(default = "default_addr")]
         addr: String,
@@ -203,6 +723,81 @@ pub enum ListenFile {
         /// Port to listen on
         #[serde(default = "default_tls_port")]
         port: u16,
+
+        /// Maximum length of the queue of pending connections, defaults to 128
+        backlog: Option<u32>,
+
+        /// Set `SO_REUSEADDR`, defaults to the platform default
+        reuse_addr: Option<bool>,
+
+        /// Set `SO_REUSEPORT` to share the port with other sockets, only supported on Unix
+        #[serde(default)]
+        reuse_port: bool,
+
+        /// Disable Nagle's algorithm on accepted connections by setting `TCP_NODELAY`
+        #[serde(default)]
+        nodelay: bool,
+
+        /// Enable TCP keepalive probes on accepted connections
+        keepalive: Option<TcpKeepalive>,
+
+        /// Maximum time in milliseconds a read on an accepted connection waits for data,
+        /// including the TLS handshake, unlimited if not set
+        read_timeout_ms: Option<u64>,
+
+        /// Application protocols offered during the TLS handshake
+        #[serde(default)]
+        alpn: Vec<String>,
+
+        /// Minimum TLS protocol version
+        #[serde(default)]
+        min_version: TlsVersion,
+
+        /// TLS cipher suites in order of preference, defaults are used if empty
+        #[serde(default)]
+        cipher_suites: Vec<CipherSuite>,
+
+        /// Require clients to authenticate with a certificate
+        #[serde(default)]
+        client_auth: bool,
+
+        /// PEM-encoded trust anchors for client certificates, which default to the root
+        /// of the keep certificate chain
+        client_ca: Option<String>,
+
+        /// Maximum rate of accepted connections per second, unlimited if not set
+        max_connections_per_second: Option<u32>,
+
+        /// Maximum amount of accepted connections open at the same time, unlimited if not set
+        max_concurrent_connections: Option<u32>,
+
+        /// Maximum time in milliseconds each accepted connection is drained for, when it is
+        /// closed, defaults to 5000
+        drain_timeout_ms: Option<u64>,
+
+        /// Base64-encoded DER OCSP response stapled to the keep certificate in TLS handshakes
+        ///
+        /// It replaces any OCSP response provided by the Steward.
+        ocsp_response: Option<String>,
+
+        /// Path of a file on the host containing the DER OCSP response stapled to the keep
+        /// certificate, as an alternative to `ocsp_response`
+        ocsp_response_path: Option<PathBuf>,
+
+        /// Certificates presented to clients requesting the given server name via SNI
+        ///
+        /// Clients requesting any other server name, or none at all, are presented
+        /// the keep certificate.
+        #[serde(default)]
+        sni_certs: HashMap<String, CertAndKey>,
+
+        /// Networks of the clients allowed to connect, all clients are allowed if not set
+        #[schemars(with = "Option<Vec<String>>")]
+        allow_ips: Option<Vec<IpNet>>,
+
+        /// Networks of the clients not allowed to connect, which take precedence over `allow_ips`
+        #[schemars(with = "Option<Vec<String>>")]
+        deny_ips: Option<Vec<IpNet>>,
     },
 
     /// TCP listen socket
@@ -211,6 +806,9 @@ pub enum ListenFile {
         /// Name assigned to the file descriptor
         name: FileName,
 
+        /// Capabilities the file descriptor is restricted to, all of its kind if not set
+        caps: Option<Vec<FileCap>>,
+
         /// Address to listen on
         #[serde(default = "default_addr")]
         addr: String,
@@ -218,11 +816,58 @@ pub enum ListenFile {
         /// Port to listen on
         #[serde(default = "default_tcp_port")]
         port: u16,
+
+        /// Maximum length of the queue of pending connections, defaults to 128
+        backlog: Option<u32>,
+
+        /// Set `SO_REUSEADDR`, defaults to the platform default
+        reuse_addr: Option<bool>,
+
+        /// Set `SO_REUSEPORT` to share the port with other sockets, only supported on Unix
+        #[serde(default)]
+        reuse_port: bool,
+
+        /// Disable Nagle's algorithm on accepted connections by setting `TCP_NODELAY`
+        #[serde(default)]
+        nodelay: bool,
+
+        /// Enable TCP keepalive probes on accepted connections
+        keepalive: Option<TcpKeepalive>,
+    },
+
+    /// UDP socket bound to a local address
+    #[serde(rename = "udp")]
+    Udp {
+        /// Name assigned to the file descriptor
+        name: FileName,
+
+        /// Capabilities the file descriptor is restricted to, all of its kind if not set
+        caps: Option<Vec<FileCap>>,
+
+        /// Address to bind to
+        #[serde(default = "default_addr")]
+        addr: String,
+
+        /// Port to bind to
+        port: u16,
+    },
+
+    /// Unix domain stream socket bound to a path on the host
+    #[serde(rename = "unix")]
+    Unix {
+        /// Name assigned to the file descriptor
+        name: FileName,
+
+        /// Capabilities the file descriptor is restricted to, all of its kind if not set
+        caps: Option<Vec<FileCap>>,
+
+        /// Path of the socket on the host
+        path: PathBuf,
     },
 }
 
 /// File descriptor of a stream socket
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "prot", deny_unknown_fields)]
 pub enum ConnectFile {
     /// TLS stream socket
@@ -231,12 +876,51 @@ pub enum ConnectFile {
         /// Name assigned to the file descriptor
         name: Option<FileName>,
 
+        /// Capabilities the file descriptor is restricted to, all of its kind if not set
+        caps: Option<Vec<FileCap>>,
+
         /// Host address to connect to
         host: String,
 
         /// Port to connect to
         #[serde(default = "default_tls_port")]
         port: u16,
+
+        /// Resume TLS sessions established by previous connections
+        #[serde(default)]
+        session_resumption: bool,
+
+        /// Application protocols offered during the TLS handshake
+        #[serde(default)]
+        alpn: Vec<String>,
+
+        /// Minimum TLS protocol version
+        #[serde(default)]
+        min_version: TlsVersion,
+
+        /// TLS cipher suites in order of preference, defaults are used if empty
+        #[serde(default)]
+        cipher_suites: Vec<CipherSuite>,
+
+        /// PEM-encoded client certificate chain presented instead of the keep certificate
+        client_cert: Option<String>,
+
+        /// PEM-encoded private key of the client certificate
+        client_key: Option<String>,
+
+        /// Maximum time in milliseconds to wait for the TCP connection to be established
+        connect_timeout_ms: Option<u64>,
+
+        /// Disable Nagle's algorithm by setting `TCP_NODELAY`
+        #[serde(default)]
+        nodelay: bool,
+
+        /// Maximum time in milliseconds a read waits for data, including the TLS handshake,
+        /// unlimited if not set
+        read_timeout_ms: Option<u64>,
+
+        /// Enable TCP keepalive probes
+        keepalive: Option<TcpKeepalive>,
     },
 
     /// TCP stream socket
@@ -245,17 +929,261 @@ pub enum ConnectFile {
         /// Name assigned to the file descriptor
         name: Option<FileName>,
 
+        /// Capabilities the file descriptor is restricted to, all of its kind if not set
+        caps: Option<Vec<FileCap>>,
+
         /// Host address to connect to
         host: String,
 
         /// Port to connect to
         #[serde(default = "default_tcp_port")]
         port: u16,
+
+        /// Maximum time in milliseconds to wait for the TCP connection to be established
+        connect_timeout_ms: Option<u64>,
+
+        /// Disable Nagle's algorithm by setting `TCP_NODELAY`
+        #[serde(default)]
+        nodelay: bool,
+
+        /// Enable TCP keepalive probes
+        keepalive: Option<TcpKeepalive>,
+    },
+
+    /// UDP socket connected to a remote address
+    #[serde(rename = "udp")]
+    Udp {
+        /// Name assigned to the file descriptor
+        name: Option<FileName>,
+
+        /// Capabilities the file descriptor is restricted to, all of its kind if not set
+        caps: Option<Vec<FileCap>>,
+
+        /// Host address to connect to
+        host: String,
+
+        /// Port to connect to
+        port: u16,
     },
+
+    /// Unix domain stream socket connected to a path on the host
+    #[serde(rename = "unix")]
+    Unix {
+        /// Name assigned to the file descriptor
+        name: FileName,
+
+        /// Capabilities the file descriptor is restricted to, all of its kind if not set
+        caps: Option<Vec<FileCap>>,
+
+        /// Path of the socket on the host
+        path: PathBuf,
+    },
+}
+
+/// PEM-encoded certificate chain and the matching private key
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CertAndKey {
+    /// PEM-encoded certificate chain, starting with the end-entity certificate
+    pub cert: String,
+
+    /// PEM-encoded private key of the end-entity certificate
+    pub key: String,
+}
+
+/// Parameters of TCP keepalive probes
+///
+/// Parameters, which are not specified, default to the settings of the OS.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TcpKeepalive {
+    /// Time in seconds the connection is idle before the first probe is sent
+    pub idle_secs: Option<u64>,
+
+    /// Time in seconds between two probes, only supported on Linux
+    pub interval_secs: Option<u64>,
+
+    /// Number of unanswered probes, after which the connection is dropped, only supported on Linux
+    pub count: Option<u32>,
+}
+
+/// Algorithm of the key of the workload identity
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum KeyAlgorithm {
+    /// ECDSA with the NIST P-256 curve
+    #[serde(rename = "P-256")]
+    P256,
+
+    /// ECDSA with the NIST P-384 curve
+    #[serde(rename = "P-384")]
+    P384,
+
+    /// EdDSA with Curve25519
+    #[serde(rename = "Ed25519")]
+    Ed25519,
+}
+
+/// Optimization level of the compiler
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OptLevel {
+    /// No optimizations, which compiles fastest
+    None,
+
+    /// Optimizations for execution speed
+    Speed,
+
+    /// Optimizations for execution speed and code size
+    SpeedAndSize,
+}
+
+/// TLS protocol version
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+pub enum TlsVersion {
+    /// TLS 1.2
+    #[serde(rename = "1.2")]
+    Tls12,
+
+    /// TLS 1.3
+    #[default]
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+/// TLS cipher suite
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum CipherSuite {
+    /// TLS 1.3 AES-256-GCM with SHA-384
+    #[serde(rename = "TLS13_AES_256_GCM_SHA384")]
+    Tls13Aes256GcmSha384,
+
+    /// TLS 1.3 AES-128-GCM with SHA-256
+    #[serde(rename = "TLS13_AES_128_GCM_SHA256")]
+    Tls13Aes128GcmSha256,
+
+    /// TLS 1.3 ChaCha20-Poly1305 with SHA-256
+    #[serde(rename = "TLS13_CHACHA20_POLY1305_SHA256")]
+    Tls13Chacha20Poly1305Sha256,
+
+    /// TLS 1.2 ECDHE-ECDSA AES-256-GCM with SHA-384
+    #[serde(rename = "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384")]
+    TlsEcdheEcdsaWithAes256GcmSha384,
+
+    /// TLS 1.2 ECDHE-ECDSA AES-128-GCM with SHA-256
+    #[serde(rename = "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256")]
+    TlsEcdheEcdsaWithAes128GcmSha256,
+
+    /// TLS 1.2 ECDHE-ECDSA ChaCha20-Poly1305 with SHA-256
+    #[serde(rename = "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256")]
+    TlsEcdheEcdsaWithChacha20Poly1305Sha256,
+
+    /// TLS 1.2 ECDHE-RSA AES-256-GCM with SHA-384
+    #[serde(rename = "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384")]
+    TlsEcdheRsaWithAes256GcmSha384,
+
+    /// TLS 1.2 ECDHE-RSA AES-128-GCM with SHA-256
+    #[serde(rename = "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256")]
+    TlsEcdheRsaWithAes128GcmSha256,
+
+    /// TLS 1.2 ECDHE-RSA ChaCha20-Poly1305 with SHA-256
+    #[serde(rename = "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256")]
+    TlsEcdheRsaWithChacha20Poly1305Sha256,
+}
+
+/// Directory pre-opened from the host
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DirFile {
+    /// Name assigned to the file descriptor, which is also the path the directory is
+    /// pre-opened at in the guest
+    pub name: FileName,
+
+    /// Capabilities the file descriptor is restricted to, all of its kind if not set
+    pub caps: Option<Vec<FileCap>>,
+
+    /// Path of the directory on the host
+    pub host_path: PathBuf,
+
+    /// Whether the directory is mounted read-only
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// One end of an anonymous pipe
+///
+/// The first file with a given `pair` index is the read end of the pipe,
+/// the second one is the write end.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PipeFile {
+    /// Name assigned to the file descriptor
+    pub name: FileName,
+
+    /// Capabilities the file descriptor is restricted to, all of its kind if not set
+    pub caps: Option<Vec<FileCap>>,
+
+    /// Index identifying the pipe, which this file is an end of
+    pub pair: u32,
+}
+
+/// A read-only in-memory file
+///
+/// The data is stored in an anonymous memory-backed file on the host,
+/// which is never written to disk.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MemFdFile {
+    /// Name assigned to the file descriptor
+    pub name: FileName,
+
+    /// Capabilities the file descriptor is restricted to, all of its kind if not set
+    pub caps: Option<Vec<FileCap>>,
+
+    /// Contents of the file
+    pub data: Vec<u8>,
+
+    /// Prevent modifications of the contents by sealing the file
+    #[serde(default)]
+    pub sealed: bool,
+}
+
+/// A read-only file with contents given in the configuration or read from the host
+///
+/// Exactly one of `data` and `host_path` must be specified.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct InlineFile {
+    /// Name assigned to the file descriptor
+    pub name: FileName,
+
+    /// Capabilities the file descriptor is restricted to, all of its kind if not set
+    pub caps: Option<Vec<FileCap>>,
+
+    /// Base64-encoded contents of the file
+    pub data: Option<String>,
+
+    /// Path of a file on the host to read the contents from
+    pub host_path: Option<PathBuf>,
+}
+
+/// A read-only file with contents sealed to the platform, which are unsealed in the keep
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SealedFile {
+    /// Name assigned to the file descriptor
+    pub name: FileName,
+
+    /// Capabilities the file descriptor is restricted to, all of its kind if not set
+    pub caps: Option<Vec<FileCap>>,
+
+    /// Base64-encoded contents of the file encrypted with the sealing key of the platform
+    pub ciphertext: String,
 }
 
 /// Parameters for a pre-opened file descriptor
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "kind", deny_unknown_fields)]
 pub enum File {
     /// File descriptor of `/dev/null`
@@ -281,20 +1209,79 @@ pub enum File {
     /// File descriptor of a stream socket
     #[serde(rename = "connect")]
     Connect(ConnectFile),
+
+    /// File descriptor of a pre-opened host directory
+    #[serde(rename = "dir")]
+    Dir(DirFile),
+
+    /// File descriptor of an end of an anonymous pipe
+    #[serde(rename = "pipe")]
+    Pipe(PipeFile),
+
+    /// File descriptor capturing the written data in memory
+    #[serde(rename = "capture")]
+    Capture(CaptureFile),
+
+    /// File descriptor of a read-only in-memory file
+    #[serde(rename = "memfd")]
+    MemFd(MemFdFile),
+
+    /// File descriptor of a read-only file with fixed contents
+    #[serde(rename = "inline")]
+    Inline(InlineFile),
+
+    /// File descriptor of a read-only file with contents sealed to the platform
+    #[serde(rename = "sealed")]
+    Sealed(SealedFile),
 }
 
 impl File {
+    /// Get the capabilities the file descriptor is restricted to, if any
+    pub fn caps(&self) -> Option<&[FileCap]> {
+        let caps = match self {
+            Self::Null(NullFile { caps, .. }) => caps,
+            Self::Stdin(StdioFile { caps, .. }) => caps,
+            Self::Stdout(StdioFile { caps, .. }) => caps,
+            Self::Stderr(StdioFile { caps, .. }) => caps,
+            Self::Listen(ListenFile::Tls { caps, .. }) => caps,
+            Self::Listen(ListenFile::Tcp { caps, .. }) => caps,
+            Self::Listen(ListenFile::Udp { caps, .. }) => caps,
+            Self::Listen(ListenFile::Unix { caps, .. }) => caps,
+            Self::Connect(ConnectFile::Tls { caps, .. }) => caps,
+            Self::Connect(ConnectFile::Tcp { caps, .. }) => caps,
+            Self::Connect(ConnectFile::Udp { caps, .. }) => caps,
+            Self::Connect(ConnectFile::Unix { caps, .. }) => caps,
+            Self::Dir(DirFile { caps, .. }) => caps,
+            Self::Pipe(PipeFile { caps, .. }) => caps,
+            Self::Capture(CaptureFile { caps, .. }) => caps,
+            Self::MemFd(MemFdFile { caps, .. }) => caps,
+            Self::Inline(InlineFile { caps, .. }) => caps,
+            Self::Sealed(SealedFile { caps, .. }) => caps,
+        };
+        caps.as_deref()
+    }
+
     /// Get the name for a file descriptor
     pub fn name(&self) -> &str {
         match self {
-            Self::Null(NullFile { name }) => name.as_deref().unwrap_or("null"),
-            Self::Stdin(StdioFile { name }) => name.as_deref().unwrap_or("stdin"),
-            Self::Stdout(StdioFile { name }) => name.as_deref().unwrap_or("stdout"),
-            Self::Stderr(StdioFile { name }) => name.as_deref().unwrap_or("stderr"),
+            Self::Null(NullFile { name, .. }) => name.as_deref().unwrap_or("null"),
+            Self::Stdin(StdioFile { name, .. }) => name.as_deref().unwrap_or("stdin"),
+            Self::Stdout(StdioFile { name, .. }) => name.as_deref().unwrap_or("stdout"),
+            Self::Stderr(StdioFile { name, .. }) => name.as_deref().unwrap_or("stderr"),
             Self::Listen(ListenFile::Tls { name, .. }) => name,
             Self::Listen(ListenFile::Tcp { name, .. }) => name,
+            Self::Listen(ListenFile::Udp { name, .. }) => name,
+            Self::Listen(ListenFile::Unix { name, .. }) => name,
             Self::Connect(ConnectFile::Tls { name, host, .. }) => name.as_deref().unwrap_or(host),
             Self::Connect(ConnectFile::Tcp { name, host, .. }) => name.as_deref().unwrap_or(host),
+            Self::Connect(ConnectFile::Udp { name, host, .. }) => name.as_deref().unwrap_or(host),
+            Self::Connect(ConnectFile::Unix { name, .. }) => name,
+            Self::Dir(DirFile { name, .. }) => name,
+            Self::Pipe(PipeFile { name, .. }) => name,
+            Self::Capture(CaptureFile { name, .. }) => name.as_deref().unwrap_or("capture"),
+            Self::MemFd(MemFdFile { name, .. }) => name,
+            Self::Inline(InlineFile { name, .. }) => name,
+            Self::Sealed(SealedFile { name, .. }) => name,
         }
     }
 }
@@ -304,6 +1291,8 @@ mod test {
     use super::*;
 
     const CONFIG: &str = r#"
+        env_passthrough = ["LANG"]
+
         [[files]]
         kind = "stdin"
 
@@ -312,6 +1301,8 @@ mod test {
         kind = "listen"
         prot = "tcp"
         port = 9000
+        backlog = 1024
+        reuse_port = true
 
         [[files]]
         kind = "stdout"
@@ -326,28 +1317,147 @@ mod test {
         kind = "connect"
         host = "example.com"
         prot = "tls"
+        alpn = ["h2", "http/1.1"]
+        connect_timeout_ms = 5000
+        read_timeout_ms = 30000
+        nodelay = true
+        keepalive = { idle_secs = 60, count = 3 }
+        min_version = "1.2"
+        cipher_suites = ["TLS13_AES_256_GCM_SHA384", "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"]
+
+        [[files]]
+        name = "/data"
+        kind = "dir"
+        host_path = "/var/lib/data"
+
+        [[files]]
+        kind = "connect"
+        host = "127.0.0.1"
+        prot = "udp"
+        port = 53
+
+        [[files]]
+        name = "rx"
+        kind = "pipe"
+        pair = 0
+
+        [[files]]
+        name = "tx"
+        kind = "pipe"
+        pair = 0
+
+        [[files]]
+        name = "model"
+        kind = "memfd"
+        data = [0, 1, 2]
+        sealed = true
+
+        [[files]]
+        name = "manifest"
+        kind = "inline"
+        data = "aGVsbG8="
+
+        [[files]]
+        name = "password"
+        kind = "sealed"
+        ciphertext = "c2VhbGVk"
+
+        [[files]]
+        name = "sidecar"
+        kind = "connect"
+        prot = "unix"
+        path = "/run/sidecar.sock"
     "#;
 
     #[test]
     fn values() {
         let cfg: Config = toml::from_str(CONFIG).unwrap();
 
+        assert_eq!(cfg.env_passthrough, vec!["LANG".to_string()]);
         assert_eq!(
             cfg.files,
             vec![
                 File::Stdin(Default::default()),
                 File::Listen(ListenFile::Tcp {
                     name: "X".try_into().unwrap(),
+                    caps: None,
                     port: 9000,
-                    addr: default_addr()
+                    addr: default_addr(),
+                    backlog: Some(1024),
+                    reuse_addr: None,
+                    reuse_port: true,
+                    nodelay: false,
+                    keepalive: None,
                 }),
                 File::Stdout(Default::default()),
                 File::Null(Default::default()),
                 File::Stderr(Default::default()),
                 File::Connect(ConnectFile::Tls {
                     name: Default::default(),
+                    caps: None,
                     port: default_tls_port(),
                     host: "example.com".into(),
+                    session_resumption: false,
+                    alpn: vec!["h2".into(), "http/1.1".into()],
+                    min_version: TlsVersion::Tls12,
+                    cipher_suites: vec![
+                        CipherSuite::Tls13Aes256GcmSha384,
+                        CipherSuite::TlsEcdheEcdsaWithAes256GcmSha384,
+                    ],
+                    client_cert: None,
+                    client_key: None,
+                    connect_timeout_ms: Some(5000),
+                    nodelay: true,
+                    keepalive: Some(TcpKeepalive {
+                        idle_secs: Some(60),
+                        interval_secs: None,
+                        count: Some(3),
+                    }),
+                    read_timeout_ms: Some(30000),
+                }),
+                File::Dir(DirFile {
+                    name: "/data".try_into().unwrap(),
+                    caps: None,
+                    host_path: "/var/lib/data".into(),
+                    read_only: false,
+                }),
+                File::Connect(ConnectFile::Udp {
+                    name: Default::default(),
+                    caps: None,
+                    port: 53,
+                    host: "127.0.0.1".into(),
+                }),
+                File::Pipe(PipeFile {
+                    name: "rx".try_into().unwrap(),
+                    caps: None,
+                    pair: 0,
+                }),
+                File::Pipe(PipeFile {
+                    name: "tx".try_into().unwrap(),
+                    caps: None,
+                    pair: 0,
+                }),
+                File::MemFd(MemFdFile {
+                    name: "model".try_into().unwrap(),
+                    caps: None,
+                    data: vec![0, 1, 2],
+                    sealed: true,
+                }),
+                File::Inline(InlineFile {
+                    name: "manifest".try_into().unwrap(),
+                    caps: None,
+                    data: Some("aGVsbG8=".into()),
+                    host_path: None,
+                }),
+                File::Sealed(SealedFile {
+                    name: "password".try_into().unwrap(),
+                    caps: None,
+                    ciphertext: "c2VhbGVk".into(),
+                }),
+                File::Connect(ConnectFile::Unix {
+                    name: "sidecar".try_into().unwrap(),
+                    caps: None,
+                    path: "/run/sidecar.sock".into(),
                 }),
             ]
         );
@@ -360,11 +1470,291 @@ mod test {
         let cfg: Config = toml::from_str(CONFIG).unwrap();
 
         assert_eq!(
-            vec!["stdin", "X", "stdout", "null", "stderr", "example.com"],
+            vec![
+                "stdin",
+                "X",
+                "stdout",
+                "null",
+                "stderr",
+                "example.com",
+                "/data",
+                "127.0.0.1",
+                "rx",
+                "tx",
+                "model",
+                "manifest",
+                "password",
+                "sidecar"
+            ],
             cfg.files.iter().map(|f| f.name()).collect::<Vec<_>>()
         );
     }
 
+    #[test]
+    fn key_algorithm() {
+        let cfg: Config = toml::from_str("").unwrap();
+        assert_eq!(cfg.key_algorithm, None);
+
+        let cfg: Config = toml::from_str(r#"key_algorithm = "Ed25519""#).unwrap();
+        assert_eq!(cfg.key_algorithm, Some(KeyAlgorithm::Ed25519));
+
+        assert!(toml::from_str::<Config>(r#"key_algorithm = "RSA""#).is_err());
+    }
+
+    #[test]
+    fn listen_limits() {
+        let cfg: Config = toml::from_str(
+            r#"
+            [[files]]
+            name = "ingress"
+            kind = "listen"
+            prot = "tls"
+            max_connections_per_second = 100
+            max_concurrent_connections = 1000
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            cfg.files[..],
+            [File::Listen(ListenFile::Tls {
+                max_connections_per_second: Some(100),
+                max_concurrent_connections: Some(1000),
+                ..
+            })]
+        ));
+    }
+
+    #[test]
+    fn listen_ips() {
+        let cfg: Config = toml::from_str(
+            r#"
+            [[files]]
+            name = "ingress"
+            kind = "listen"
+            prot = "tls"
+            allow_ips = ["10.0.0.0/8", "2001:db8::/32"]
+            deny_ips = ["10.0.0.1/32"]
+            "#,
+        )
+        .unwrap();
+        match &cfg.files[..] {
+            [File::Listen(ListenFile::Tls {
+                allow_ips: Some(allow_ips),
+                deny_ips: Some(deny_ips),
+                ..
+            })] => {
+                assert_eq!(
+                    allow_ips,
+                    &[
+                        "10.0.0.0/8".parse().unwrap(),
+                        "2001:db8::/32".parse().unwrap()
+                    ]
+                );
+                assert_eq!(deny_ips, &["10.0.0.1/32".parse().unwrap()]);
+            }
+            files => panic!("unexpected files: {files:?}"),
+        }
+
+        assert!(toml::from_str::<Config>(
+            r#"
+            [[files]]
+            name = "ingress"
+            kind = "listen"
+            prot = "tls"
+            allow_ips = ["10.0.0.0"]
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn caps() {
+        let cfg: Config = toml::from_str(
+            r#"
+            [[files]]
+            kind = "stdin"
+            caps = ["read", "poll", "fdstat_set_flags"]
+
+            [[files]]
+            kind = "stdout"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.files[0].caps(),
+            Some(&[FileCap::Read, FileCap::Poll, FileCap::FdstatSetFlags][..])
+        );
+        assert_eq!(cfg.files[1].caps(), None);
+
+        assert!(toml::from_str::<Config>(
+            r#"
+            [[files]]
+            kind = "stdin"
+            caps = ["execute"]
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn sni_certs() {
+        let cfg: Config = toml::from_str(
+            r#"
+            [[files]]
+            name = "ingress"
+            kind = "listen"
+            prot = "tls"
+
+            [files.sni_certs."a.example.com"]
+            cert = "CERT"
+            key = "KEY"
+            "#,
+        )
+        .unwrap();
+        match &cfg.files[..] {
+            [File::Listen(ListenFile::Tls { sni_certs, .. })] => assert_eq!(
+                sni_certs,
+                &HashMap::from([(
+                    "a.example.com".into(),
+                    CertAndKey {
+                        cert: "CERT".into(),
+                        key: "KEY".into(),
+                    }
+                )])
+            ),
+            files => panic!("unexpected files: {files:?}"),
+        }
+    }
+
+    #[test]
+    fn self_signed_cert_validity() {
+        let cfg: Config = toml::from_str("").unwrap();
+        assert_eq!(cfg.self_signed_cert_validity_secs, None);
+
+        let cfg: Config = toml::from_str("self_signed_cert_validity_secs = 604800").unwrap();
+        assert_eq!(cfg.self_signed_cert_validity_secs, Some(604800));
+    }
+
+    #[test]
+    fn san() {
+        let cfg: Config = toml::from_str(
+            r#"
+            subject = "CN=example.com"
+            san = ["example.com", "*.example.com", "192.0.2.1", "::1"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.subject.as_deref(), Some("CN=example.com"));
+        assert_eq!(
+            cfg.san,
+            vec![
+                SubjectAltName::Dns("example.com".into()),
+                SubjectAltName::Dns("*.example.com".into()),
+                SubjectAltName::Ip([192, 0, 2, 1].into()),
+                SubjectAltName::Ip(std::net::Ipv6Addr::LOCALHOST.into()),
+            ]
+        );
+
+        let cfg_str = toml::to_string(&cfg).unwrap();
+        assert_eq!(toml::from_str::<Config>(&cfg_str).unwrap(), cfg);
+
+        for invalid in [
+            "",
+            "-example.com",
+            "example..com",
+            "exa mple.com",
+            "*",
+            "a.*.com",
+        ] {
+            let cfg = format!(r#"san = ["example.com", "{invalid}"]"#);
+            assert!(toml::from_str::<Config>(&cfg).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn steward() {
+        let cfg: Config = toml::from_str(r#"steward = "https://attest.profian.com""#).unwrap();
+        assert_eq!(
+            cfg.steward,
+            vec!["https://attest.profian.com".parse().unwrap()]
+        );
+
+        assert!(!cfg.steward_shuffle);
+        assert!(cfg.steward_pin.is_empty());
+
+        let cfg: Config = toml::from_str(
+            r#"
+            steward = ["https://attest.profian.com", "https://attest2.profian.com"]
+            steward_shuffle = true
+            steward_pin = "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+            "#,
+        )
+        .unwrap();
+        assert!(cfg.steward_shuffle);
+        assert_eq!(
+            cfg.steward_pin,
+            vec!["47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string()]
+        );
+        assert_eq!(
+            cfg.steward,
+            vec![
+                "https://attest.profian.com".parse().unwrap(),
+                "https://attest2.profian.com".parse().unwrap()
+            ]
+        );
+
+        let cfg_str = toml::to_string(&cfg).unwrap();
+        assert_eq!(toml::from_str::<Config>(&cfg_str).unwrap(), cfg);
+    }
+
+    #[test]
+    fn file_values() {
+        const CONFIG: &str = r#"
+        args = ["--key", { file = "/run/secrets/key", trim = true }]
+
+        [env]
+        VAR = "var"
+        BLOB = { file = "/run/secrets/blob" }
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.args,
+            vec![
+                "--key".into(),
+                Value::File {
+                    file: "/run/secrets/key".into(),
+                    trim: true
+                }
+            ]
+        );
+        assert_eq!(cfg.env["VAR"], "var".into());
+        assert_eq!(
+            cfg.env["BLOB"],
+            Value::File {
+                file: "/run/secrets/blob".into(),
+                trim: false
+            }
+        );
+
+        let relative = Value::File {
+            file: "secrets/key".into(),
+            trim: false,
+        };
+        assert_eq!(
+            relative.resolve().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        let traversal = Value::File {
+            file: "/run/../etc/shadow".into(),
+            trim: false,
+        };
+        assert_eq!(
+            traversal.resolve().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
     #[test]
     fn invalid_name() {
         const CONFIG: &str = r#"
@@ -381,6 +1771,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn invalid_cipher_suite() {
+        const CONFIG: &str = r#"
+        [[files]]
+        kind = "connect"
+        prot = "tls"
+        host = "example.com"
+        cipher_suites = ["TLS_RSA_WITH_RC4_128_SHA"]
+        "#;
+
+        let err = toml::from_str::<Config>(CONFIG).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("unknown variant `TLS_RSA_WITH_RC4_128_SHA`, expected one of"));
+    }
+
     #[test]
     fn check_template() {
         let cfg_str = CONFIG_TEMPLATE
@@ -394,4 +1800,291 @@ mod test {
         let cfg2: Config = toml::from_str(&cfg_str).unwrap();
         assert_eq!(cfg, cfg2);
     }
+
+    #[test]
+    fn toml_string() {
+        let cfg = Config::from_toml_str(CONFIG).unwrap();
+        let cfg_str = cfg.to_toml_string().unwrap();
+        assert_eq!(Config::from_toml_str(&cfg_str).unwrap(), cfg);
+
+        assert!(Config::from_toml_str(r#"key_algorithm = "RSA""#).is_err());
+
+        let cfg = Config::from_toml_str(r#"args = ["--key", { file = "/run/key" }]"#).unwrap();
+        assert!(cfg.to_toml_string().is_err());
+    }
+
+    #[test]
+    fn read_timeout() {
+        let cfg = Config::from_toml_str(
+            r#"
+            [[files]]
+            kind = "connect"
+            prot = "tls"
+            host = "example.com"
+            read_timeout_ms = 5000
+            keepalive = { idle_secs = 60 }
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            cfg.files[..],
+            [File::Connect(ConnectFile::Tls {
+                read_timeout_ms: Some(5000),
+                keepalive: Some(TcpKeepalive {
+                    idle_secs: Some(60),
+                    ..
+                }),
+                ..
+            })]
+        ));
+
+        // The value is serialized ahead of the table.
+        let cfg_str = cfg.to_toml_string().unwrap();
+        assert_eq!(Config::from_toml_str(&cfg_str).unwrap(), cfg);
+    }
+
+    #[test]
+    fn interpolation() {
+        let var = |name: &str| (name == "REGION").then(|| "eu".to_string());
+
+        assert_eq!(interpolate("${REGION}-1", var).unwrap(), "eu-1");
+        assert_eq!(interpolate("${REGION:-us}", var).unwrap(), "eu");
+        assert_eq!(interpolate("${ZONE:-a}", var).unwrap(), "a");
+        assert_eq!(interpolate("${ZONE:-}", var).unwrap(), "");
+        assert_eq!(
+            interpolate("$${REGION} $REGION $", var).unwrap(),
+            "${REGION} $REGION $"
+        );
+        assert_eq!(
+            interpolate("${ZONE}", var).unwrap_err().to_string(),
+            "variable `ZONE` is not set"
+        );
+        assert!(interpolate("${REGION", var).is_err());
+        assert!(interpolate("${RE GION}", var).is_err());
+
+        const CONFIG: &str = r#"
+            env_passthrough = ["REGION"]
+            steward = "https://${REGION}.attest.example.com/${REGION}"
+            args = ["--region=${REGION}", "$${REGION}", { file = "/run/${REGION}" }]
+
+            [env]
+            REGION = "${REGION}"
+            ZONE = "${ZONE:-a}"
+        "#;
+        let var = |name: &str| (name == "REGION").then(|| "EU".to_string());
+        let cfg = Config::from_toml_str_interpolated(CONFIG, var).unwrap();
+        assert_eq!(
+            cfg.steward,
+            vec!["https://eu.attest.example.com/EU".parse().unwrap()]
+        );
+        assert_eq!(
+            cfg.args,
+            vec![
+                "--region=EU".into(),
+                "${REGION}".into(),
+                Value::File {
+                    file: "/run/${REGION}".into(),
+                    trim: false
+                }
+            ]
+        );
+        assert_eq!(cfg.env["REGION"], "EU".into());
+        assert_eq!(cfg.env["ZONE"], "a".into());
+
+        // Listing a variable in the config does not allow referencing it.
+        let err = Config::from_toml_str_interpolated(
+            "env_passthrough = [\"HOME\"]\nargs = [\"${HOME}\"]",
+            var,
+        )
+        .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "failed to interpolate `args`: variable `HOME` is not set"
+        );
+    }
+
+    #[test]
+    fn json_schema() {
+        let schema = Config::json_schema();
+        let properties = schema["properties"].as_object().unwrap();
+        let fields = toml::Value::try_from(Config::default()).unwrap();
+        for field in fields.as_table().unwrap().keys() {
+            assert!(properties.contains_key(field), "{field}");
+        }
+        assert_eq!(properties["fuel"]["nullable"], true);
+        assert_eq!(properties["steward_retries"].get("nullable"), None);
+
+        let variants = schema["definitions"]["OptLevel"]["oneOf"]
+            .as_array()
+            .unwrap();
+        assert_eq!(variants.len(), 3);
+        assert!(variants.iter().all(|v| v["description"].is_string()));
+    }
+
+    mod roundtrip {
+        use super::*;
+
+        use proptest::collection::{hash_map, vec};
+        use proptest::option::of;
+        use proptest::prelude::*;
+
+        /// Integers, which are representable in TOML
+        const MAX_INT: u64 = i64::MAX as u64;
+
+        fn file_name() -> impl Strategy<Value = FileName> {
+            "[a-z][a-z0-9_-]{0,15}".prop_map(FileName)
+        }
+
+        fn inline_value() -> impl Strategy<Value = Value> {
+            any::<String>().prop_map(Value::Inline)
+        }
+
+        fn file_value() -> impl Strategy<Value = Value> {
+            ("(/[a-z0-9]{1,8}){1,4}", any::<bool>()).prop_map(|(file, trim)| Value::File {
+                file: file.into(),
+                trim,
+            })
+        }
+
+        fn value() -> impl Strategy<Value = Value> {
+            prop_oneof![inline_value(), file_value()]
+        }
+
+        fn san() -> impl Strategy<Value = SubjectAltName> {
+            prop_oneof![
+                "(\\*\\.)?[a-z0-9]{1,8}\\.example".prop_map(SubjectAltName::Dns),
+                any::<IpAddr>().prop_map(SubjectAltName::Ip),
+            ]
+        }
+
+        fn file() -> impl Strategy<Value = File> {
+            prop_oneof![
+                of(file_name()).prop_map(|name| File::Null(NullFile { name, caps: None })),
+                of(file_name()).prop_map(|name| File::Stdin(StdioFile { name, caps: None })),
+                of(file_name()).prop_map(|name| File::Stdout(StdioFile { name, caps: None })),
+                of(file_name()).prop_map(|name| File::Capture(CaptureFile { name, caps: None })),
+                (file_name(), any::<u16>(), of(1..1024u32), any::<bool>()).prop_map(
+                    |(name, port, backlog, nodelay)| File::Listen(ListenFile::Tcp {
+                        name,
+                        caps: None,
+                        addr: default_addr(),
+                        port,
+                        backlog,
+                        reuse_addr: None,
+                        reuse_port: false,
+                        nodelay,
+                        keepalive: None,
+                    })
+                ),
+                (
+                    of(file_name()),
+                    "[a-z]{1,8}\\.example",
+                    any::<u16>(),
+                    of(0..MAX_INT),
+                )
+                    .prop_map(|(name, host, port, connect_timeout_ms)| {
+                        File::Connect(ConnectFile::Tcp {
+                            name,
+                            caps: None,
+                            host,
+                            port,
+                            connect_timeout_ms,
+                            nodelay: false,
+                            keepalive: Some(TcpKeepalive {
+                                idle_secs: Some(60),
+                                ..Default::default()
+                            }),
+                        })
+                    }),
+                (file_name(), "[A-Za-z0-9+/]{0,16}").prop_map(|(name, data)| {
+                    File::Inline(InlineFile {
+                        name,
+                        caps: None,
+                        data: Some(data),
+                        host_path: None,
+                    })
+                }),
+                (file_name(), "[A-Za-z0-9+/]{4,16}").prop_map(|(name, ciphertext)| {
+                    File::Sealed(SealedFile {
+                        name,
+                        caps: None,
+                        ciphertext,
+                    })
+                }),
+            ]
+        }
+
+        prop_compose! {
+            fn config()(
+                steward in vec("https://[a-z]{1,8}\\.example/[a-z]{0,8}", 0..3),
+                steward_pin in vec("[A-Za-z0-9+/]{43}=", 0..3),
+                steward_shuffle in any::<bool>(),
+                steward_retries in any::<u32>(),
+                steward_backoff_ms in of(0..MAX_INT),
+                steward_max_backoff_ms in of(0..MAX_INT),
+                steward_deadline_secs in of(0..MAX_INT),
+                key_algorithm in of(prop_oneof![
+                    Just(KeyAlgorithm::P256),
+                    Just(KeyAlgorithm::P384),
+                    Just(KeyAlgorithm::Ed25519),
+                ]),
+                subject in of("CN=[a-z]{1,8}"),
+                san in vec(san(), 0..3),
+                self_signed_cert_validity_secs in of(1..MAX_INT),
+                attestation_cache_size in 0..1024usize,
+                // Arrays mixing inline values and files cannot be serialized.
+                args in prop_oneof![vec(inline_value(), 0..4), vec(file_value(), 0..4)],
+                entrypoint in of("[a-z_]{1,8}"),
+                fuel in of(0..MAX_INT),
+                memory_limit_bytes in of(0..MAX_INT),
+                deterministic_time_secs in of(0..MAX_INT),
+                cranelift_opt_level in of(prop_oneof![
+                    Just(OptLevel::None),
+                    Just(OptLevel::Speed),
+                    Just(OptLevel::SpeedAndSize),
+                ]),
+                module_signing_key in of("[A-Za-z0-9+/]{43}="),
+                denied_imports in vec("[a-z_]{1,8}(::[a-z_]{1,8})?", 0..3),
+                env_passthrough in vec("[A-Z_]{1,8}", 0..3),
+                files in vec(file(), 0..6),
+                env in hash_map("[A-Z_][A-Z0-9_]{0,7}", value(), 0..4),
+            ) -> Config {
+                Config {
+                    steward: steward.iter().map(|url| url.parse().unwrap()).collect(),
+                    steward_pin,
+                    steward_shuffle,
+                    steward_retries,
+                    steward_backoff_ms,
+                    steward_max_backoff_ms,
+                    steward_deadline_secs,
+                    key_algorithm,
+                    subject,
+                    san,
+                    self_signed_cert_validity_secs,
+                    attestation_cache_size,
+                    args,
+                    entrypoint,
+                    fuel,
+                    memory_limit_bytes,
+                    deterministic_time_secs,
+                    cranelift_opt_level,
+                    module_signing_key,
+                    denied_imports,
+                    env_passthrough,
+                    files,
+                    env,
+                }
+            }
+        }
+
+        proptest! {
+            #[test]
+            fn toml_string_idempotent(cfg in config()) {
+                let cfg_str = cfg.to_toml_string().unwrap();
+                let parsed = Config::from_toml_str(&cfg_str).unwrap();
+                prop_assert_eq!(&parsed, &cfg);
+                prop_assert_eq!(parsed.to_toml_string().unwrap(), cfg_str);
+            }
+        }
+    }
 }
@@ -6,12 +6,23 @@
 #![deny(clippy::all)]
 #![warn(rust_2018_idioms)]
 
+#[cfg(feature = "remote-fetch")]
+mod oci;
 mod runtime;
 mod workload;
 
+#[cfg(feature = "remote-fetch")]
+pub use oci::RegistryAuth;
+#[cfg(feature = "profiling")]
+pub use runtime::ExecutionProfile;
+pub use runtime::{
+    CompiledModule, EgressPolicy, Exit, Interrupt, Output, Resolver, Runtime, RuntimeBuilder,
+};
 pub use workload::{Package, Workload, PACKAGE_CONFIG, PACKAGE_ENTRYPOINT};
 
-use runtime::Runtime;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
 
 /// The Arguments
 // NOTE: `repr(C)` is required, otherwise `toml` serialization fails with `values must be emitted before tables`
@@ -19,20 +30,146 @@ use runtime::Runtime;
 #[cfg_attr(unix, derive(serde::Deserialize, serde::Serialize))]
 #[repr(C)]
 pub struct Args {
+    /// Host environment variables passed through to the Wasm module
+    ///
+    /// Only these variables may be referenced in the config of the [Package],
+    /// see [RuntimeBuilder::env_passthrough].
+    #[cfg_attr(unix, serde(default))]
+    pub env_passthrough: Vec<String>,
+
+    /// Patterns of the destinations the connect files of the [Package] may reach
+    ///
+    /// If set, other destinations are denied, see [EgressPolicy]. Otherwise, any destination
+    /// may be reached.
+    #[cfg_attr(unix, serde(default))]
+    pub egress: Option<Vec<String>>,
+
     /// Package
     pub package: Package,
+
+    /// Execution options
+    #[cfg_attr(unix, serde(default))]
+    pub options: ExecutionOptions,
+}
+
+/// Options controlling the execution of a [Package]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(unix, derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(unix, serde(deny_unknown_fields))]
+pub struct ExecutionOptions {
+    /// Maximum wall-clock time the Wasm module may execute for
+    #[cfg_attr(unix, serde(default))]
+    pub timeout: Option<Duration>,
+
+    /// Path to a module precompiled for the Wasm module of the [Package]
+    ///
+    /// The precompiled module is used instead of compiling the Wasm module, if it was compiled
+    /// from the same Wasm module by a compatible engine. Otherwise, the Wasm module is compiled
+    /// and the result is stored at the path for subsequent executions.
+    ///
+    /// The precompiled module is executed as native code, so it must be stored in trusted storage.
+    /// It is never read from the [Args] passed by the host, as the host is not trusted.
+    #[cfg_attr(unix, serde(skip))]
+    pub compiled: Option<PathBuf>,
+
+    /// Expected SHA-256 digest of the Wasm module of the [Package]
+    ///
+    /// The execution fails before compiling the Wasm module, if its digest does not match.
+    #[cfg_attr(unix, serde(default))]
+    pub expected_sha256: Option<[u8; 32]>,
+
+    /// Ed25519 signature of the Wasm module of the [Package]
+    ///
+    /// The signature is verified against the `module_signing_key` of the config before compiling
+    /// the Wasm module. It is required, if the config specifies a key.
+    #[cfg_attr(unix, serde(default))]
+    pub signature: Option<Vec<u8>>,
+
+    /// Seed of the random number generator of a deterministic execution
+    ///
+    /// If set, the Wasm module is executed deterministically, see [Runtime::execute_deterministic].
+    /// It is never read from the [Args] passed by the host, as it fixes the randomness and the
+    /// clocks of the execution.
+    #[cfg_attr(unix, serde(skip))]
+    pub deterministic_seed: Option<[u8; 32]>,
 }
 
+/// Error returned, when the execution exceeds [ExecutionOptions::timeout]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExecutionTimeout(pub Duration);
+
+impl fmt::Display for ExecutionTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "execution timed out after {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ExecutionTimeout {}
+
+/// Error returned, when the execution consumes the fuel budget of the [Package]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FuelExhausted(pub u64);
+
+impl fmt::Display for FuelExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "execution exhausted the fuel budget of {}", self.0)
+    }
+}
+
+impl std::error::Error for FuelExhausted {}
+
+/// Error returned, when the execution is cancelled by [Interrupt::cancel]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "execution was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
 /// Execute
-pub fn execute_with_args(args: Args) -> anyhow::Result<()> {
-    Runtime::execute(args.package).map(|_| ())
+///
+/// Returns the exit code of the Wasm module, which is `0`, unless it called `proc_exit`.
+pub fn execute_with_args(args: Args) -> anyhow::Result<i32> {
+    execute_with_output(args).map(|output| output.exit.code())
+}
+
+/// Execute and return the [Output] of the Wasm module
+///
+/// This includes the data written to files of kind `capture`.
+pub fn execute_with_output(args: Args) -> anyhow::Result<Output> {
+    runtime(&args)?.execute(args.package, args.options)
+}
+
+/// Execute asynchronously
+///
+/// Returns the exit code of the Wasm module, which is `0`, unless it called `proc_exit`.
+/// Dropping the returned future aborts the execution, see [Runtime::execute_async].
+pub async fn execute_with_args_async(args: Args) -> anyhow::Result<i32> {
+    runtime(&args)?
+        .execute_async(args.package, args.options)
+        .await
+        .map(|output| output.exit.code())
+}
+
+/// Build the runtime applying the policies of the launcher passed in `args`
+fn runtime(args: &Args) -> anyhow::Result<Runtime> {
+    let mut builder = Runtime::builder().env_passthrough(args.env_passthrough.iter().cloned());
+    if let Some(egress) = &args.egress {
+        builder = builder.egress(EgressPolicy::new(egress)?);
+    }
+    Ok(builder.build())
 }
 
 /// Execute
 ///
 /// with configuration read from file descriptor 3.
+/// Returns the exit code of the Wasm module, which is `0`, unless it called `proc_exit`.
 #[cfg(unix)]
-pub fn execute() -> anyhow::Result<()> {
+pub fn execute() -> anyhow::Result<i32> {
     use anyhow::Context;
     use std::io::Read;
     use std::mem::forget;
@@ -53,21 +190,20 @@ pub fn execute() -> anyhow::Result<()> {
 
     let args = toml::from_str::<Args>(&args).context("failed to decode arguments")?;
 
-    execute_with_args(args)?;
-
-    Ok(())
+    execute_with_args(args)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use std::collections::BTreeMap;
     use std::io::{Seek, Write};
     #[cfg(unix)]
     use std::os::unix::io::IntoRawFd;
 
     use anyhow::Context;
-    use tempfile::tempfile;
+    use tempfile::{tempdir, tempfile};
     use wasmtime::Val;
 
     const NO_EXPORT_WAT: &str = r#"(module
@@ -78,6 +214,95 @@ mod test {
       (func (export "") (result i32) i32.const 1)
     )"#;
 
+    const REACTOR_WAT: &str = r#"(module
+      (global $base (mut i32) (i32.const 0))
+      (func (export "_initialize") (global.set $base (i32.const 41)))
+      (func (export "answer") (result i32) (i32.add (global.get $base) (i32.const 1)))
+    )"#;
+
+    const DETERMINISTIC_WAT: &str = r#"(module
+      (import "wasi_snapshot_preview1" "random_get"
+        (func $random_get (param i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "clock_time_get"
+        (func $clock_time_get (param i32 i64 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "") (result i64 i64 i64)
+        (drop (call $random_get (i32.const 0) (i32.const 8)))
+        (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 8)))
+        (drop (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 16)))
+        (i64.load (i32.const 0))
+        (i64.load (i32.const 8))
+        (i64.load (i32.const 16))
+      )
+    )"#;
+
+    const ATTESTATION_REPORT_INFO_WAT: &str = r#"(module
+      (import "host" "attestation_report_info"
+        (func $attestation_report_info (param i32) (result i32)))
+      (func (export "") (result i32 i32 i32)
+        (call $attestation_report_info (i32.const 64))
+        (call $attestation_report_info (i32.const 65))
+        (call $attestation_report_info (i32.const 1025))
+      )
+    )"#;
+
+    const ATTESTATION_REPORT_WAT: &str = r#"(module
+      (import "host" "attestation_report_v2"
+        (func $attestation_report (param i32 i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "") (result i32 i32 i32)
+        (call $attestation_report (i32.const 0) (i32.const 1025) (i32.const 0) (i32.const 0) (i32.const 0))
+        (call $attestation_report (i32.const 0) (i32.const 64) (i32.const 0) (i32.const 64) (i32.const 65536))
+        (call $attestation_report (i32.const 0) (i32.const 64) (i32.const 64) (i32.const 64) (i32.const 128))
+      )
+    )"#;
+
+    const PLATFORM_INFO_WAT: &str = r#"(module
+      (import "host" "platform_info"
+        (func $platform_info (param i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "") (result i32 i32 i32)
+        (call $platform_info (i32.const 0) (i32.const 28))
+        (i32.load8_u (i32.const 0))
+        (i32.or
+          (i32.or (i32.load (i32.const 12)) (i32.load (i32.const 16)))
+          (i32.or (i32.load (i32.const 20)) (i32.load (i32.const 24))))
+      )
+    )"#;
+
+    const PEER_CERTIFICATES_WAT: &str = r#"(module
+      (import "host" "peer_certificates"
+        (func $peer_certificates (param i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "") (result i32)
+        (call $peer_certificates (i32.const 0) (i32.const 0) (i32.const 16) (i32.const 16))
+      )
+    )"#;
+
+    const MEMORY_GROW_WAT: &str = r#"(module
+      (memory (export "memory") 1)
+      (func (export "") (result i32)
+        (if (i32.eq (memory.grow (i32.const 2)) (i32.const -1))
+          (then unreachable))
+        (memory.grow (i32.const 1))
+      )
+    )"#;
+
+    const LOOP_WAT: &str = r#"(module
+      (func (export "")
+        (loop br 0)
+      )
+    )"#;
+
+    const EXIT_42_WAT: &str = r#"(module
+      (import "wasi_snapshot_preview1" "proc_exit"
+        (func $__wasi_proc_exit (param i32)))
+      (memory (export "memory") 1)
+      (func (export "_start")
+        (call $__wasi_proc_exit (i32.const 42))
+      )
+    )"#;
+
     const HELLO_WASI_WAT: &str = r#"(module
       (import "wasi_snapshot_preview1" "proc_exit"
         (func $__wasi_proc_exit (param i32)))
@@ -104,16 +329,68 @@ mod test {
       (data (i32.const 0) "Hello, world!\0a")
     )"#;
 
-    pub fn run(wasm: &[u8]) -> anyhow::Result<Vec<Val>> {
-        let mut file = tempfile().context("failed to create module file")?;
-        file.write(wasm).context("failed to write module to file")?;
+    const SOCK_SEND_RECV_WAT: &str = r#"(module
+      (import "wasi_snapshot_preview1" "sock_send"
+        (func $__wasi_sock_send (param i32 i32 i32 i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "sock_recv"
+        (func $__wasi_sock_recv (param i32 i32 i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "ping")
+      (func (export "") (result i32 i32 i32 i32 i32)
+        (i32.store (i32.const 16) (i32.const 0))
+        (i32.store (i32.const 20) (i32.const 4))
+        (i32.store (i32.const 24) (i32.const 64))
+        (i32.store (i32.const 28) (i32.const 4))
+        (call $__wasi_sock_send
+          (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 0) (i32.const 32))
+        (i32.load (i32.const 32))
+        (call $__wasi_sock_recv
+          (i32.const 0) (i32.const 24) (i32.const 1) (i32.const 0) (i32.const 36) (i32.const 40))
+        (i32.load (i32.const 36))
+        (i32.load (i32.const 64))
+      )
+    )"#;
+
+    const READ_FD_0_WAT: &str = r#"(module
+      (import "wasi_snapshot_preview1" "fd_read"
+        (func $__wasi_fd_read (param i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "") (result i32)
+        (i32.store (i32.const 16) (i32.const 0))
+        (i32.store (i32.const 20) (i32.const 8))
+        (if (call $__wasi_fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 24))
+          (then unreachable))
+        (if (i32.ne (i32.load8_u (i32.const 2)) (i32.const 3))
+          (then unreachable))
+        (i32.load (i32.const 24))
+      )
+    )"#;
+
+    fn temp_file(data: &[u8]) -> anyhow::Result<std::fs::File> {
+        let mut file = tempfile().context("failed to create file")?;
+        file.write_all(data).context("failed to write file")?;
         file.rewind().context("failed to rewind file")?;
+        Ok(file)
+    }
+
+    pub fn package_with_config(wasm: &[u8], conf: Option<&str>) -> anyhow::Result<Package> {
+        let wasm = temp_file(wasm).context("failed to create module file")?;
+        let conf = conf
+            .map(|conf| temp_file(conf.as_bytes()).context("failed to create config file"))
+            .transpose()?;
         #[cfg(unix)]
-        let file = file.into_raw_fd();
-        Runtime::execute(Package::Local {
-            wasm: file,
-            conf: None,
-        })
+        let (wasm, conf) = (wasm.into_raw_fd(), conf.map(IntoRawFd::into_raw_fd));
+        Ok(Package::Local { wasm, conf })
+    }
+
+    pub fn package(wasm: &[u8]) -> anyhow::Result<Package> {
+        package_with_config(wasm, None)
+    }
+
+    pub fn run(wasm: &[u8]) -> anyhow::Result<Vec<Val>> {
+        Runtime::default()
+            .execute(package(wasm)?, Default::default())
+            .map(|output| output.exit.values().to_vec())
     }
 
     #[test]
@@ -129,6 +406,462 @@ mod test {
         assert_eq!(results, vec![1]);
     }
 
+    #[test]
+    fn workload_run_async_return_1() {
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+
+        let results: Vec<i32> = futures::executor::block_on(
+            Runtime::default().execute_async(package(&bytes).unwrap(), Default::default()),
+        )
+        .unwrap()
+        .exit
+        .values()
+        .iter()
+        .map(wasmtime::Val::unwrap_i32)
+        .collect();
+
+        assert_eq!(results, vec![1]);
+    }
+
+    #[test]
+    fn workload_run_async_dropped() {
+        use futures::FutureExt;
+
+        let bytes = wat::parse_str(LOOP_WAT).expect("error parsing wat");
+
+        // The looping Wasm module yields, so that the execution is pending and can be dropped.
+        let execution =
+            Runtime::default().execute_async(package(&bytes).unwrap(), Default::default());
+        assert!(execution.now_or_never().is_none());
+    }
+
+    #[test]
+    fn workload_run_async_timeout() {
+        let bytes = wat::parse_str(LOOP_WAT).expect("error parsing wat");
+        let timeout = Duration::from_millis(100);
+
+        let err = futures::executor::block_on(Runtime::default().execute_async(
+            package(&bytes).unwrap(),
+            ExecutionOptions {
+                timeout: Some(timeout),
+                ..Default::default()
+            },
+        ))
+        .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<ExecutionTimeout>(),
+            Some(&ExecutionTimeout(timeout))
+        );
+    }
+
+    #[test]
+    fn workload_run_timeout() {
+        let bytes = wat::parse_str(LOOP_WAT).expect("error parsing wat");
+        let timeout = Duration::from_millis(100);
+
+        let err = Runtime::default()
+            .execute(
+                package(&bytes).unwrap(),
+                ExecutionOptions {
+                    timeout: Some(timeout),
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<ExecutionTimeout>(),
+            Some(&ExecutionTimeout(timeout))
+        );
+    }
+
+    #[test]
+    fn workload_run_cancelled() {
+        let bytes = wat::parse_str(LOOP_WAT).expect("error parsing wat");
+
+        let (interrupt, execution) = Runtime::default()
+            .execute_interruptible(package(&bytes).unwrap(), Default::default())
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        interrupt.cancel();
+
+        let err = execution.join().unwrap().unwrap_err();
+        assert_eq!(err.downcast_ref::<Cancelled>(), Some(&Cancelled));
+    }
+
+    #[test]
+    fn workload_run_fuel() {
+        let bytes = wat::parse_str(LOOP_WAT).expect("error parsing wat");
+        let package = package_with_config(&bytes, Some("fuel = 1000")).unwrap();
+
+        let err = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<FuelExhausted>(),
+            Some(&FuelExhausted(1000))
+        );
+    }
+
+    #[test]
+    fn workload_run_runtime_fuel() {
+        let bytes = wat::parse_str(LOOP_WAT).expect("error parsing wat");
+        let runtime = Runtime::builder().fuel(1000).build();
+
+        // The fuel budget of the runtime applies to workloads without and with larger budgets.
+        for config in [None, Some("fuel = 1000000")] {
+            let package = package_with_config(&bytes, config).unwrap();
+            let err = runtime.execute(package, Default::default()).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<FuelExhausted>(),
+                Some(&FuelExhausted(1000))
+            );
+        }
+
+        let package = package_with_config(&bytes, Some("fuel = 10")).unwrap();
+        let err = runtime.execute(package, Default::default()).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<FuelExhausted>(),
+            Some(&FuelExhausted(10))
+        );
+    }
+
+    #[test]
+    fn workload_run_memory_limit() {
+        let bytes = wat::parse_str(MEMORY_GROW_WAT).expect("error parsing wat");
+
+        let package = package_with_config(&bytes, Some("max_memory = 262144")).unwrap();
+        let results: Vec<i32> = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap()
+            .exit
+            .values()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![3]);
+
+        let package = package_with_config(&bytes, Some("max_memory = 131072")).unwrap();
+        let err = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<wasmtime::Trap>()
+                .and_then(wasmtime::Trap::trap_code),
+            Some(wasmtime::TrapCode::UnreachableCodeReached)
+        );
+    }
+
+    #[test]
+    fn workload_run_compiled() {
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let dir = tempdir().expect("failed to create temporary directory");
+        let options = ExecutionOptions {
+            compiled: Some(dir.path().join("main.cwasm")),
+            ..Default::default()
+        };
+
+        for _ in 0..2 {
+            let results: Vec<i32> = Runtime::default()
+                .execute(package(&bytes).unwrap(), options.clone())
+                .unwrap()
+                .exit
+                .values()
+                .iter()
+                .map(wasmtime::Val::unwrap_i32)
+                .collect();
+            assert_eq!(results, vec![1]);
+            assert!(dir.path().join("main.cwasm").exists());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn args_untrusted_options() {
+        const PACKAGE: &str = "[package]\nt = \"Module\"\nc = []\n\n[options]\n";
+
+        toml::from_str::<Args>(PACKAGE).unwrap();
+        for option in ["compiled = \"/tmp/main.cwasm\"", "deterministic_seed = []"] {
+            let args = format!("{PACKAGE}{option}");
+            assert!(toml::from_str::<Args>(&args).is_err(), "{option}");
+        }
+    }
+
+    #[test]
+    fn workload_compile() {
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let runtime = Runtime::default();
+        let compiled = runtime.compile(&bytes).unwrap();
+
+        for _ in 0..2 {
+            let results: Vec<i32> = runtime
+                .execute_compiled(&compiled, package(&bytes).unwrap(), Default::default())
+                .unwrap()
+                .exit
+                .values()
+                .iter()
+                .map(wasmtime::Val::unwrap_i32)
+                .collect();
+            assert_eq!(results, vec![1]);
+        }
+
+        let other = wat::parse_str(EXIT_42_WAT).expect("error parsing wat");
+        assert!(runtime
+            .execute_compiled(&compiled, package(&other).unwrap(), Default::default())
+            .is_err());
+
+        let conf = package_with_config(&bytes, Some("cranelift_opt_level = \"none\"")).unwrap();
+        let err = runtime
+            .execute_compiled(&compiled, conf, Default::default())
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("`cranelift_opt_level`"),
+            "{err:#}"
+        );
+
+        let options = ExecutionOptions {
+            deterministic_seed: Some([0; 32]),
+            ..Default::default()
+        };
+        assert!(runtime
+            .execute_compiled(&compiled, package(&bytes).unwrap(), options)
+            .is_err());
+
+        assert!(runtime.compile(b"not wasm").is_err());
+        let bytes = wat::parse_str(r#"(module (import "env" "f" (func)))"#).unwrap();
+        let err = runtime.compile(&bytes).unwrap_err();
+        assert!(format!("{err:#}").contains("`env::f`"), "{err:#}");
+    }
+
+    #[test]
+    fn workload_compile_concurrent_timeouts() {
+        let bytes = wat::parse_str(LOOP_WAT).expect("error parsing wat");
+        let runtime = Runtime::default();
+        let compiled = runtime.compile(&bytes).unwrap();
+
+        // The timeout of the first execution must not interrupt the second one,
+        // although both share the engine of the compiled module.
+        let executions: Vec<_> = [Duration::from_millis(100), Duration::from_secs(1)]
+            .into_iter()
+            .map(|timeout| {
+                let runtime = runtime.clone();
+                let compiled = compiled.clone();
+                let bytes = bytes.clone();
+                std::thread::spawn(move || {
+                    let options = ExecutionOptions {
+                        timeout: Some(timeout),
+                        ..Default::default()
+                    };
+                    let start = std::time::Instant::now();
+                    let err = runtime
+                        .execute_compiled(&compiled, package(&bytes).unwrap(), options)
+                        .unwrap_err();
+                    (timeout, start.elapsed(), err)
+                })
+            })
+            .collect();
+        for execution in executions {
+            let (timeout, elapsed, err) = execution.join().unwrap();
+            assert_eq!(
+                err.downcast_ref::<ExecutionTimeout>(),
+                Some(&ExecutionTimeout(timeout))
+            );
+            assert!(elapsed >= timeout, "{elapsed:?} < {timeout:?}");
+        }
+    }
+
+    #[test]
+    fn workload_run_sha256() {
+        use sha2::{Digest, Sha256};
+
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let mut options = ExecutionOptions {
+            expected_sha256: Some(Sha256::digest(&bytes).into()),
+            ..Default::default()
+        };
+        Runtime::default()
+            .execute(package(&bytes).unwrap(), options.clone())
+            .unwrap();
+
+        options.expected_sha256 = Some([0; 32]);
+        let err = Runtime::default()
+            .execute(package(&bytes).unwrap(), options)
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("does not match"),
+            "unexpected error: {err:#}"
+        );
+    }
+
+    #[test]
+    fn workload_run_signature() {
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let rand = ring::rand::SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rand).unwrap();
+        let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let conf = format!(
+            "module_signing_key = \"{}\"",
+            base64::encode(key.public_key())
+        );
+        let execute = |signature: Option<Vec<u8>>| {
+            let options = ExecutionOptions {
+                signature,
+                ..Default::default()
+            };
+            Runtime::default().execute(package_with_config(&bytes, Some(&conf)).unwrap(), options)
+        };
+
+        execute(Some(key.sign(&bytes).as_ref().to_vec())).unwrap();
+
+        let err = execute(Some(key.sign(b"other").as_ref().to_vec())).unwrap_err();
+        assert!(
+            err.to_string().contains("invalid signature"),
+            "unexpected error: {err:#}"
+        );
+
+        let err = execute(None).unwrap_err();
+        assert!(
+            err.to_string().contains("not signed"),
+            "unexpected error: {err:#}"
+        );
+    }
+
+    #[test]
+    fn workload_run_imports() {
+        let bytes = wat::parse_str(ATTESTATION_REPORT_INFO_WAT).expect("error parsing wat");
+        run(&bytes).unwrap();
+
+        for denied in ["host", "host::attestation_report_info"] {
+            let conf = format!("denied_imports = [\"{denied}\"]");
+            let err = Runtime::default()
+                .execute(
+                    package_with_config(&bytes, Some(&conf)).unwrap(),
+                    Default::default(),
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Wasm module imports `host::attestation_report_info`, which is denied by `denied_imports`"
+            );
+        }
+
+        let bytes = wat::parse_str(r#"(module (import "env" "unknown" (func)))"#)
+            .expect("error parsing wat");
+        let err = run(&bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Wasm module imports `env::unknown`, which is not provided by the runtime"
+        );
+    }
+
+    #[test]
+    fn workload_run_reactor() {
+        let bytes = wat::parse_str(REACTOR_WAT).expect("error parsing wat");
+        assert!(run(&bytes).unwrap().is_empty());
+
+        let package = package_with_config(&bytes, Some("entrypoint = \"answer\"")).unwrap();
+        let results: Vec<i32> = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap()
+            .exit
+            .values()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![42]);
+
+        let package = package_with_config(&bytes, Some("entrypoint = \"unknown\"")).unwrap();
+        let err = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Wasm module does not export function `unknown`"
+        );
+    }
+
+    #[test]
+    fn workload_run_deterministic() {
+        let bytes = wat::parse_str(DETERMINISTIC_WAT).expect("error parsing wat");
+        let run = |seed| -> Vec<i64> {
+            let package =
+                package_with_config(&bytes, Some("deterministic_time_secs = 42")).unwrap();
+            Runtime::default()
+                .execute_deterministic(package, seed)
+                .unwrap()
+                .exit
+                .values()
+                .iter()
+                .map(wasmtime::Val::unwrap_i64)
+                .collect()
+        };
+
+        let results = run([1; 32]);
+        // The system clock reports the configured time and the monotonic clock stands still.
+        assert_eq!(results[1..], [42_000_000_000, 0]);
+        assert_eq!(run([1; 32]), results);
+        assert_ne!(run([2; 32])[0], results[0]);
+    }
+
+    #[test]
+    fn workload_run_cache() {
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let dir = tempdir().expect("failed to create temporary directory");
+        let runtime = Runtime::with_cache(dir.path());
+
+        for _ in 0..2 {
+            let results: Vec<i32> = runtime
+                .execute(package(&bytes).unwrap(), Default::default())
+                .unwrap()
+                .exit
+                .values()
+                .iter()
+                .map(wasmtime::Val::unwrap_i32)
+                .collect();
+            assert_eq!(results, vec![1]);
+            assert_eq!(dir.path().read_dir().unwrap().count(), 1);
+        }
+    }
+
+    #[test]
+    fn workload_run_opt_level() {
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let dir = tempdir().expect("failed to create temporary directory");
+        let runtime = Runtime::with_cache(dir.path());
+
+        // Modules compiled with a different optimization level are cached separately.
+        for (i, config) in [None, Some("cranelift_opt_level = \"none\"")]
+            .into_iter()
+            .enumerate()
+        {
+            let package = package_with_config(&bytes, config).unwrap();
+            let results: Vec<i32> = runtime
+                .execute(package, Default::default())
+                .unwrap()
+                .exit
+                .values()
+                .iter()
+                .map(wasmtime::Val::unwrap_i32)
+                .collect();
+            assert_eq!(results, vec![1]);
+            assert_eq!(dir.path().read_dir().unwrap().count(), i + 1);
+        }
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test]
+    fn workload_run_profile() {
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let output = Runtime::default()
+            .execute(package(&bytes).unwrap(), Default::default())
+            .unwrap();
+        assert!(output.profile.compile_duration > Duration::ZERO);
+        assert!(output.profile.attestation_duration > Duration::ZERO);
+    }
+
     #[test]
     fn workload_run_no_export() {
         let bytes = wat::parse_str(NO_EXPORT_WAT).expect("error parsing wat");
@@ -139,13 +872,310 @@ mod test {
         }
     }
 
+    #[test]
+    fn workload_run_attestation_report_info() {
+        let bytes = wat::parse_str(ATTESTATION_REPORT_INFO_WAT).expect("error parsing wat");
+
+        let results: Vec<i32> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        assert_eq!(results, vec![0, 1, -1]);
+    }
+
+    #[test]
+    fn workload_run_attestation_report() {
+        let bytes = wat::parse_str(ATTESTATION_REPORT_WAT).expect("error parsing wat");
+
+        let results: Vec<i32> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        assert_eq!(results, vec![-1, -3, 0]);
+    }
+
+    #[test]
+    fn workload_run_platform_info() {
+        let bytes = wat::parse_str(PLATFORM_INFO_WAT).expect("error parsing wat");
+
+        let results: Vec<i32> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        assert_eq!(results[0], 0);
+        assert_ne!(results[1], 0, "the technology must be set");
+        assert_eq!(results[2], 0, "the TCB version is reserved");
+    }
+
+    #[test]
+    fn workload_run_peer_certificates() {
+        let bytes = wat::parse_str(PEER_CERTIFICATES_WAT).expect("error parsing wat");
+
+        let results: Vec<i32> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        // stdin is not a TLS stream
+        assert_eq!(results, vec![-5]);
+    }
+
+    #[test]
+    fn workload_run_exit_code() {
+        let bytes = wat::parse_str(EXIT_42_WAT).expect("error parsing wat");
+        let exit = Runtime::default()
+            .execute(package(&bytes).unwrap(), Default::default())
+            .unwrap()
+            .exit;
+        assert!(matches!(exit, Exit::Exited(42)));
+        assert_eq!(exit.code(), 42);
+    }
+
     #[test]
     fn workload_run_hello_wasi() {
         let bytes = wat::parse_str(HELLO_WASI_WAT).expect("error parsing wat");
         let values = run(&bytes).unwrap();
         assert_eq!(values.len(), 0);
+    }
+
+    #[test]
+    fn workload_run_sock_send_recv() {
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request, b"ping");
+            stream.write_all(b"pong").unwrap();
+        });
+
+        let config = format!(
+            "[[files]]\nkind = \"connect\"\nprot = \"tcp\"\nhost = \"127.0.0.1\"\nport = {port}"
+        );
+        let bytes = wat::parse_str(SOCK_SEND_RECV_WAT).expect("error parsing wat");
+        let results: Vec<i32> = Runtime::default()
+            .execute(
+                package_with_config(&bytes, Some(&config)).unwrap(),
+                Default::default(),
+            )
+            .unwrap()
+            .exit
+            .values()
+            .iter()
+            .map(Val::unwrap_i32)
+            .collect();
+        server.join().unwrap();
+
+        assert_eq!(results, vec![0, 4, 0, 4, i32::from_le_bytes(*b"pong")]);
+    }
+
+    #[test]
+    fn workload_run_capture() {
+        const CONFIG: &str = r#"
+        [[files]]
+        kind = "stdin"
+
+        [[files]]
+        kind = "capture"
+        "#;
+
+        let bytes = wat::parse_str(HELLO_WASI_WAT).expect("error parsing wat");
+        let package = package_with_config(&bytes, Some(CONFIG)).unwrap();
+        let output = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap();
+        assert_eq!(output.exit.code(), 0);
+        assert_eq!(
+            output.captured,
+            BTreeMap::from([(1, b"Hello, world!\n".to_vec())])
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn workload_run_memfd() {
+        const CONFIG: &str = r#"
+        [[files]]
+        name = "data"
+        kind = "memfd"
+        data = [1, 2, 3]
+        sealed = true
+        "#;
+
+        let bytes = wat::parse_str(READ_FD_0_WAT).expect("error parsing wat");
+        let package = package_with_config(&bytes, Some(CONFIG)).unwrap();
+        let results: Vec<i32> = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap()
+            .exit
+            .values()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![3]);
+    }
+
+    #[test]
+    fn workload_run_inline() {
+        const CONFIG: &str = r#"
+        [[files]]
+        name = "data"
+        kind = "inline"
+        data = "AQID"
+        "#;
+
+        let bytes = wat::parse_str(READ_FD_0_WAT).expect("error parsing wat");
+        let package = package_with_config(&bytes, Some(CONFIG)).unwrap();
+        let results: Vec<i32> = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap()
+            .exit
+            .values()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![3]);
+    }
+
+    #[cfg(feature = "remote-fetch")]
+    #[test]
+    fn package_from_url() {
+        for url in [
+            "http://example.com/main.wasm",
+            "https://example.com/main.wasm#md5=d41d8cd98f00b204e9800998ecf8427e",
+        ] {
+            let url = url.parse().unwrap();
+            assert!(Package::from_url(&url, None).is_err(), "{url}");
+        }
+    }
+
+    #[cfg(feature = "remote-fetch")]
+    #[test]
+    fn package_from_url_https() {
+        use sha2::{Digest, Sha256};
+
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let fetch = |digest: Option<String>| {
+            let (mut url, roots) = runtime::mock_https(bytes.clone());
+            url.set_fragment(digest.map(|digest| format!("sha256={digest}")).as_deref());
+            let tls_config = rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            Package::from_url(&url, Some(std::sync::Arc::new(tls_config)))
+        };
+
+        let package = fetch(None).unwrap();
+        assert!(matches!(&package, Package::Module(wasm) if *wasm == bytes));
+        let results: Vec<i32> = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap()
+            .exit
+            .values()
+            .iter()
+            .map(Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![1]);
+
+        let digest = format!("{:x}", Sha256::digest(&bytes));
+        fetch(Some(digest.to_ascii_uppercase())).unwrap();
+
+        let err = fetch(Some(format!("{:x}", Sha256::digest(b"")))).unwrap_err();
+        assert!(
+            format!("{err:#}").contains(&format!("digest `{digest}`")),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn execute_with_output_egress() {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = format!(
+            "[[files]]\nkind = \"connect\"\nprot = \"tcp\"\nhost = \"127.0.0.1\"\nport = {port}"
+        );
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let args = |egress: Option<Vec<String>>| Args {
+            env_passthrough: vec![],
+            egress,
+            package: package_with_config(&bytes, Some(&config)).unwrap(),
+            options: Default::default(),
+        };
+
+        execute_with_output(args(None)).unwrap();
+        execute_with_output(args(Some(vec![format!("127.0.0.1:{port}")]))).unwrap();
+        let err = execute_with_output(args(Some(vec![]))).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("not allowed by the egress policy"),
+            "{err:#}"
+        );
+        assert!(execute_with_output(args(Some(vec!["127.0.0.1".into()]))).is_err());
+    }
+
+    #[test]
+    fn package_validate() {
+        const CONFIG: &str = r#"
+        [[files]]
+        kind = "stdin"
+
+        [[files]]
+        kind = "stdout"
+        "#;
+
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let package = package_with_config(&bytes, Some(CONFIG)).unwrap();
+        package.validate(&[]).unwrap();
+        // The package can still be executed after validating it.
+        Runtime::default()
+            .execute(package, Default::default())
+            .unwrap();
+
+        for config in [
+            "[[files]]\nkind = \"null\"\n[[files]]\nkind = \"null\"",
+            "[[files]]\nname = \"rx\"\nkind = \"pipe\"\npair = 0",
+            "[[files]]\nname = \"data\"\nkind = \"inline\"",
+            "[env]\n\"A=B\" = \"C\"",
+            "steward = \"ftp://attest.profian.com\"",
+            "self_signed_cert_validity_secs = 0",
+        ] {
+            let package = package_with_config(&bytes, Some(config)).unwrap();
+            assert!(package.validate(&[]).is_err(), "{config}");
+        }
+
+        // Only the variables passed through by the launcher may be referenced.
+        let config = "env_passthrough = [\"PATH\"]\nargs = [\"${PATH}\"]";
+        let package = package_with_config(&bytes, Some(config)).unwrap();
+        assert!(package.validate(&[]).is_err());
+        let package = package_with_config(&bytes, Some(config)).unwrap();
+        package.validate(&["PATH".into()]).unwrap();
+    }
+
+    #[test]
+    fn package_bundle() {
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let package = Package::Bundle {
+            wasm: bytes.clone(),
+            conf: Some("[[files]]\nkind = \"stdin\"".into()),
+        };
+        package.validate(&[]).unwrap();
+        let workload = Workload::try_from(package).unwrap();
+        assert_eq!(workload.webasm, bytes);
+        assert_eq!(workload.config.unwrap().files.len(), 1);
 
-        // TODO/FIXME: we need a way to configure WASI stdout so we can capture
-        // and check it here...
+        let package = Package::Bundle {
+            wasm: bytes,
+            conf: Some("self_signed_cert_validity_secs = 0".into()),
+        };
+        assert!(package.validate(&[]).is_err());
     }
 }
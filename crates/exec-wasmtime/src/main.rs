@@ -41,5 +41,6 @@ fn main() -> anyhow::Result<()> {
         .with(EnvFilter::from_default_env())
         .init();
 
-    execute()
+    let code = execute()?;
+    std::process::exit(code)
 }
@@ -0,0 +1,384 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Retrieval of Wasm workloads from OCI image registries
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// Media type of the OCI image manifests requested from the registry
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// Media type of the image layer containing the Wasm module
+const WASM_LAYER_MEDIA_TYPE: &str = "application/vnd.wasm.content.layer.v1+wasm";
+
+/// Annotation of the image manifest containing the Enarx.toml of the workload
+const CONFIG_ANNOTATION: &str = "dev.enarx.config";
+
+/// Registry of image references without an explicit registry
+const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// Host serving the registry API of [DEFAULT_REGISTRY]
+const DEFAULT_REGISTRY_HOST: &str = "registry-1.docker.io";
+
+/// Maximum size of an image manifest in bytes
+const MAX_MANIFEST_SIZE: u64 = 4_000_000;
+
+/// Credentials used to authenticate to an OCI registry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegistryAuth {
+    /// Authenticate with a username and password, or an access token as password
+    Basic {
+        /// Name of the user
+        username: String,
+        /// Password or access token of the user
+        password: String,
+    },
+}
+
+impl RegistryAuth {
+    fn header(&self) -> String {
+        match self {
+            Self::Basic { username, password } => {
+                format!("Basic {}", base64::encode(format!("{username}:{password}")))
+            }
+        }
+    }
+}
+
+/// Reference to an image in an OCI registry, e.g. `ghcr.io/enarx/app:latest`
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Reference {
+    registry: String,
+    repository: String,
+    /// Tag or digest of the image manifest
+    reference: String,
+}
+
+impl std::str::FromStr for Reference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, reference) = match s.split_once('@') {
+            Some((name, digest)) => (name, digest.to_string()),
+            None => match s.rsplit_once(':') {
+                // A colon in the last path component separates the tag, other colons a port.
+                Some((name, tag)) if !tag.contains('/') => (name, tag.to_string()),
+                _ => (s, "latest".to_string()),
+            },
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((registry, repository))
+                if registry.contains(['.', ':']) || registry == "localhost" =>
+            {
+                (registry.to_string(), repository.to_string())
+            }
+            _ => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+        };
+        // Official images of Docker Hub reside in the `library` namespace.
+        let repository = if registry == DEFAULT_REGISTRY && !repository.contains('/') {
+            format!("library/{repository}")
+        } else {
+            repository
+        };
+        ensure!(
+            !repository.is_empty()
+                && !reference.is_empty()
+                && repository
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-/".contains(c)),
+            "invalid image reference `{s}`"
+        );
+        Ok(Self {
+            registry,
+            repository,
+            reference,
+        })
+    }
+}
+
+impl Reference {
+    fn url(&self, kind: &str, reference: &str) -> String {
+        let host = if self.registry == DEFAULT_REGISTRY {
+            DEFAULT_REGISTRY_HOST
+        } else {
+            &self.registry
+        };
+        format!("https://{host}/v2/{}/{kind}/{reference}", self.repository)
+    }
+}
+
+/// Descriptor of content in an OCI registry
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+    size: u64,
+}
+
+/// OCI image manifest
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    layers: Vec<Descriptor>,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+impl Manifest {
+    /// Returns the layer containing the Wasm module
+    fn wasm_layer(&self) -> Result<&Descriptor> {
+        let mut layers = self
+            .layers
+            .iter()
+            .filter(|layer| layer.media_type == WASM_LAYER_MEDIA_TYPE);
+        let layer = layers
+            .next()
+            .ok_or_else(|| anyhow!("image contains no layer of type `{WASM_LAYER_MEDIA_TYPE}`"))?;
+        ensure!(
+            layers.next().is_none(),
+            "image contains more than one layer of type `{WASM_LAYER_MEDIA_TYPE}`"
+        );
+        Ok(layer)
+    }
+}
+
+/// Checks that `data` matches the `sha256:<hex>` digest `digest`
+fn verify_digest(data: &[u8], digest: &str) -> Result<()> {
+    let expected = digest
+        .strip_prefix("sha256:")
+        .ok_or_else(|| anyhow!("unsupported digest `{digest}`"))?;
+    let actual = format!("{:x}", Sha256::digest(data));
+    ensure!(
+        actual == expected,
+        "SHA-256 digest `{actual}` does not match `{digest}`"
+    );
+    Ok(())
+}
+
+/// Parses the parameters of a `WWW-Authenticate` challenge, e.g. `Bearer realm="...",service="..."`
+fn challenge(header: &str) -> (&str, HashMap<&str, &str>) {
+    let (scheme, params) = header.split_once(' ').unwrap_or((header, ""));
+    let params = params
+        .split(',')
+        .filter_map(|param| {
+            let (key, value) = param.trim().split_once('=')?;
+            Some((key, value.trim_matches('"')))
+        })
+        .collect();
+    (scheme, params)
+}
+
+/// Client of the registry API for the repository of an image
+struct Registry<'a> {
+    agent: ureq::Agent,
+    image: &'a Reference,
+    auth: Option<&'a RegistryAuth>,
+    authorization: Option<String>,
+}
+
+impl<'a> Registry<'a> {
+    fn new(image: &'a Reference, auth: Option<&'a RegistryAuth>) -> Self {
+        Self {
+            agent: ureq::agent(),
+            image,
+            auth,
+            authorization: None,
+        }
+    }
+
+    /// Answers the authentication challenge of the registry
+    fn authenticate(&mut self, header: Option<&str>) -> Result<()> {
+        let (scheme, params) = header
+            .map(challenge)
+            .context("registry requires authentication without a challenge")?;
+        if scheme.eq_ignore_ascii_case("basic") {
+            let auth = self.auth.context("registry requires credentials")?;
+            self.authorization = Some(auth.header());
+            return Ok(());
+        }
+        ensure!(
+            scheme.eq_ignore_ascii_case("bearer"),
+            "unsupported authentication scheme `{scheme}`"
+        );
+
+        let realm = params
+            .get("realm")
+            .context("authentication challenge lacks a realm")?;
+        let scope = format!("repository:{}:pull", self.image.repository);
+        let mut request = self.agent.get(realm).query("scope", &scope);
+        if let Some(service) = params.get("service") {
+            request = request.query("service", service);
+        }
+        if let Some(auth) = self.auth {
+            request = request.set("Authorization", &auth.header());
+        }
+
+        #[derive(Deserialize)]
+        struct Token {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+        let token: Token = request
+            .call()
+            .with_context(|| format!("failed to fetch token from `{realm}`"))?
+            .into_json()
+            .context("failed to decode token")?;
+        let token = token
+            .token
+            .or(token.access_token)
+            .context("token response lacks a token")?;
+        self.authorization = Some(format!("Bearer {token}"));
+        Ok(())
+    }
+
+    /// Fetches `kind`, i.e. `manifests` or `blobs`, by `reference` from the repository
+    fn get(&mut self, kind: &str, reference: &str, accept: &str, limit: u64) -> Result<Vec<u8>> {
+        let url = self.image.url(kind, reference);
+        let mut retried = false;
+        let response = loop {
+            let mut request = self.agent.get(&url).set("Accept", accept);
+            if let Some(authorization) = &self.authorization {
+                request = request.set("Authorization", authorization);
+            }
+            match request.call() {
+                Ok(response) => break response,
+                Err(ureq::Error::Status(401, response)) if !retried => {
+                    self.authenticate(response.header("WWW-Authenticate"))
+                        .with_context(|| format!("failed to authenticate to `{url}`"))?;
+                    retried = true;
+                }
+                Err(e) => return Err(e).with_context(|| format!("failed to fetch `{url}`")),
+            }
+        };
+
+        let mut body = vec![];
+        response
+            .into_reader()
+            .take(limit + 1)
+            .read_to_end(&mut body)
+            .with_context(|| format!("failed to read `{url}`"))?;
+        ensure!(
+            body.len() as u64 <= limit,
+            "`{url}` exceeds the size limit of `{limit}` bytes"
+        );
+        Ok(body)
+    }
+}
+
+/// Pulls the Wasm module and the optional config annotated on the manifest of `image`
+pub fn pull(
+    image: &str,
+    auth: Option<&RegistryAuth>,
+    max_wasm_size: u64,
+    max_conf_size: u64,
+) -> Result<(Vec<u8>, Option<String>)> {
+    let image: Reference = image.parse()?;
+    let mut registry = Registry::new(&image, auth);
+
+    let manifest = registry
+        .get(
+            "manifests",
+            &image.reference,
+            MANIFEST_MEDIA_TYPE,
+            MAX_MANIFEST_SIZE,
+        )
+        .context("failed to fetch image manifest")?;
+    if image.reference.contains(':') {
+        verify_digest(&manifest, &image.reference).context("invalid image manifest")?;
+    }
+    let manifest: Manifest =
+        serde_json::from_slice(&manifest).context("failed to decode image manifest")?;
+
+    let conf = manifest.annotations.get(CONFIG_ANNOTATION).cloned();
+    if let Some(conf) = &conf {
+        ensure!(
+            conf.len() as u64 <= max_conf_size,
+            "config annotation exceeds the size limit of `{max_conf_size}` bytes"
+        );
+    }
+
+    let layer = manifest.wasm_layer()?;
+    if layer.size > max_wasm_size {
+        bail!("Wasm layer exceeds the size limit of `{max_wasm_size}` bytes");
+    }
+    let wasm = registry
+        .get("blobs", &layer.digest, &layer.media_type, max_wasm_size)
+        .context("failed to fetch Wasm layer")?;
+    verify_digest(&wasm, &layer.digest).context("invalid Wasm layer")?;
+    Ok((wasm, conf))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reference() {
+        let parse = |s: &str| {
+            let r = s.parse::<Reference>().unwrap();
+            (r.registry, r.repository, r.reference)
+        };
+        let owned = |(a, b, c): (&str, &str, &str)| (a.into(), b.into(), c.into());
+
+        assert_eq!(parse("app"), owned(("docker.io", "library/app", "latest")));
+        assert_eq!(
+            parse("enarx/app:v1"),
+            owned(("docker.io", "enarx/app", "v1"))
+        );
+        assert_eq!(
+            parse("ghcr.io/enarx/app"),
+            owned(("ghcr.io", "enarx/app", "latest"))
+        );
+        assert_eq!(
+            parse("localhost:5000/app:v1"),
+            owned(("localhost:5000", "app", "v1"))
+        );
+        assert_eq!(
+            parse("ghcr.io/enarx/app@sha256:0123"),
+            owned(("ghcr.io", "enarx/app", "sha256:0123"))
+        );
+        assert!("ghcr.io/Enarx/app".parse::<Reference>().is_err());
+        assert!("app:".parse::<Reference>().is_err());
+    }
+
+    #[test]
+    fn wasm_layer() {
+        let manifest = |layers: &[&str]| -> Manifest {
+            let layers = layers
+                .iter()
+                .map(|typ| format!(r#"{{"mediaType":"{typ}","digest":"sha256:00","size":1}}"#))
+                .collect::<Vec<_>>()
+                .join(",");
+            serde_json::from_str(&format!(
+                r#"{{"schemaVersion":2,"layers":[{layers}],"annotations":{{"{CONFIG_ANNOTATION}":"args = []"}}}}"#
+            ))
+            .unwrap()
+        };
+
+        let m = manifest(&[
+            "application/vnd.oci.image.layer.v1.tar+gzip",
+            WASM_LAYER_MEDIA_TYPE,
+        ]);
+        assert_eq!(m.wasm_layer().unwrap().media_type, WASM_LAYER_MEDIA_TYPE);
+        assert_eq!(m.annotations[CONFIG_ANNOTATION], "args = []");
+
+        assert!(manifest(&["application/vnd.oci.image.layer.v1.tar+gzip"])
+            .wasm_layer()
+            .is_err());
+        assert!(manifest(&[WASM_LAYER_MEDIA_TYPE, WASM_LAYER_MEDIA_TYPE])
+            .wasm_layer()
+            .is_err());
+    }
+
+    #[test]
+    fn digest() {
+        let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        verify_digest(b"hello", digest).unwrap();
+        assert!(verify_digest(b"world", digest).is_err());
+        assert!(verify_digest(b"hello", "md5:5d41402abc4b2a76b9719d911017c592").is_err());
+    }
+}
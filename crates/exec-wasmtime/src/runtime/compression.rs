@@ -0,0 +1,112 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Transparent decompression of Wasm modules
+
+use std::borrow::Cow;
+use std::io::Read;
+
+use anyhow::{bail, Context};
+use flate2::read::GzDecoder;
+
+/// Magic bytes of a gzip stream
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Magic bytes of a zstd frame
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Maximum size of a decompressed Wasm module, unless a lower memory limit is configured
+pub const MAX_MODULE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Decompresses `webasm`, if it is compressed with gzip or zstd
+///
+/// Any other input, e.g. raw Wasm, is returned unchanged.
+/// Decompression fails, if the module exceeds `limit` bytes.
+pub fn decompress(webasm: &[u8], limit: u64) -> anyhow::Result<Cow<'_, [u8]>> {
+    let (codec, reader): (_, Box<dyn Read>) = if webasm.starts_with(GZIP_MAGIC) {
+        ("gzip", Box::new(GzDecoder::new(webasm)))
+    } else if webasm.starts_with(ZSTD_MAGIC) {
+        let decoder = zstd::stream::read::Decoder::with_buffer(webasm)
+            .context("failed to initialize zstd decoder")?;
+        ("zstd", Box::new(decoder))
+    } else {
+        return Ok(Cow::Borrowed(webasm));
+    };
+
+    let mut module = vec![];
+    reader
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut module)
+        .with_context(|| format!("failed to decompress {codec} Wasm module"))?;
+    if module.len() as u64 > limit {
+        bail!("decompressed {codec} Wasm module exceeds the limit of {limit} bytes");
+    }
+    Ok(Cow::Owned(module))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    const MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+    fn compress_gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn compress_zstd(data: &[u8]) -> Vec<u8> {
+        zstd::encode_all(data, 0).unwrap()
+    }
+
+    #[test]
+    fn raw() {
+        assert!(matches!(
+            decompress(MODULE, MAX_MODULE_SIZE).unwrap(),
+            Cow::Borrowed(MODULE)
+        ));
+    }
+
+    #[test]
+    fn gzip_module() {
+        let compressed = compress_gzip(MODULE);
+        assert!(compressed.starts_with(GZIP_MAGIC));
+        assert_eq!(decompress(&compressed, MAX_MODULE_SIZE).unwrap(), MODULE);
+    }
+
+    #[test]
+    fn zstd_module() {
+        let compressed = compress_zstd(MODULE);
+        assert!(compressed.starts_with(ZSTD_MAGIC));
+        assert_eq!(decompress(&compressed, MAX_MODULE_SIZE).unwrap(), MODULE);
+    }
+
+    #[test]
+    fn bomb() {
+        let bomb = vec![0; 1024 * 1024];
+        for compressed in [compress_gzip(&bomb), compress_zstd(&bomb)] {
+            assert!(compressed.len() < 4096);
+            let err = decompress(&compressed, 1024).unwrap_err();
+            assert!(
+                err.to_string().contains("exceeds the limit of 1024 bytes"),
+                "{err}"
+            );
+            assert_eq!(
+                decompress(&compressed, bomb.len() as u64).unwrap(),
+                &bomb[..]
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut compressed = compress_gzip(MODULE);
+        compressed.truncate(compressed.len() / 2);
+        assert!(decompress(&compressed, MAX_MODULE_SIZE).is_err());
+    }
+}
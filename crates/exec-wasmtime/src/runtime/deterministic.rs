@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sources of nondeterminism replaced in deterministic executions
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use cap_std::time::{Instant, SystemTime};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use wasi_common::clocks::{WasiClocks, WasiMonotonicClock, WasiSystemClock};
+
+/// System clock standing still at a fixed time
+struct FixedSystemClock(SystemTime);
+
+impl WasiSystemClock for FixedSystemClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self, _precision: Duration) -> SystemTime {
+        self.0
+    }
+}
+
+/// Monotonic clock standing still at the creation time of the WASI context
+struct FixedMonotonicClock(Instant);
+
+impl WasiMonotonicClock for FixedMonotonicClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self, _precision: Duration) -> Instant {
+        self.0
+    }
+}
+
+/// Returns clocks, whose system clock reports `time` since the Unix epoch
+/// and whose monotonic clock reports no elapsed time
+pub fn clocks(time: Duration) -> WasiClocks {
+    let creation_time = Instant::from_std(std::time::Instant::now());
+    WasiClocks {
+        system: Box::new(FixedSystemClock(SystemTime::from_std(UNIX_EPOCH + time))),
+        monotonic: Box::new(FixedMonotonicClock(creation_time)),
+        creation_time,
+    }
+}
+
+/// Returns a cryptographically secure random number generator seeded with `seed`
+pub fn random(seed: [u8; 32]) -> Box<dyn RngCore + Send + Sync> {
+    Box::new(StdRng::from_seed(seed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reproducible() {
+        let clocks = clocks(Duration::from_secs(42));
+        let precision = Duration::ZERO;
+        assert_eq!(
+            clocks.system.now(precision),
+            SystemTime::from_std(UNIX_EPOCH + Duration::from_secs(42))
+        );
+        assert_eq!(clocks.monotonic.now(precision), clocks.creation_time);
+
+        let mut a = [0u8; 32];
+        let mut b = [0u8; 32];
+        random([1; 32]).fill_bytes(&mut a);
+        random([1; 32]).fill_bytes(&mut b);
+        assert_eq!(a, b);
+        random([2; 32]).fill_bytes(&mut b);
+        assert_ne!(a, b);
+    }
+}
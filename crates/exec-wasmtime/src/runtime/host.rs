@@ -0,0 +1,1243 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Enarx-specific host functions exposed to the guest in the `host` module
+//!
+//! # `attestation_report_v2(nonce_ptr: i32, nonce_len: i32, out_ptr: i32, out_len: i32, out_written_ptr: i32) -> i32`
+//!
+//! Writes an attestation report of the platform into the guest buffer at `out_ptr`,
+//! truncated to `out_len` bytes, and the full length of the report as a little-endian `u32`
+//! to `out_written_ptr`. A report length exceeding `out_len` indicates truncation.
+//!
+//! The nonce of `nonce_len` bytes at `nonce_ptr` must not exceed [`MAX_NONCE_SIZE`] bytes.
+//! A nonce fitting into the report data of the platform is embedded verbatim (zero-padded),
+//! while a larger nonce is hashed down with SHA-512 first.
+//!
+//! If `attestation_cache_size` is configured, the reports of the most recently used nonces
+//! are cached and a report is only requested from the platform for a new nonce.
+//!
+//! Returns `0` on success or one of the following error codes:
+//! - [`ERR_NONCE_SIZE`]: the nonce exceeds [`MAX_NONCE_SIZE`] bytes
+//! - [`ERR_PLATFORM`]: the platform could not be determined
+//! - [`ERR_MEMORY`]: a guest buffer is out of bounds
+//! - [`ERR_ATTEST`]: the platform failed to produce a report
+//!
+//! # `attestation_report_json(nonce_ptr: i32, nonce_len: i32, out_ptr: i32, out_len: i32, out_written_ptr: i32) -> i32`
+//!
+//! Like `attestation_report_v2`, but writes a JSON object, which does not require
+//! platform-specific parsing by the guest:
+//!
+//! ```json
+//! {
+//!   "platform": "snp",
+//!   "report": "<base64-encoded attestation report>",
+//!   "certificates": ["<base64-encoded DER certificate>", ...],
+//!   "nonce_echo": "<base64-encoded nonce>"
+//! }
+//! ```
+//!
+//! `platform` is one of `kvm`, `snp` or `sgx`. `certificates` contains the certificate chain
+//! of the identity of the workload starting with the end-entity certificate.
+//!
+//! # `attestation_report_eat(nonce_ptr: i32, nonce_len: i32, out_ptr: i32, out_len: i32, out_written_ptr: i32) -> i32`
+//!
+//! Like `attestation_report_v2`, but writes the claims set of an Entity Attestation Token (EAT)
+//! as defined by IETF RATS, i.e. a CBOR map with integer keys. The claims are not signed,
+//! the report of the platform embedded in them is the evidence to be verified.
+//!
+//! | Key      | Claim                                                                     |
+//! |----------|---------------------------------------------------------------------------|
+//! | `10`     | `eat_nonce`: byte string of the nonce                                     |
+//! | `-70000` | platform: text string `kvm`, `snp` or `sgx`                               |
+//! | `-70001` | report: byte string of the attestation report of the platform             |
+//! | `-70002` | public key: byte string of the DER-encoded `SubjectPublicKeyInfo` of the key of the workload |
+//!
+//! The platform-specific claims use keys of the private range, which are not registered.
+//!
+//! # `attestation_report(nonce_ptr: i32, nonce_len: i32, out_ptr: i32, out_len: i32)`
+//!
+//! The original signature of `attestation_report_v2`, which cannot report errors or the length
+//! of the report to the guest. Errors are only logged by the host and leave the guest buffer
+//! untouched. It is kept for existing guests, new guests should use `attestation_report_v2`.
+//!
+//! # `platform_info(out_ptr: i32, out_len: i32) -> i32`
+//!
+//! Writes information about the platform into the guest buffer at `out_ptr`,
+//! truncated to `out_len` bytes. The information consists of [`PLATFORM_INFO_SIZE`] bytes
+//! laid out as three little-endian `u32` values followed by the TCB version:
+//!
+//! | Offset | Field                                                      |
+//! |--------|------------------------------------------------------------|
+//! | 0      | technology: `1` for KVM, `2` for SEV-SNP and `3` for SGX  |
+//! | 4      | size of the attestation report in bytes                    |
+//! | 8      | size of the platform key in bytes                          |
+//! | 12     | TCB version of [`TCB_VERSION_SIZE`] bytes                  |
+//!
+//! The TCB version is reserved for the security version numbers of the platform, i.e. the
+//! `CURRENT_TCB` of SEV-SNP in its first 8 bytes or the `CPUSVN` of SGX. The shim does not
+//! report them to the runtime yet, so it is all zeros for now. Guests must treat zeros as
+//! an unknown version and verify the attestation report to learn the TCB of the platform.
+//!
+//! Returns `0` on success, [`ERR_PLATFORM`] or [`ERR_MEMORY`].
+//!
+//! # `attestation_report_info(nonce_len: i32) -> i32`
+//!
+//! Returns `0`, if a nonce of `nonce_len` bytes is embedded verbatim in the report,
+//! `1`, if it is hashed with SHA-512 and [`ERR_NONCE_SIZE`], if it is not supported.
+//!
+//! # `peer_certificates(fd: i32, out_ptr: i32, out_len: i32, out_written_ptr: i32) -> i32`
+//!
+//! Writes the DER-encoded certificate chain presented by the peer of the TLS stream `fd`
+//! into the guest buffer at `out_ptr`, truncated to `out_len` bytes, and the full length
+//! of the chain as a little-endian `u32` to `out_written_ptr`. The certificates are
+//! concatenated starting with the end-entity certificate. A peer, which presented
+//! no certificate, results in a length of `0`.
+//!
+//! Returns `0` on success, [`ERR_BADF`] or [`ERR_MEMORY`].
+//!
+//! # `get_certificate_chain(out_ptr: i32, out_len: i32, out_written_ptr: i32) -> i32`
+//!
+//! Writes the certificate chain of the identity of the workload, which is issued by the Steward
+//! or self-signed, if no Steward is configured, into the guest buffer at `out_ptr`, truncated to
+//! `out_len` bytes, and the full length of the data as a little-endian `u32` to `out_written_ptr`.
+//! The private key is never exposed. The data is laid out as follows, where all lengths
+//! are little-endian `u32` values:
+//!
+//! | Size   | Field                                                             |
+//! |--------|-------------------------------------------------------------------|
+//! | 4      | number of certificates `n`                                        |
+//! | 4      | length of the first certificate `l`, the end-entity certificate   |
+//! | `l`    | DER-encoded first certificate                                     |
+//! | ...    | length and DER encoding of the remaining `n - 1` certificates     |
+//! | 4      | length of the public key `k`                                      |
+//! | `k`    | DER-encoded `SubjectPublicKeyInfo` of the key of the workload     |
+//!
+//! Returns `0` on success or [`ERR_MEMORY`].
+//!
+//! # `get_random(out_ptr: i32, out_len: i32) -> i32`
+//!
+//! Fills the guest buffer at `out_ptr` with cryptographically secure random bytes of the host,
+//! which are limited to [`MAX_RANDOM_SIZE`] bytes per call. Unlike WASI `random_get`, it does
+//! not depend on the WASI context of the guest, except in deterministic executions, in which
+//! both draw from the same seeded generator. On failure, the guest buffer is zeroed.
+//!
+//! Returns the amount of bytes written on success, [`ERR_MEMORY`] or [`ERR_RANDOM`].
+//!
+//! # `fd_count() -> i32`
+//!
+//! Returns the amount of file descriptors configured in `files`, which are numbered from `0`.
+//!
+//! # `fd_info(fd: i32, out_ptr: i32, out_len: i32, out_written_ptr: i32) -> i32`
+//!
+//! Writes the kind and name of the configured file descriptor `fd` into the guest buffer
+//! at `out_ptr`, truncated to `out_len` bytes, and the full length of the data as a little-endian
+//! `u32` to `out_written_ptr`. The data is laid out as follows, where all integers are
+//! little-endian `u32` values:
+//!
+//! | Size | Field                                                                        |
+//! |------|------------------------------------------------------------------------------|
+//! | 4    | kind: `1` null, `2` stdin, `3` stdout, `4` stderr, `5` listen, `6` connect,  |
+//! |      | `7` dir, `8` pipe, `9` capture, `10` memfd, `11` inline, `12` sealed         |
+//! | 4    | length of the name `n`                                                       |
+//! | `n`  | name of the file descriptor                                                  |
+//!
+//! Unlike the `FD_NAMES` environment variable, which joins the names by `:`, the name is
+//! length-prefixed, so this is the preferred way to discover the file descriptors.
+//! `FD_COUNT` and `FD_NAMES` are still set for compatibility.
+//!
+//! Returns `0` on success, [`ERR_BADF`], if `fd` is not configured, or [`ERR_MEMORY`].
+//!
+//! # `ready(status: i32) -> i32`
+//!
+//! Signals to the embedder of the runtime, that the workload finished its startup, e.g. bound
+//! its sockets, and is serving. `status` is a byte passed on to the embedder. Only the first
+//! call notifies the embedder, subsequent calls have no effect.
+//!
+//! Returns `0` on success or [`ERR_READY_STATUS`], if `status` is not in the range of a byte.
+//!
+//! # `log(level: i32, msg_ptr: i32, msg_len: i32, kv_ptr: i32, kv_len: i32) -> i32`
+//!
+//! Emits the UTF-8 message of `msg_len` bytes at `msg_ptr` as a `tracing` event of the runtime
+//! with the target `guest`, so that it can be filtered, e.g. by `RUST_LOG=guest=info`.
+//! `level` is one of `1` error, `2` warn, `3` info, `4` debug or `5` trace.
+//! The event is emitted in a `guest` span, whose `identity` field is the base64-encoded
+//! SHA-256 digest of the public key of the workload.
+//!
+//! Optional key-value pairs of `kv_len` bytes at `kv_ptr` are attached to the event as its
+//! `fields` field. Each key and value is a UTF-8 string prefixed by its length as
+//! a little-endian `u32`, and a value follows each key. A `kv_len` of `0` attaches no pairs.
+//! Both the message and the key-value pairs are limited to [`MAX_LOG_SIZE`] bytes.
+//!
+//! Returns `0` on success or one of the following error codes:
+//! - [`ERR_LOG_LEVEL`]: the level is invalid
+//! - [`ERR_LOG_SIZE`]: the message or the key-value pairs exceed [`MAX_LOG_SIZE`] bytes
+//! - [`ERR_MEMORY`]: a guest buffer is out of bounds
+//! - [`ERR_LOG_ENCODING`]: a string is not valid UTF-8 or the key-value pairs are malformed
+
+pub mod crypto;
+
+use super::identity::platform::{Platform, Technology};
+use super::net::tls::Stream;
+use super::{wasmhelper, State};
+
+use std::collections::VecDeque;
+
+use anyhow::Context;
+use ciborium::value::Value;
+use enarx_config::File;
+use getrandom::getrandom;
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use tracing::{warn, Level};
+use wasi_common::file::{FileCaps, TableFileExt};
+use wasmtime::{Caller, Linker};
+
+/// Size of the report data of all supported platforms in bytes
+const REPORT_DATA_SIZE: usize = 64;
+
+/// Maximum size of a nonce passed to `attestation_report` in bytes
+pub const MAX_NONCE_SIZE: usize = 1024;
+
+/// Maximum amount of bytes written by a single `get_random` call
+pub const MAX_RANDOM_SIZE: usize = 4096;
+
+/// Maximum size of the message and of the key-value pairs passed to `log` in bytes
+pub const MAX_LOG_SIZE: usize = 4096;
+
+/// Size of the TCB version in the platform information written by `platform_info` in bytes
+pub const TCB_VERSION_SIZE: usize = 16;
+
+/// Size of the platform information written by `platform_info` in bytes
+pub const PLATFORM_INFO_SIZE: usize = 12 + TCB_VERSION_SIZE;
+
+/// Key of the `eat_nonce` claim in the report encoded by `attestation_report_eat`
+const EAT_NONCE: i64 = 10;
+
+/// Key of the platform claim in the report encoded by `attestation_report_eat`
+const EAT_PLATFORM: i64 = -70000;
+
+/// Key of the report claim in the report encoded by `attestation_report_eat`
+const EAT_REPORT: i64 = -70001;
+
+/// Key of the public key claim in the report encoded by `attestation_report_eat`
+const EAT_PUBLIC_KEY: i64 = -70002;
+
+/// The nonce exceeds [`MAX_NONCE_SIZE`] bytes
+pub const ERR_NONCE_SIZE: i32 = -1;
+
+/// The platform could not be determined
+pub const ERR_PLATFORM: i32 = -2;
+
+/// A guest buffer is out of bounds
+pub const ERR_MEMORY: i32 = -3;
+
+/// The platform failed to produce a report
+pub const ERR_ATTEST: i32 = -4;
+
+/// The file descriptor is not a TLS stream or not configured
+pub const ERR_BADF: i32 = -5;
+
+/// The host failed to generate random bytes
+pub const ERR_RANDOM: i32 = -6;
+
+/// The log level is invalid
+pub const ERR_LOG_LEVEL: i32 = -7;
+
+/// The log message or key-value pairs exceed [`MAX_LOG_SIZE`] bytes
+pub const ERR_LOG_SIZE: i32 = -8;
+
+/// The log message or key-value pairs are not valid UTF-8 or malformed
+pub const ERR_LOG_ENCODING: i32 = -9;
+
+/// The status passed to `ready` is not in the range of a byte
+pub const ERR_READY_STATUS: i32 = -10;
+
+/// Returns whether a nonce of `len` bytes needs to be hashed to fit into the report data
+fn hashed(len: usize) -> Option<bool> {
+    if len > MAX_NONCE_SIZE {
+        None
+    } else {
+        Some(len > REPORT_DATA_SIZE)
+    }
+}
+
+/// Derives the report data from a nonce
+fn report_data(nonce: &[u8]) -> [u8; REPORT_DATA_SIZE] {
+    let mut data = [0u8; REPORT_DATA_SIZE];
+    if nonce.len() > REPORT_DATA_SIZE {
+        data.copy_from_slice(&Sha512::digest(nonce));
+    } else {
+        data[..nonce.len()].copy_from_slice(nonce);
+    }
+    data
+}
+
+/// Attestation reports of the most recently used nonces
+///
+/// A capacity of `0` disables caching.
+#[derive(Debug, Default)]
+pub struct ReportCache {
+    capacity: usize,
+    /// Nonces and their reports ordered from the least to the most recently used
+    reports: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+impl ReportCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            reports: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the cached report for the nonce and marks it as the most recently used one
+    fn get(&mut self, nonce: &[u8]) -> Option<Vec<u8>> {
+        let index = self.reports.iter().position(|(n, _)| n == nonce)?;
+        let entry = self.reports.remove(index)?;
+        let report = entry.1.clone();
+        self.reports.push_back(entry);
+        Some(report)
+    }
+
+    /// Caches the report for the nonce evicting the least recently used one, if the cache is full
+    fn insert(&mut self, nonce: Vec<u8>, report: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.reports.len() == self.capacity {
+            self.reports.pop_front();
+        }
+        self.reports.push_back((nonce, report));
+    }
+}
+
+/// Returns the name of the platform technology used in encoded reports
+fn platform_name(technology: Technology) -> &'static str {
+    match technology {
+        Technology::Kvm => "kvm",
+        Technology::Snp => "snp",
+        Technology::Sgx => "sgx",
+    }
+}
+
+/// Attestation report encoded by `attestation_report_json`
+#[derive(Serialize)]
+struct JsonReport {
+    platform: &'static str,
+    report: String,
+    certificates: Vec<String>,
+    nonce_echo: String,
+}
+
+impl JsonReport {
+    fn new(
+        technology: Technology,
+        report: &[u8],
+        certs: &[rustls::Certificate],
+        nonce: &[u8],
+    ) -> Self {
+        Self {
+            platform: platform_name(technology),
+            report: base64::encode(report),
+            certificates: certs.iter().map(|cert| base64::encode(&cert.0)).collect(),
+            nonce_echo: base64::encode(nonce),
+        }
+    }
+}
+
+/// Encodes the report as the CBOR claims set of an Entity Attestation Token
+fn eat_report(
+    technology: Technology,
+    report: &[u8],
+    public_key: &[u8],
+    nonce: &[u8],
+) -> Result<Vec<u8>, ciborium::ser::Error<std::io::Error>> {
+    let claims = Value::Map(vec![
+        (EAT_NONCE.into(), nonce.into()),
+        (EAT_PLATFORM.into(), platform_name(technology).into()),
+        (EAT_REPORT.into(), report.into()),
+        (EAT_PUBLIC_KEY.into(), public_key.into()),
+    ]);
+    let mut eat = vec![];
+    ciborium::ser::into_writer(&claims, &mut eat)?;
+    Ok(eat)
+}
+
+/// Reads the nonce from the guest memory and returns the platform technology,
+/// the nonce and the report of the platform
+fn attest(
+    caller: &mut Caller<'_, State>,
+    nonce_ptr: i32,
+    nonce_len: i32,
+) -> Result<(Technology, Vec<u8>, Vec<u8>), i32> {
+    usize::try_from(nonce_len)
+        .ok()
+        .and_then(hashed)
+        .ok_or(ERR_NONCE_SIZE)?;
+
+    let platform = Platform::get().map_err(|_| ERR_PLATFORM)?;
+    let nonce = wasmhelper::read(caller, nonce_ptr, nonce_len).map_err(|_| ERR_MEMORY)?;
+    if let Some(report) = caller.data_mut().reports.get(&nonce) {
+        return Ok((platform.technology(), nonce, report));
+    }
+    let report = platform
+        .attest(&report_data(&nonce))
+        .map_err(|_| ERR_ATTEST)?;
+    caller
+        .data_mut()
+        .reports
+        .insert(nonce.clone(), report.clone());
+    Ok((platform.technology(), nonce, report))
+}
+
+/// Writes the report into the guest buffer and returns the full length of the report
+fn write_report(
+    caller: &mut Caller<'_, State>,
+    nonce_ptr: i32,
+    nonce_len: i32,
+    out_ptr: i32,
+    out_len: i32,
+) -> Result<usize, i32> {
+    let (_, _, report) = attest(caller, nonce_ptr, nonce_len)?;
+    wasmhelper::write(caller, out_ptr, out_len, &report).map_err(|_| ERR_MEMORY)?;
+    Ok(report.len())
+}
+
+/// Writes the JSON-encoded report into the guest buffer and returns its full length
+fn write_report_json(
+    caller: &mut Caller<'_, State>,
+    nonce_ptr: i32,
+    nonce_len: i32,
+    out_ptr: i32,
+    out_len: i32,
+) -> Result<usize, i32> {
+    let (technology, nonce, report) = attest(caller, nonce_ptr, nonce_len)?;
+    let json = JsonReport::new(technology, &report, &caller.data().certs, &nonce);
+    let json = serde_json::to_vec(&json).map_err(|_| ERR_ATTEST)?;
+    wasmhelper::write(caller, out_ptr, out_len, &json).map_err(|_| ERR_MEMORY)?;
+    Ok(json.len())
+}
+
+/// Writes the EAT-encoded report into the guest buffer and returns its full length
+fn write_report_eat(
+    caller: &mut Caller<'_, State>,
+    nonce_ptr: i32,
+    nonce_len: i32,
+    out_ptr: i32,
+    out_len: i32,
+) -> Result<usize, i32> {
+    let (technology, nonce, report) = attest(caller, nonce_ptr, nonce_len)?;
+    let eat = eat_report(technology, &report, &caller.data().public_key, &nonce)
+        .map_err(|_| ERR_ATTEST)?;
+    wasmhelper::write(caller, out_ptr, out_len, &eat).map_err(|_| ERR_MEMORY)?;
+    Ok(eat.len())
+}
+
+fn attestation_report_v2(
+    mut caller: Caller<'_, State>,
+    nonce_ptr: i32,
+    nonce_len: i32,
+    out_ptr: i32,
+    out_len: i32,
+    out_written_ptr: i32,
+) -> i32 {
+    match write_report(&mut caller, nonce_ptr, nonce_len, out_ptr, out_len) {
+        Ok(len) => write_len(&mut caller, out_written_ptr, len),
+        Err(errno) => errno,
+    }
+}
+
+fn attestation_report_json(
+    mut caller: Caller<'_, State>,
+    nonce_ptr: i32,
+    nonce_len: i32,
+    out_ptr: i32,
+    out_len: i32,
+    out_written_ptr: i32,
+) -> i32 {
+    match write_report_json(&mut caller, nonce_ptr, nonce_len, out_ptr, out_len) {
+        Ok(len) => write_len(&mut caller, out_written_ptr, len),
+        Err(errno) => errno,
+    }
+}
+
+fn attestation_report_eat(
+    mut caller: Caller<'_, State>,
+    nonce_ptr: i32,
+    nonce_len: i32,
+    out_ptr: i32,
+    out_len: i32,
+    out_written_ptr: i32,
+) -> i32 {
+    match write_report_eat(&mut caller, nonce_ptr, nonce_len, out_ptr, out_len) {
+        Ok(len) => write_len(&mut caller, out_written_ptr, len),
+        Err(errno) => errno,
+    }
+}
+
+fn attestation_report(
+    mut caller: Caller<'_, State>,
+    nonce_ptr: i32,
+    nonce_len: i32,
+    out_ptr: i32,
+    out_len: i32,
+) {
+    if let Err(errno) = write_report(&mut caller, nonce_ptr, nonce_len, out_ptr, out_len) {
+        warn!("`attestation_report` failed with error code {errno}");
+    }
+}
+
+fn platform_info(mut caller: Caller<'_, State>, out_ptr: i32, out_len: i32) -> i32 {
+    let platform = match Platform::get() {
+        Ok(platform) => platform,
+        Err(_) => return ERR_PLATFORM,
+    };
+    let technology: u32 = match platform.technology() {
+        Technology::Kvm => 1,
+        Technology::Snp => 2,
+        Technology::Sgx => 3,
+    };
+
+    let mut info = [0u8; PLATFORM_INFO_SIZE];
+    info[0..4].copy_from_slice(&technology.to_le_bytes());
+    info[4..8].copy_from_slice(&(platform.report_size() as u32).to_le_bytes());
+    info[8..12].copy_from_slice(&(platform.key_size() as u32).to_le_bytes());
+    // The TCB version at `info[12..]` is reserved and left zeroed.
+
+    match wasmhelper::write(&mut caller, out_ptr, out_len, &info) {
+        Ok(_) => 0,
+        Err(_) => ERR_MEMORY,
+    }
+}
+
+fn attestation_report_info(nonce_len: i32) -> i32 {
+    match usize::try_from(nonce_len).ok().and_then(hashed) {
+        Some(false) => 0,
+        Some(true) => 1,
+        None => ERR_NONCE_SIZE,
+    }
+}
+
+/// Writes the length of the data written by a host function as a little-endian `u32`
+fn write_len(caller: &mut Caller<'_, State>, out_written_ptr: i32, len: usize) -> i32 {
+    let len = match u32::try_from(len) {
+        Ok(len) => len.to_le_bytes(),
+        Err(_) => return ERR_MEMORY,
+    };
+    match wasmhelper::write(caller, out_written_ptr, len.len() as _, &len) {
+        Ok(written) if written == len.len() => 0,
+        _ => ERR_MEMORY,
+    }
+}
+
+/// Encodes the certificate chain and public key as laid out for `get_certificate_chain`
+fn certificate_chain(certs: &[rustls::Certificate], public_key: &[u8]) -> Option<Vec<u8>> {
+    let mut chain = u32::try_from(certs.len()).ok()?.to_le_bytes().to_vec();
+    for der in certs
+        .iter()
+        .map(|cert| cert.0.as_slice())
+        .chain([public_key])
+    {
+        chain.extend_from_slice(&u32::try_from(der.len()).ok()?.to_le_bytes());
+        chain.extend_from_slice(der);
+    }
+    Some(chain)
+}
+
+fn get_certificate_chain(
+    mut caller: Caller<'_, State>,
+    out_ptr: i32,
+    out_len: i32,
+    out_written_ptr: i32,
+) -> i32 {
+    let state = caller.data();
+    let chain = match certificate_chain(&state.certs, &state.public_key) {
+        Some(chain) => chain,
+        None => return ERR_MEMORY,
+    };
+    if wasmhelper::write(&mut caller, out_ptr, out_len, &chain).is_err() {
+        return ERR_MEMORY;
+    }
+    write_len(&mut caller, out_written_ptr, chain.len())
+}
+
+/// Returns the concatenated certificate chain of the peer of the TLS stream `fd`
+fn peer_chain(caller: &mut Caller<'_, State>, fd: u32) -> Option<Vec<u8>> {
+    let file = caller
+        .data_mut()
+        .wasi
+        .table()
+        .get_file(fd)
+        .ok()?
+        .get_cap(FileCaps::empty())
+        .ok()?;
+    let stream = file.as_any().downcast_ref::<Stream>()?;
+    Some(
+        stream
+            .peer_certificates()
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|cert| cert.0.iter().copied())
+            .collect(),
+    )
+}
+
+fn peer_certificates(
+    mut caller: Caller<'_, State>,
+    fd: i32,
+    out_ptr: i32,
+    out_len: i32,
+    out_written_ptr: i32,
+) -> i32 {
+    let chain = match u32::try_from(fd)
+        .ok()
+        .and_then(|fd| peer_chain(&mut caller, fd))
+    {
+        Some(chain) => chain,
+        None => return ERR_BADF,
+    };
+    if wasmhelper::write(&mut caller, out_ptr, out_len, &chain).is_err() {
+        return ERR_MEMORY;
+    }
+    write_len(&mut caller, out_written_ptr, chain.len())
+}
+
+fn get_random(mut caller: Caller<'_, State>, out_ptr: i32, out_len: i32) -> i32 {
+    let len = match usize::try_from(out_len) {
+        Ok(len) => len.min(MAX_RANDOM_SIZE),
+        Err(_) => return ERR_MEMORY,
+    };
+    let mut buf = vec![0; len];
+    let res = if caller.data().deterministic {
+        // Deterministic executions draw from the seeded generator of the WASI context.
+        caller.data_mut().wasi.random.fill_bytes(&mut buf);
+        Ok(())
+    } else {
+        getrandom(&mut buf)
+    };
+    if let Err(e) = res {
+        warn!("failed to generate random bytes: {e}");
+        buf.fill(0);
+    }
+    match (wasmhelper::write(&mut caller, out_ptr, len as _, &buf), res) {
+        (Err(_), _) => ERR_MEMORY,
+        (Ok(_), Err(_)) => ERR_RANDOM,
+        (Ok(written), Ok(())) => written as _,
+    }
+}
+
+/// Kind and name of a configured file descriptor as written by `fd_info`
+#[derive(Debug)]
+pub struct FdInfo {
+    kind: u32,
+    name: String,
+}
+
+impl From<&File> for FdInfo {
+    fn from(file: &File) -> Self {
+        let kind = match file {
+            File::Null(..) => 1,
+            File::Stdin(..) => 2,
+            File::Stdout(..) => 3,
+            File::Stderr(..) => 4,
+            File::Listen(..) => 5,
+            File::Connect(..) => 6,
+            File::Dir(..) => 7,
+            File::Pipe(..) => 8,
+            File::Capture(..) => 9,
+            File::MemFd(..) => 10,
+            File::Inline(..) => 11,
+            File::Sealed(..) => 12,
+        };
+        Self {
+            kind,
+            name: file.name().into(),
+        }
+    }
+}
+
+impl FdInfo {
+    /// Encodes the kind and the length-prefixed name as laid out for `fd_info`
+    fn encode(&self) -> Option<Vec<u8>> {
+        let mut info = self.kind.to_le_bytes().to_vec();
+        info.extend_from_slice(&u32::try_from(self.name.len()).ok()?.to_le_bytes());
+        info.extend_from_slice(self.name.as_bytes());
+        Some(info)
+    }
+}
+
+fn ready(mut caller: Caller<'_, State>, status: i32) -> i32 {
+    let status = match u8::try_from(status) {
+        Ok(status) => status,
+        Err(_) => return ERR_READY_STATUS,
+    };
+    if let Some(ready) = caller.data_mut().ready.take() {
+        (ready.0)(status);
+    }
+    0
+}
+
+fn fd_count(caller: Caller<'_, State>) -> i32 {
+    caller.data().fds.len().try_into().unwrap_or(i32::MAX)
+}
+
+fn fd_info(
+    mut caller: Caller<'_, State>,
+    fd: i32,
+    out_ptr: i32,
+    out_len: i32,
+    out_written_ptr: i32,
+) -> i32 {
+    let info = match usize::try_from(fd)
+        .ok()
+        .and_then(|fd| caller.data().fds.get(fd))
+    {
+        Some(info) => info.encode(),
+        None => return ERR_BADF,
+    };
+    let info = match info {
+        Some(info) => info,
+        None => return ERR_MEMORY,
+    };
+    if wasmhelper::write(&mut caller, out_ptr, out_len, &info).is_err() {
+        return ERR_MEMORY;
+    }
+    write_len(&mut caller, out_written_ptr, info.len())
+}
+
+/// Decodes the length-prefixed key-value pairs passed to `log` as `key=value` separated by spaces
+fn log_fields(mut kv: &[u8]) -> Option<String> {
+    fn string<'a>(kv: &mut &'a [u8]) -> Option<&'a str> {
+        let len = u32::from_le_bytes(kv.get(..4)?.try_into().ok()?);
+        let len = usize::try_from(len).ok()?;
+        let string = kv.get(4..)?.get(..len)?;
+        *kv = &kv[4 + len..];
+        std::str::from_utf8(string).ok()
+    }
+
+    let mut fields = vec![];
+    while !kv.is_empty() {
+        let key = string(&mut kv)?;
+        let value = string(&mut kv)?;
+        fields.push(format!("{key}={value:?}"));
+    }
+    Some(fields.join(" "))
+}
+
+fn log(
+    mut caller: Caller<'_, State>,
+    level: i32,
+    msg_ptr: i32,
+    msg_len: i32,
+    kv_ptr: i32,
+    kv_len: i32,
+) -> i32 {
+    let level = match level {
+        1 => Level::ERROR,
+        2 => Level::WARN,
+        3 => Level::INFO,
+        4 => Level::DEBUG,
+        5 => Level::TRACE,
+        _ => return ERR_LOG_LEVEL,
+    };
+    if [msg_len, kv_len]
+        .into_iter()
+        .any(|len| usize::try_from(len).map_or(true, |len| len > MAX_LOG_SIZE))
+    {
+        return ERR_LOG_SIZE;
+    }
+    let (message, kv) = match (
+        wasmhelper::read(&mut caller, msg_ptr, msg_len),
+        wasmhelper::read(&mut caller, kv_ptr, kv_len),
+    ) {
+        (Ok(message), Ok(kv)) => (message, kv),
+        _ => return ERR_MEMORY,
+    };
+    let (message, fields) = match (String::from_utf8(message), log_fields(&kv)) {
+        (Ok(message), Some(fields)) => (message, fields),
+        _ => return ERR_LOG_ENCODING,
+    };
+
+    let identity = base64::encode(Sha256::digest(&caller.data().public_key));
+    let span = tracing::span!(target: "guest", Level::ERROR, "guest", %identity);
+    let _span = span.enter();
+    match level {
+        Level::ERROR => tracing::error!(target: "guest", fields = %fields, "{message}"),
+        Level::WARN => tracing::warn!(target: "guest", fields = %fields, "{message}"),
+        Level::INFO => tracing::info!(target: "guest", fields = %fields, "{message}"),
+        Level::DEBUG => tracing::debug!(target: "guest", fields = %fields, "{message}"),
+        _ => tracing::trace!(target: "guest", fields = %fields, "{message}"),
+    }
+    0
+}
+
+/// Adds the `host` module to the linker
+pub fn add_to_linker(linker: &mut Linker<State>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("host", "attestation_report", attestation_report)
+        .context("failed to add `attestation_report`")?;
+    linker
+        .func_wrap("host", "attestation_report_v2", attestation_report_v2)
+        .context("failed to add `attestation_report_v2`")?;
+    linker
+        .func_wrap("host", "attestation_report_json", attestation_report_json)
+        .context("failed to add `attestation_report_json`")?;
+    linker
+        .func_wrap("host", "attestation_report_eat", attestation_report_eat)
+        .context("failed to add `attestation_report_eat`")?;
+    linker
+        .func_wrap("host", "platform_info", platform_info)
+        .context("failed to add `platform_info`")?;
+    linker
+        .func_wrap("host", "attestation_report_info", attestation_report_info)
+        .context("failed to add `attestation_report_info`")?;
+    linker
+        .func_wrap("host", "peer_certificates", peer_certificates)
+        .context("failed to add `peer_certificates`")?;
+    linker
+        .func_wrap("host", "get_certificate_chain", get_certificate_chain)
+        .context("failed to add `get_certificate_chain`")?;
+    linker
+        .func_wrap("host", "get_random", get_random)
+        .context("failed to add `get_random`")?;
+    linker
+        .func_wrap("host", "fd_count", fd_count)
+        .context("failed to add `fd_count`")?;
+    linker
+        .func_wrap("host", "fd_info", fd_info)
+        .context("failed to add `fd_info`")?;
+    linker
+        .func_wrap("host", "log", log)
+        .context("failed to add `log`")?;
+    linker
+        .func_wrap("host", "ready", ready)
+        .context("failed to add `ready`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::Runtime;
+
+    const MOCK_ATTESTATION_REPORT_WAT: &str = r#"(module
+      (import "host" "attestation_report_v2"
+        (func $attestation_report (param i32 i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "nonce")
+      (func (export "") (result i32 i32 i32)
+        (call $attestation_report
+          (i32.const 0) (i32.const 5) (i32.const 64) (i32.const 16) (i32.const 128))
+        (i32.load8_u (i32.const 64))
+        (i32.load (i32.const 128))
+      )
+    )"#;
+
+    const MOCK_LEGACY_ATTESTATION_REPORT_WAT: &str = r#"(module
+      (import "host" "attestation_report"
+        (func $attestation_report (param i32 i32 i32 i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "nonce")
+      (func (export "") (result i32)
+        (call $attestation_report (i32.const 0) (i32.const 5) (i32.const 64) (i32.const 16))
+        (i32.load8_u (i32.const 64))
+      )
+    )"#;
+
+    const TWO_ATTESTATION_REPORTS_WAT: &str = r#"(module
+      (import "host" "attestation_report_v2"
+        (func $attestation_report (param i32 i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "nonce")
+      (func (export "") (result i32 i32)
+        (call $attestation_report
+          (i32.const 0) (i32.const 5) (i32.const 64) (i32.const 16) (i32.const 128))
+        (call $attestation_report
+          (i32.const 0) (i32.const 5) (i32.const 64) (i32.const 16) (i32.const 128))
+      )
+    )"#;
+
+    const GET_CERTIFICATE_CHAIN_WAT: &str = r#"(module
+      (import "host" "get_certificate_chain"
+        (func $get_certificate_chain (param i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "") (result i32 i32 i32)
+        (call $get_certificate_chain (i32.const 0) (i32.const 4096) (i32.const 8192))
+        (i32.load (i32.const 0))
+        (i32.gt_u (i32.load (i32.const 4)) (i32.const 0))
+      )
+    )"#;
+
+    const GET_RANDOM_WAT: &str = r#"(module
+      (import "host" "get_random"
+        (func $get_random (param i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "") (result i32 i32 i32 i32)
+        (call $get_random (i32.const 0) (i32.const 16))
+        (call $get_random (i32.const 16) (i32.const 16))
+        (i32.or
+          (i64.ne (i64.load (i32.const 0)) (i64.load (i32.const 16)))
+          (i64.ne (i64.load (i32.const 8)) (i64.load (i32.const 24))))
+        (call $get_random (i32.const 0) (i32.const 65536))
+      )
+    )"#;
+
+    const FD_INFO_WAT: &str = r#"(module
+      (import "host" "fd_count" (func $fd_count (result i32)))
+      (import "host" "fd_info"
+        (func $fd_info (param i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "") (result i32 i32 i32 i32 i32 i32 i32)
+        (call $fd_count)
+        (call $fd_info (i32.const 2) (i32.const 0) (i32.const 64) (i32.const 128))
+        (i32.load (i32.const 0))
+        (i32.load (i32.const 4))
+        (i32.load (i32.const 8))
+        (i32.load (i32.const 128))
+        (call $fd_info (i32.const 3) (i32.const 0) (i32.const 64) (i32.const 128))
+      )
+    )"#;
+
+    const LOG_WAT: &str = r#"(module
+      (import "host" "log" (func $log (param i32 i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "hello from the guest")
+      (data (i32.const 32) "\03\00\00\00key\05\00\00\00value")
+      (data (i32.const 64) "\ff")
+      (func (export "") (result i32 i32 i32 i32)
+        (call $log (i32.const 3) (i32.const 0) (i32.const 20) (i32.const 32) (i32.const 16))
+        (call $log (i32.const 6) (i32.const 0) (i32.const 20) (i32.const 0) (i32.const 0))
+        (call $log (i32.const 3) (i32.const 64) (i32.const 1) (i32.const 0) (i32.const 0))
+        (call $log (i32.const 3) (i32.const 0) (i32.const 4097) (i32.const 0) (i32.const 0))
+      )
+    )"#;
+
+    const READY_WAT: &str = r#"(module
+      (import "host" "ready" (func $ready (param i32) (result i32)))
+      (func (export "") (result i32 i32 i32)
+        (call $ready (i32.const 256))
+        (call $ready (i32.const 7))
+        (call $ready (i32.const 8))
+      )
+    )"#;
+
+    #[test]
+    fn nonce() {
+        assert_eq!(hashed(0), Some(false));
+        assert_eq!(hashed(REPORT_DATA_SIZE), Some(false));
+        assert_eq!(hashed(REPORT_DATA_SIZE + 1), Some(true));
+        assert_eq!(hashed(MAX_NONCE_SIZE), Some(true));
+        assert_eq!(hashed(MAX_NONCE_SIZE + 1), None);
+
+        let data = report_data(b"nonce");
+        assert_eq!(&data[..5], b"nonce");
+        assert!(data[5..].iter().all(|b| *b == 0));
+
+        let nonce = [0xffu8; MAX_NONCE_SIZE];
+        assert_eq!(report_data(&nonce)[..], Sha512::digest(nonce)[..]);
+    }
+
+    #[test]
+    fn json_report() {
+        let certs = [rustls::Certificate(vec![1, 2, 3])];
+        let json = JsonReport::new(Technology::Snp, &[0xaa; 4], &certs, b"nonce");
+        let json: serde_json::Value = serde_json::to_value(json).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "platform": "snp",
+                "report": "qqqqqg==",
+                "certificates": ["AQID"],
+                "nonce_echo": "bm9uY2U=",
+            })
+        );
+    }
+
+    #[test]
+    fn eat_claims() {
+        let eat = eat_report(Technology::Sgx, &[0xaa; 4], &[1, 2, 3], b"nonce").unwrap();
+        let claims: Value = ciborium::de::from_reader(&eat[..]).unwrap();
+
+        assert_eq!(
+            claims,
+            Value::Map(vec![
+                (10.into(), b"nonce"[..].into()),
+                ((-70000).into(), "sgx".into()),
+                ((-70001).into(), vec![0xaa_u8; 4].into()),
+                ((-70002).into(), vec![1_u8, 2, 3].into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn chain() {
+        let certs = [
+            rustls::Certificate(vec![1, 2]),
+            rustls::Certificate(vec![3]),
+        ];
+        assert_eq!(
+            certificate_chain(&certs, &[4, 5, 6]).unwrap(),
+            [
+                &[2, 0, 0, 0][..],
+                &[2, 0, 0, 0, 1, 2],
+                &[1, 0, 0, 0, 3],
+                &[3, 0, 0, 0, 4, 5, 6],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn guest_certificate_chain() {
+        let bytes = wat::parse_str(GET_CERTIFICATE_CHAIN_WAT).expect("error parsing wat");
+        let results: Vec<i32> = crate::test::run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        // Without a Steward, the chain consists of the self-signed certificate only.
+        assert_eq!(results, vec![0, 1, 1]);
+    }
+
+    #[test]
+    fn guest_certificate_chain_steward() {
+        use crate::runtime::identity::{self, DEFAULT_SELFSIGNED_VALIDITY};
+
+        Platform::mock(vec![0xaa; 16]);
+        let san = ["localhost".try_into().unwrap()];
+        let (key, _) = identity::generate(None, None, &san).unwrap();
+        let (ca, _) = identity::generate(None, None, &[]).unwrap();
+        let certs = [
+            identity::selfsigned(&key, None, &san, DEFAULT_SELFSIGNED_VALIDITY).unwrap(),
+            identity::selfsigned(&ca, Some("CN=Steward"), &[], DEFAULT_SELFSIGNED_VALIDITY)
+                .unwrap(),
+        ]
+        .concat();
+        let url = identity::mock_steward(&certs, &key);
+
+        let bytes = wat::parse_str(GET_CERTIFICATE_CHAIN_WAT).expect("error parsing wat");
+        let config = format!("steward = \"{url}\"");
+        let package = crate::test::package_with_config(&bytes, Some(&config)).unwrap();
+        let results: Vec<i32> = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap()
+            .exit
+            .values()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        // The chain issued by the Steward is returned instead of a self-signed certificate.
+        assert_eq!(results, vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn guest_get_random() {
+        let bytes = wat::parse_str(GET_RANDOM_WAT).expect("error parsing wat");
+        let results: Vec<i32> = crate::test::run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        // Two calls produce different bytes and larger requests are capped.
+        assert_eq!(results, vec![16, 16, 1, MAX_RANDOM_SIZE as _]);
+    }
+
+    #[test]
+    fn guest_fd_info() {
+        let bytes = wat::parse_str(FD_INFO_WAT).expect("error parsing wat");
+        let config = r#"
+            [[files]]
+            kind = "stdin"
+
+            [[files]]
+            kind = "stdout"
+
+            [[files]]
+            kind = "null"
+            name = "log file"
+        "#;
+        let package = crate::test::package_with_config(&bytes, Some(config)).unwrap();
+        let results: Vec<i32> = Runtime::default()
+            .execute(package, Default::default())
+            .unwrap()
+            .exit
+            .values()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        let name = i32::from_le_bytes(*b"log ");
+        assert_eq!(results, vec![3, 0, 1, 8, name, 16, ERR_BADF]);
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(log_fields(b"").unwrap(), "");
+        let kv = [
+            &[1, 0, 0, 0][..],
+            b"a",
+            &[2, 0, 0, 0],
+            b"bc",
+            &[1, 0, 0, 0],
+            b"d",
+            &[0, 0, 0, 0],
+        ]
+        .concat();
+        assert_eq!(log_fields(&kv).unwrap(), r#"a="bc" d="""#);
+        // A key without a value
+        assert_eq!(log_fields(&kv[..kv.len() - 4]), None);
+        assert_eq!(log_fields(&[2, 0, 0, 0, 0xff, 0xfe, 0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn guest_log() {
+        use std::io::{self, Write};
+        use std::sync::{Arc, Mutex};
+
+        use tracing_subscriber::util::SubscriberInitExt;
+
+        #[derive(Clone, Default)]
+        struct Logs(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let logs = Logs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(Level::TRACE)
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .finish();
+
+        let bytes = wat::parse_str(LOG_WAT).expect("error parsing wat");
+        let results: Vec<i32> = {
+            let _subscriber = subscriber.set_default();
+            crate::test::run(&bytes)
+                .unwrap()
+                .iter()
+                .map(wasmtime::Val::unwrap_i32)
+                .collect()
+        };
+        assert_eq!(
+            results,
+            vec![0, ERR_LOG_LEVEL, ERR_LOG_ENCODING, ERR_LOG_SIZE]
+        );
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let line = logs
+            .lines()
+            .find(|line| line.contains("hello from the guest"))
+            .unwrap_or_else(|| panic!("guest log line missing in:\n{logs}"));
+        assert!(line.contains("INFO"), "{line}");
+        assert!(line.contains("guest{identity="), "{line}");
+        assert!(line.contains(r#"fields=key="value""#), "{line}");
+    }
+
+    #[test]
+    fn report_cache() {
+        let mut cache = ReportCache::new(2);
+        cache.insert(b"a".to_vec(), vec![1]);
+        cache.insert(b"b".to_vec(), vec![2]);
+        assert_eq!(cache.get(b"a"), Some(vec![1]));
+        // `b` is the least recently used report now.
+        cache.insert(b"c".to_vec(), vec![3]);
+        assert_eq!(cache.get(b"b"), None);
+        assert_eq!(cache.get(b"a"), Some(vec![1]));
+        assert_eq!(cache.get(b"c"), Some(vec![3]));
+
+        let mut cache = ReportCache::new(0);
+        cache.insert(b"a".to_vec(), vec![1]);
+        assert_eq!(cache.get(b"a"), None);
+    }
+
+    #[test]
+    fn cached_attestation_report() {
+        let bytes = wat::parse_str(TWO_ATTESTATION_REPORTS_WAT).expect("error parsing wat");
+        for (config, attestations) in [("", 3), ("attestation_cache_size = 1", 2)] {
+            Platform::mock(vec![0xaa; 16]);
+            let package = crate::test::package_with_config(&bytes, Some(config)).unwrap();
+            let results: Vec<i32> = Runtime::default()
+                .execute(package, Default::default())
+                .unwrap()
+                .exit
+                .values()
+                .iter()
+                .map(wasmtime::Val::unwrap_i32)
+                .collect();
+            assert_eq!(results, vec![0, 0]);
+            // One report is requested for the identity of the workload and
+            // the second report for the same nonce is returned from the cache.
+            assert_eq!(Platform::mock_attestations(), attestations, "{config}");
+        }
+    }
+
+    #[test]
+    fn mock_attestation_report() {
+        Platform::mock(vec![0xaa; 16]);
+
+        let bytes = wat::parse_str(MOCK_ATTESTATION_REPORT_WAT).expect("error parsing wat");
+        let results: Vec<i32> = crate::test::run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        assert_eq!(results, vec![0, (0xaa ^ b'n').into(), 16]);
+    }
+
+    #[test]
+    fn mock_legacy_attestation_report() {
+        Platform::mock(vec![0xaa; 16]);
+
+        let bytes = wat::parse_str(MOCK_LEGACY_ATTESTATION_REPORT_WAT).expect("error parsing wat");
+        let results: Vec<i32> = crate::test::run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        assert_eq!(results, vec![(0xaa ^ b'n').into()]);
+    }
+
+    #[test]
+    fn ready() {
+        use std::sync::{Arc, Mutex};
+
+        let statuses = Arc::new(Mutex::new(vec![]));
+        let runtime = Runtime::builder()
+            .on_ready({
+                let statuses = statuses.clone();
+                move |status| statuses.lock().unwrap().push(status)
+            })
+            .build();
+
+        let bytes = wat::parse_str(READY_WAT).expect("error parsing wat");
+        for executions in 1..=2 {
+            let results: Vec<i32> = runtime
+                .execute(crate::test::package(&bytes).unwrap(), Default::default())
+                .unwrap()
+                .exit
+                .values()
+                .iter()
+                .map(wasmtime::Val::unwrap_i32)
+                .collect();
+            assert_eq!(results, vec![ERR_READY_STATUS, 0, 0]);
+            // Only the first valid call of each execution notifies the embedder.
+            assert_eq!(*statuses.lock().unwrap(), vec![7; executions]);
+        }
+    }
+}
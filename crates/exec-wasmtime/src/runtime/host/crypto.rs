@@ -0,0 +1,900 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host functions of the [wasi-crypto](https://github.com/WebAssembly/wasi-crypto) ABIs
+//!
+//! The functions allow the workload to hash data and to sign data with the key of its identity
+//! without the key ever leaving the keep. The following subset of the `wasi_ephemeral_crypto_*`
+//! modules is provided:
+//!
+//! | Module                                    | Functions                                         |
+//! |-------------------------------------------|---------------------------------------------------|
+//! | `wasi_ephemeral_crypto_common`            | `array_output_len`, `array_output_pull`, `secrets_manager_open`, `secrets_manager_close` |
+//! | `wasi_ephemeral_crypto_asymmetric_common` | `keypair_from_id`, `keypair_publickey`, `keypair_close`, `publickey_export`, `publickey_close` |
+//! | `wasi_ephemeral_crypto_signatures`        | `signature_state_open`, `signature_state_update`, `signature_state_sign`, `signature_state_close`, `signature_export`, `signature_import`, `signature_close`, `signature_verification_state_open`, `signature_verification_state_update`, `signature_verification_state_verify`, `signature_verification_state_close` |
+//! | `wasi_ephemeral_crypto_symmetric`         | `symmetric_state_open`, `symmetric_state_absorb`, `symmetric_state_squeeze`, `symmetric_state_close` |
+//!
+//! The key of the workload identity is the only key pair. It is opened by `keypair_from_id`
+//! with the id [`KEEP_KEYPAIR_ID`] and an unspecified or the latest version. Depending on
+//! the configured `key_algorithm`, it signs with `ECDSA_P256_SHA256`, `ECDSA_P384_SHA384`
+//! or `Ed25519`. Signatures are encoded `raw` or, for ECDSA only, `der`. Public keys are
+//! encoded `raw` or `pkcs8`, i.e. as DER-encoded `SubjectPublicKeyInfo`.
+//!
+//! The symmetric functions support the hash functions `SHA-256`, `SHA-384`, `SHA-512`
+//! and `SHA-512/256`, which take neither a key nor options.
+//!
+//! `array_output_pull` closes the array output after copying its data into the guest buffer.
+//!
+//! All functions return `0` on success or a `crypto_errno` of the specification.
+
+use super::super::{wasmhelper, State};
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use ring::digest;
+use ring::rand::SystemRandom;
+use ring::signature::{self, EcdsaKeyPair, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use wasmtime::{Caller, Linker};
+
+/// Id of the key pair of the workload identity passed to `keypair_from_id`
+pub const KEEP_KEYPAIR_ID: &str = "keep";
+
+/// Maximum amount of handles open at the same time
+pub const MAX_HANDLES: usize = 1024;
+
+const COMMON: &str = "wasi_ephemeral_crypto_common";
+const ASYMMETRIC_COMMON: &str = "wasi_ephemeral_crypto_asymmetric_common";
+const SIGNATURES: &str = "wasi_ephemeral_crypto_signatures";
+const SYMMETRIC: &str = "wasi_ephemeral_crypto_symmetric";
+
+/// The operation succeeded
+pub const SUCCESS: i32 = 0;
+
+/// A guest buffer is out of bounds or malformed
+pub const GUEST_ERROR: i32 = 1;
+
+/// The encoding is not supported
+pub const UNSUPPORTED_ENCODING: i32 = 5;
+
+/// The algorithm is not supported
+pub const UNSUPPORTED_ALGORITHM: i32 = 6;
+
+/// Options are not supported
+pub const UNSUPPORTED_OPTION: i32 = 7;
+
+/// The requested output exceeds the output of the algorithm
+pub const INVALID_LENGTH: i32 = 9;
+
+/// The signature does not match the data
+pub const VERIFICATION_FAILED: i32 = 10;
+
+/// The algorithm failed
+pub const ALGORITHM_FAILURE: i32 = 12;
+
+/// The handle is not open or of the wrong type
+pub const INVALID_HANDLE: i32 = 15;
+
+/// The output exceeds the guest buffer
+pub const OVERFLOW: i32 = 16;
+
+/// [`MAX_HANDLES`] handles are open
+pub const TOO_MANY_HANDLES: i32 = 18;
+
+/// The algorithm does not take a key
+pub const KEY_NOT_SUPPORTED: i32 = 19;
+
+/// The key pair does not exist
+pub const NOT_FOUND: i32 = 26;
+
+/// Unspecified version of a managed key pair
+const VERSION_UNSPECIFIED: u64 = 0xff00_0000_0000_0000;
+
+/// Latest version of a managed key pair
+const VERSION_LATEST: u64 = 0xff00_0000_0000_0001;
+
+/// `publickey_encoding` of the raw public key
+const PUBLICKEY_RAW: i32 = 0;
+
+/// `publickey_encoding` of the DER-encoded `SubjectPublicKeyInfo`
+const PUBLICKEY_PKCS8: i32 = 1;
+
+/// `signature_encoding` of the raw signature, `r || s` for ECDSA
+const SIGNATURE_RAW: i32 = 0;
+
+/// `signature_encoding` of the DER-encoded `Ecdsa-Sig-Value`
+const SIGNATURE_DER: i32 = 1;
+
+/// Tag of `none` of an optional handle
+const NONE: u8 = 1;
+
+type Result<T> = std::result::Result<T, i32>;
+
+/// Signature algorithm of the key of the workload identity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Algorithm {
+    EcdsaP256,
+    EcdsaP384,
+    Ed25519,
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Self::EcdsaP256 => "ECDSA_P256_SHA256",
+            Self::EcdsaP384 => "ECDSA_P384_SHA384",
+            Self::Ed25519 => "Ed25519",
+        }
+    }
+
+    /// Returns the verification algorithm of signatures in `encoding`, if supported
+    fn verification(self, encoding: i32) -> Option<&'static dyn signature::VerificationAlgorithm> {
+        match (self, encoding) {
+            (Self::EcdsaP256, SIGNATURE_RAW) => Some(&signature::ECDSA_P256_SHA256_FIXED),
+            (Self::EcdsaP256, SIGNATURE_DER) => Some(&signature::ECDSA_P256_SHA256_ASN1),
+            (Self::EcdsaP384, SIGNATURE_RAW) => Some(&signature::ECDSA_P384_SHA384_FIXED),
+            (Self::EcdsaP384, SIGNATURE_DER) => Some(&signature::ECDSA_P384_SHA384_ASN1),
+            (Self::Ed25519, SIGNATURE_RAW) => Some(&signature::ED25519),
+            _ => None,
+        }
+    }
+}
+
+/// Key pair of the workload identity
+enum Key {
+    Ecdsa(EcdsaKeyPair),
+    Ed25519(Ed25519KeyPair),
+}
+
+/// Signature and its encoding
+struct Signature {
+    encoding: i32,
+    data: Vec<u8>,
+}
+
+/// Object referenced by a handle
+enum Object {
+    ArrayOutput(Vec<u8>),
+    SecretsManager,
+    Keypair,
+    PublicKey,
+    /// Signature state and the data to sign
+    SignatureState(Vec<u8>),
+    Signature(Signature),
+    /// Verification state and the data to verify
+    VerificationState(Vec<u8>),
+    Hash(digest::Context),
+}
+
+/// Key of the workload identity and the objects opened by the guest
+pub struct Crypto {
+    algorithm: Algorithm,
+    key: Key,
+    objects: HashMap<u32, Object>,
+    next: u32,
+}
+
+impl Crypto {
+    /// Loads the DER-encoded PKCS#8 private key of the workload identity
+    pub fn new(key: &[u8]) -> anyhow::Result<Self> {
+        let (algorithm, key) = if let Ok(key) = Ed25519KeyPair::from_pkcs8(key) {
+            (Algorithm::Ed25519, Key::Ed25519(key))
+        } else if let Ok(key) =
+            EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, key)
+        {
+            (Algorithm::EcdsaP256, Key::Ecdsa(key))
+        } else if let Ok(key) =
+            EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P384_SHA384_FIXED_SIGNING, key)
+        {
+            (Algorithm::EcdsaP384, Key::Ecdsa(key))
+        } else {
+            bail!("unsupported key algorithm")
+        };
+        Ok(Self {
+            algorithm,
+            key,
+            objects: HashMap::new(),
+            next: 0,
+        })
+    }
+
+    /// Returns the raw public key
+    fn public_key(&self) -> &[u8] {
+        match &self.key {
+            Key::Ecdsa(key) => key.public_key().as_ref(),
+            Key::Ed25519(key) => key.public_key().as_ref(),
+        }
+    }
+
+    /// Signs `data` returning the raw signature
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match &self.key {
+            Key::Ecdsa(key) => key
+                .sign(&SystemRandom::new(), data)
+                .map(|sig| sig.as_ref().to_vec())
+                .map_err(|_| ALGORITHM_FAILURE),
+            Key::Ed25519(key) => Ok(key.sign(data).as_ref().to_vec()),
+        }
+    }
+
+    fn verify(&self, data: &[u8], signature: &Signature) -> Result<()> {
+        let algorithm = self
+            .algorithm
+            .verification(signature.encoding)
+            .ok_or(UNSUPPORTED_ENCODING)?;
+        UnparsedPublicKey::new(algorithm, self.public_key())
+            .verify(data, &signature.data)
+            .map_err(|_| VERIFICATION_FAILED)
+    }
+
+    /// Opens a handle to `object`
+    fn insert(&mut self, object: Object) -> Result<u32> {
+        if self.objects.len() >= MAX_HANDLES {
+            return Err(TOO_MANY_HANDLES);
+        }
+        while self.objects.contains_key(&self.next) {
+            self.next = self.next.wrapping_add(1);
+        }
+        let handle = self.next;
+        self.objects.insert(handle, object);
+        Ok(handle)
+    }
+
+    fn get(&mut self, handle: i32) -> Result<&mut Object> {
+        self.objects.get_mut(&(handle as u32)).ok_or(INVALID_HANDLE)
+    }
+
+    /// Closes `handle`, if it refers to an object of the kind matched by `kind`
+    fn close(&mut self, handle: i32, kind: fn(&Object) -> bool) -> Result<Object> {
+        match self.objects.get(&(handle as u32)) {
+            Some(object) if kind(object) => {
+                self.objects.remove(&(handle as u32)).ok_or(INVALID_HANDLE)
+            }
+            _ => Err(INVALID_HANDLE),
+        }
+    }
+}
+
+/// Encodes the raw ECDSA signature `r || s` as DER-encoded `Ecdsa-Sig-Value`
+///
+/// The integers of the supported curves are short enough for single byte lengths.
+fn der(raw: &[u8]) -> Vec<u8> {
+    let (r, s) = raw.split_at(raw.len() / 2);
+    let mut seq = vec![];
+    for int in [r, s] {
+        let start = int.iter().position(|b| *b != 0).unwrap_or(int.len() - 1);
+        let int = &int[start..];
+        let pad = int[0] & 0x80 != 0;
+        seq.push(0x02);
+        seq.push((int.len() + usize::from(pad)) as u8);
+        if pad {
+            seq.push(0);
+        }
+        seq.extend_from_slice(int);
+    }
+    [&[0x30, seq.len() as u8][..], &seq].concat()
+}
+
+/// Returns the error code of `f`
+fn call(f: impl FnOnce() -> Result<()>) -> i32 {
+    f().err().unwrap_or(SUCCESS)
+}
+
+fn read(caller: &mut Caller<'_, State>, ptr: i32, len: i32) -> Result<Vec<u8>> {
+    wasmhelper::read(caller, ptr, len).map_err(|_| GUEST_ERROR)
+}
+
+fn write(caller: &mut Caller<'_, State>, ptr: i32, data: &[u8]) -> Result<()> {
+    let len = data.len().try_into().map_err(|_| OVERFLOW)?;
+    match wasmhelper::write(caller, ptr, len, data) {
+        Ok(written) if written == data.len() => Ok(()),
+        _ => Err(GUEST_ERROR),
+    }
+}
+
+fn write_u32(caller: &mut Caller<'_, State>, ptr: i32, value: u32) -> Result<()> {
+    write(caller, ptr, &value.to_le_bytes())
+}
+
+/// Opens a handle to `object` and writes it to `handle_ptr`
+fn open(caller: &mut Caller<'_, State>, object: Object, handle_ptr: i32) -> Result<()> {
+    let handle = caller.data_mut().crypto.insert(object)?;
+    let written = write_u32(caller, handle_ptr, handle);
+    if written.is_err() {
+        caller.data_mut().crypto.objects.remove(&handle);
+    }
+    written
+}
+
+/// Ensures the optional handle at `ptr` is `none`
+fn none(caller: &mut Caller<'_, State>, ptr: i32, err: i32) -> Result<()> {
+    match read(caller, ptr, 1)?.as_slice() {
+        [NONE] => Ok(()),
+        _ => Err(err),
+    }
+}
+
+fn array_output_len(mut caller: Caller<'_, State>, array_output: i32, len_ptr: i32) -> i32 {
+    call(|| {
+        let len = match caller.data_mut().crypto.get(array_output)? {
+            Object::ArrayOutput(data) => data.len(),
+            _ => return Err(INVALID_HANDLE),
+        };
+        write_u32(&mut caller, len_ptr, len.try_into().map_err(|_| OVERFLOW)?)
+    })
+}
+
+fn array_output_pull(
+    mut caller: Caller<'_, State>,
+    array_output: i32,
+    buf_ptr: i32,
+    buf_len: i32,
+    len_ptr: i32,
+) -> i32 {
+    call(|| {
+        let data = match caller.data_mut().crypto.get(array_output)? {
+            Object::ArrayOutput(data) => data.clone(),
+            _ => return Err(INVALID_HANDLE),
+        };
+        if data.len() > usize::try_from(buf_len).map_err(|_| GUEST_ERROR)? {
+            return Err(OVERFLOW);
+        }
+        write(&mut caller, buf_ptr, &data)?;
+        write_u32(&mut caller, len_ptr, data.len() as u32)?;
+        caller
+            .data_mut()
+            .crypto
+            .close(array_output, |o| matches!(o, Object::ArrayOutput(..)))
+            .map(drop)
+    })
+}
+
+fn secrets_manager_open(
+    mut caller: Caller<'_, State>,
+    options_ptr: i32,
+    secrets_manager_ptr: i32,
+) -> i32 {
+    call(|| {
+        none(&mut caller, options_ptr, UNSUPPORTED_OPTION)?;
+        open(&mut caller, Object::SecretsManager, secrets_manager_ptr)
+    })
+}
+
+fn secrets_manager_close(mut caller: Caller<'_, State>, secrets_manager: i32) -> i32 {
+    call(|| {
+        caller
+            .data_mut()
+            .crypto
+            .close(secrets_manager, |o| matches!(o, Object::SecretsManager))
+            .map(drop)
+    })
+}
+
+fn keypair_from_id(
+    mut caller: Caller<'_, State>,
+    secrets_manager: i32,
+    id_ptr: i32,
+    id_len: i32,
+    version: i64,
+    keypair_ptr: i32,
+) -> i32 {
+    call(|| {
+        if !matches!(
+            caller.data_mut().crypto.get(secrets_manager)?,
+            Object::SecretsManager
+        ) {
+            return Err(INVALID_HANDLE);
+        }
+        let id = read(&mut caller, id_ptr, id_len)?;
+        if id != KEEP_KEYPAIR_ID.as_bytes()
+            || ![VERSION_UNSPECIFIED, VERSION_LATEST].contains(&(version as u64))
+        {
+            return Err(NOT_FOUND);
+        }
+        open(&mut caller, Object::Keypair, keypair_ptr)
+    })
+}
+
+fn keypair_publickey(mut caller: Caller<'_, State>, keypair: i32, publickey_ptr: i32) -> i32 {
+    call(|| {
+        if !matches!(caller.data_mut().crypto.get(keypair)?, Object::Keypair) {
+            return Err(INVALID_HANDLE);
+        }
+        open(&mut caller, Object::PublicKey, publickey_ptr)
+    })
+}
+
+fn keypair_close(mut caller: Caller<'_, State>, keypair: i32) -> i32 {
+    call(|| {
+        caller
+            .data_mut()
+            .crypto
+            .close(keypair, |o| matches!(o, Object::Keypair))
+            .map(drop)
+    })
+}
+
+fn publickey_export(
+    mut caller: Caller<'_, State>,
+    publickey: i32,
+    encoding: i32,
+    array_output_ptr: i32,
+) -> i32 {
+    call(|| {
+        let state = caller.data_mut();
+        if !matches!(state.crypto.get(publickey)?, Object::PublicKey) {
+            return Err(INVALID_HANDLE);
+        }
+        let data = match encoding {
+            PUBLICKEY_RAW => state.crypto.public_key().to_vec(),
+            PUBLICKEY_PKCS8 => state.public_key.clone(),
+            _ => return Err(UNSUPPORTED_ENCODING),
+        };
+        open(&mut caller, Object::ArrayOutput(data), array_output_ptr)
+    })
+}
+
+fn publickey_close(mut caller: Caller<'_, State>, publickey: i32) -> i32 {
+    call(|| {
+        caller
+            .data_mut()
+            .crypto
+            .close(publickey, |o| matches!(o, Object::PublicKey))
+            .map(drop)
+    })
+}
+
+fn signature_state_open(mut caller: Caller<'_, State>, keypair: i32, state_ptr: i32) -> i32 {
+    call(|| {
+        if !matches!(caller.data_mut().crypto.get(keypair)?, Object::Keypair) {
+            return Err(INVALID_HANDLE);
+        }
+        open(&mut caller, Object::SignatureState(vec![]), state_ptr)
+    })
+}
+
+fn signature_state_update(
+    mut caller: Caller<'_, State>,
+    state: i32,
+    data_ptr: i32,
+    data_len: i32,
+) -> i32 {
+    call(|| {
+        let data = read(&mut caller, data_ptr, data_len)?;
+        match caller.data_mut().crypto.get(state)? {
+            Object::SignatureState(buf) => buf.extend_from_slice(&data),
+            _ => return Err(INVALID_HANDLE),
+        }
+        Ok(())
+    })
+}
+
+fn signature_state_sign(mut caller: Caller<'_, State>, state: i32, signature_ptr: i32) -> i32 {
+    call(|| {
+        let crypto = &mut caller.data_mut().crypto;
+        let data = match crypto.get(state)? {
+            Object::SignatureState(data) => data.clone(),
+            _ => return Err(INVALID_HANDLE),
+        };
+        let signature = Signature {
+            encoding: SIGNATURE_RAW,
+            data: crypto.sign(&data)?,
+        };
+        open(&mut caller, Object::Signature(signature), signature_ptr)
+    })
+}
+
+fn signature_state_close(mut caller: Caller<'_, State>, state: i32) -> i32 {
+    call(|| {
+        caller
+            .data_mut()
+            .crypto
+            .close(state, |o| matches!(o, Object::SignatureState(..)))
+            .map(drop)
+    })
+}
+
+fn signature_export(
+    mut caller: Caller<'_, State>,
+    signature: i32,
+    encoding: i32,
+    array_output_ptr: i32,
+) -> i32 {
+    call(|| {
+        let crypto = &mut caller.data_mut().crypto;
+        let ecdsa = crypto.algorithm != Algorithm::Ed25519;
+        let data = match crypto.get(signature)? {
+            Object::Signature(sig) if sig.encoding == encoding => sig.data.clone(),
+            Object::Signature(sig)
+                if ecdsa && (sig.encoding, encoding) == (SIGNATURE_RAW, SIGNATURE_DER) =>
+            {
+                der(&sig.data)
+            }
+            Object::Signature(..) => return Err(UNSUPPORTED_ENCODING),
+            _ => return Err(INVALID_HANDLE),
+        };
+        open(&mut caller, Object::ArrayOutput(data), array_output_ptr)
+    })
+}
+
+fn signature_import(
+    mut caller: Caller<'_, State>,
+    algorithm_ptr: i32,
+    algorithm_len: i32,
+    encoded_ptr: i32,
+    encoded_len: i32,
+    encoding: i32,
+    signature_ptr: i32,
+) -> i32 {
+    call(|| {
+        let algorithm = read(&mut caller, algorithm_ptr, algorithm_len)?;
+        let data = read(&mut caller, encoded_ptr, encoded_len)?;
+        let crypto = &caller.data().crypto;
+        if algorithm != crypto.algorithm.name().as_bytes() {
+            return Err(UNSUPPORTED_ALGORITHM);
+        }
+        if crypto.algorithm.verification(encoding).is_none() {
+            return Err(UNSUPPORTED_ENCODING);
+        }
+        let signature = Signature { encoding, data };
+        open(&mut caller, Object::Signature(signature), signature_ptr)
+    })
+}
+
+fn signature_close(mut caller: Caller<'_, State>, signature: i32) -> i32 {
+    call(|| {
+        caller
+            .data_mut()
+            .crypto
+            .close(signature, |o| matches!(o, Object::Signature(..)))
+            .map(drop)
+    })
+}
+
+fn signature_verification_state_open(
+    mut caller: Caller<'_, State>,
+    publickey: i32,
+    state_ptr: i32,
+) -> i32 {
+    call(|| {
+        if !matches!(caller.data_mut().crypto.get(publickey)?, Object::PublicKey) {
+            return Err(INVALID_HANDLE);
+        }
+        open(&mut caller, Object::VerificationState(vec![]), state_ptr)
+    })
+}
+
+fn signature_verification_state_update(
+    mut caller: Caller<'_, State>,
+    state: i32,
+    data_ptr: i32,
+    data_len: i32,
+) -> i32 {
+    call(|| {
+        let data = read(&mut caller, data_ptr, data_len)?;
+        match caller.data_mut().crypto.get(state)? {
+            Object::VerificationState(buf) => buf.extend_from_slice(&data),
+            _ => return Err(INVALID_HANDLE),
+        }
+        Ok(())
+    })
+}
+
+fn signature_verification_state_verify(
+    caller: Caller<'_, State>,
+    state: i32,
+    signature: i32,
+) -> i32 {
+    call(|| {
+        let crypto = &caller.data().crypto;
+        match (
+            crypto.objects.get(&(state as u32)),
+            crypto.objects.get(&(signature as u32)),
+        ) {
+            (Some(Object::VerificationState(data)), Some(Object::Signature(signature))) => {
+                crypto.verify(data, signature)
+            }
+            _ => Err(INVALID_HANDLE),
+        }
+    })
+}
+
+fn signature_verification_state_close(mut caller: Caller<'_, State>, state: i32) -> i32 {
+    call(|| {
+        caller
+            .data_mut()
+            .crypto
+            .close(state, |o| matches!(o, Object::VerificationState(..)))
+            .map(drop)
+    })
+}
+
+fn symmetric_state_open(
+    mut caller: Caller<'_, State>,
+    algorithm_ptr: i32,
+    algorithm_len: i32,
+    key_ptr: i32,
+    options_ptr: i32,
+    state_ptr: i32,
+) -> i32 {
+    call(|| {
+        let algorithm = match &read(&mut caller, algorithm_ptr, algorithm_len)?[..] {
+            b"SHA-256" => &digest::SHA256,
+            b"SHA-384" => &digest::SHA384,
+            b"SHA-512" => &digest::SHA512,
+            b"SHA-512/256" => &digest::SHA512_256,
+            _ => return Err(UNSUPPORTED_ALGORITHM),
+        };
+        none(&mut caller, key_ptr, KEY_NOT_SUPPORTED)?;
+        none(&mut caller, options_ptr, UNSUPPORTED_OPTION)?;
+        let hash = Object::Hash(digest::Context::new(algorithm));
+        open(&mut caller, hash, state_ptr)
+    })
+}
+
+fn symmetric_state_absorb(
+    mut caller: Caller<'_, State>,
+    state: i32,
+    data_ptr: i32,
+    data_len: i32,
+) -> i32 {
+    call(|| {
+        let data = read(&mut caller, data_ptr, data_len)?;
+        match caller.data_mut().crypto.get(state)? {
+            Object::Hash(hash) => hash.update(&data),
+            _ => return Err(INVALID_HANDLE),
+        }
+        Ok(())
+    })
+}
+
+fn symmetric_state_squeeze(
+    mut caller: Caller<'_, State>,
+    state: i32,
+    out_ptr: i32,
+    out_len: i32,
+) -> i32 {
+    call(|| {
+        let digest = match caller.data_mut().crypto.get(state)? {
+            Object::Hash(hash) => hash.clone().finish(),
+            _ => return Err(INVALID_HANDLE),
+        };
+        let len = usize::try_from(out_len).map_err(|_| GUEST_ERROR)?;
+        let out = digest.as_ref().get(..len).ok_or(INVALID_LENGTH)?;
+        write(&mut caller, out_ptr, out)
+    })
+}
+
+fn symmetric_state_close(mut caller: Caller<'_, State>, state: i32) -> i32 {
+    call(|| {
+        caller
+            .data_mut()
+            .crypto
+            .close(state, |o| matches!(o, Object::Hash(..)))
+            .map(drop)
+    })
+}
+
+/// Adds the `wasi_ephemeral_crypto_*` modules to the linker
+pub fn add_to_linker(linker: &mut Linker<State>) -> anyhow::Result<()> {
+    linker
+        .func_wrap(COMMON, "array_output_len", array_output_len)
+        .context("failed to add `array_output_len`")?;
+    linker
+        .func_wrap(COMMON, "array_output_pull", array_output_pull)
+        .context("failed to add `array_output_pull`")?;
+    linker
+        .func_wrap(COMMON, "secrets_manager_open", secrets_manager_open)
+        .context("failed to add `secrets_manager_open`")?;
+    linker
+        .func_wrap(COMMON, "secrets_manager_close", secrets_manager_close)
+        .context("failed to add `secrets_manager_close`")?;
+    linker
+        .func_wrap(ASYMMETRIC_COMMON, "keypair_from_id", keypair_from_id)
+        .context("failed to add `keypair_from_id`")?;
+    linker
+        .func_wrap(ASYMMETRIC_COMMON, "keypair_publickey", keypair_publickey)
+        .context("failed to add `keypair_publickey`")?;
+    linker
+        .func_wrap(ASYMMETRIC_COMMON, "keypair_close", keypair_close)
+        .context("failed to add `keypair_close`")?;
+    linker
+        .func_wrap(ASYMMETRIC_COMMON, "publickey_export", publickey_export)
+        .context("failed to add `publickey_export`")?;
+    linker
+        .func_wrap(ASYMMETRIC_COMMON, "publickey_close", publickey_close)
+        .context("failed to add `publickey_close`")?;
+    linker
+        .func_wrap(SIGNATURES, "signature_state_open", signature_state_open)
+        .context("failed to add `signature_state_open`")?;
+    linker
+        .func_wrap(SIGNATURES, "signature_state_update", signature_state_update)
+        .context("failed to add `signature_state_update`")?;
+    linker
+        .func_wrap(SIGNATURES, "signature_state_sign", signature_state_sign)
+        .context("failed to add `signature_state_sign`")?;
+    linker
+        .func_wrap(SIGNATURES, "signature_state_close", signature_state_close)
+        .context("failed to add `signature_state_close`")?;
+    linker
+        .func_wrap(SIGNATURES, "signature_export", signature_export)
+        .context("failed to add `signature_export`")?;
+    linker
+        .func_wrap(SIGNATURES, "signature_import", signature_import)
+        .context("failed to add `signature_import`")?;
+    linker
+        .func_wrap(SIGNATURES, "signature_close", signature_close)
+        .context("failed to add `signature_close`")?;
+    linker
+        .func_wrap(
+            SIGNATURES,
+            "signature_verification_state_open",
+            signature_verification_state_open,
+        )
+        .context("failed to add `signature_verification_state_open`")?;
+    linker
+        .func_wrap(
+            SIGNATURES,
+            "signature_verification_state_update",
+            signature_verification_state_update,
+        )
+        .context("failed to add `signature_verification_state_update`")?;
+    linker
+        .func_wrap(
+            SIGNATURES,
+            "signature_verification_state_verify",
+            signature_verification_state_verify,
+        )
+        .context("failed to add `signature_verification_state_verify`")?;
+    linker
+        .func_wrap(
+            SIGNATURES,
+            "signature_verification_state_close",
+            signature_verification_state_close,
+        )
+        .context("failed to add `signature_verification_state_close`")?;
+    linker
+        .func_wrap(SYMMETRIC, "symmetric_state_open", symmetric_state_open)
+        .context("failed to add `symmetric_state_open`")?;
+    linker
+        .func_wrap(SYMMETRIC, "symmetric_state_absorb", symmetric_state_absorb)
+        .context("failed to add `symmetric_state_absorb`")?;
+    linker
+        .func_wrap(
+            SYMMETRIC,
+            "symmetric_state_squeeze",
+            symmetric_state_squeeze,
+        )
+        .context("failed to add `symmetric_state_squeeze`")?;
+    linker
+        .func_wrap(SYMMETRIC, "symmetric_state_close", symmetric_state_close)
+        .context("failed to add `symmetric_state_close`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use sha2::{Digest, Sha256};
+
+    const HASH_WAT: &str = r#"(module
+      (import "wasi_ephemeral_crypto_symmetric" "symmetric_state_open"
+        (func $open (param i32 i32 i32 i32 i32) (result i32)))
+      (import "wasi_ephemeral_crypto_symmetric" "symmetric_state_absorb"
+        (func $absorb (param i32 i32 i32) (result i32)))
+      (import "wasi_ephemeral_crypto_symmetric" "symmetric_state_squeeze"
+        (func $squeeze (param i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "SHA-256")
+      (data (i32.const 16) "abc")
+      (data (i32.const 32) "\01")
+      (data (i32.const 40) "MD5")
+      (func (export "") (result i32 i32 i32 i32 i32 i32)
+        (call $open (i32.const 0) (i32.const 7) (i32.const 32) (i32.const 32) (i32.const 64))
+        (call $absorb (i32.load (i32.const 64)) (i32.const 16) (i32.const 3))
+        (call $squeeze (i32.load (i32.const 64)) (i32.const 128) (i32.const 32))
+        (i32.load (i32.const 128))
+        (call $squeeze (i32.load (i32.const 64)) (i32.const 128) (i32.const 33))
+        (call $open (i32.const 40) (i32.const 3) (i32.const 32) (i32.const 32) (i32.const 64))
+      )
+    )"#;
+
+    const SIGNATURE_WAT: &str = r#"(module
+      (import "wasi_ephemeral_crypto_common" "secrets_manager_open"
+        (func $secrets_manager_open (param i32 i32) (result i32)))
+      (import "wasi_ephemeral_crypto_asymmetric_common" "keypair_from_id"
+        (func $keypair_from_id (param i32 i32 i32 i64 i32) (result i32)))
+      (import "wasi_ephemeral_crypto_asymmetric_common" "keypair_publickey"
+        (func $keypair_publickey (param i32 i32) (result i32)))
+      (import "wasi_ephemeral_crypto_signatures" "signature_state_open"
+        (func $sign_open (param i32 i32) (result i32)))
+      (import "wasi_ephemeral_crypto_signatures" "signature_state_update"
+        (func $sign_update (param i32 i32 i32) (result i32)))
+      (import "wasi_ephemeral_crypto_signatures" "signature_state_sign"
+        (func $sign (param i32 i32) (result i32)))
+      (import "wasi_ephemeral_crypto_signatures" "signature_verification_state_open"
+        (func $verify_open (param i32 i32) (result i32)))
+      (import "wasi_ephemeral_crypto_signatures" "signature_verification_state_update"
+        (func $verify_update (param i32 i32 i32) (result i32)))
+      (import "wasi_ephemeral_crypto_signatures" "signature_verification_state_verify"
+        (func $verify (param i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "keep")
+      (data (i32.const 16) "abc")
+      (data (i32.const 32) "\01")
+      (func (export "") (result i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32)
+        (call $secrets_manager_open (i32.const 32) (i32.const 64))
+        (call $keypair_from_id
+          (i32.load (i32.const 64)) (i32.const 0) (i32.const 4)
+          (i64.const 0xff00000000000001) (i32.const 68))
+        (call $keypair_from_id
+          (i32.load (i32.const 64)) (i32.const 16) (i32.const 3)
+          (i64.const 0xff00000000000001) (i32.const 68))
+        (call $sign_open (i32.load (i32.const 68)) (i32.const 72))
+        (call $sign_update (i32.load (i32.const 72)) (i32.const 16) (i32.const 3))
+        (call $sign (i32.load (i32.const 72)) (i32.const 76))
+        (call $keypair_publickey (i32.load (i32.const 68)) (i32.const 80))
+        (call $verify_open (i32.load (i32.const 80)) (i32.const 84))
+        (call $verify_update (i32.load (i32.const 84)) (i32.const 16) (i32.const 3))
+        (call $verify (i32.load (i32.const 84)) (i32.load (i32.const 76)))
+        (call $verify_update (i32.load (i32.const 84)) (i32.const 16) (i32.const 3))
+        (call $verify (i32.load (i32.const 84)) (i32.load (i32.const 76)))
+      )
+    )"#;
+
+    #[test]
+    fn der_signature() {
+        let rng = SystemRandom::new();
+        for alg in [
+            &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+            &signature::ECDSA_P384_SHA384_FIXED_SIGNING,
+        ] {
+            let key = EcdsaKeyPair::generate_pkcs8(alg, &rng).unwrap();
+            let crypto = Crypto::new(key.as_ref()).unwrap();
+            let raw = crypto.sign(b"data").unwrap();
+            let signature = Signature {
+                encoding: SIGNATURE_DER,
+                data: der(&raw),
+            };
+            crypto.verify(b"data", &signature).unwrap();
+        }
+
+        assert_eq!(
+            der(&[[0; 4], [0, 0x80, 0, 1]].concat()),
+            [0x30, 0x09, 0x02, 0x01, 0x00, 0x02, 0x04, 0x00, 0x80, 0x00, 0x01]
+        );
+    }
+
+    #[test]
+    fn guest_hash() {
+        let bytes = wat::parse_str(HASH_WAT).expect("error parsing wat");
+        let results: Vec<i32> = crate::test::run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        let digest = Sha256::digest(b"abc");
+        let prefix = i32::from_le_bytes(digest[..4].try_into().unwrap());
+        assert_eq!(
+            results,
+            vec![0, 0, 0, prefix, INVALID_LENGTH, UNSUPPORTED_ALGORITHM]
+        );
+    }
+
+    #[test]
+    fn guest_signature() {
+        let bytes = wat::parse_str(SIGNATURE_WAT).expect("error parsing wat");
+        let results: Vec<i32> = crate::test::run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+
+        // The signature of `abc` by the keep key does not match `abcabc`.
+        assert_eq!(
+            results,
+            vec![0, 0, NOT_FOUND, 0, 0, 0, 0, 0, 0, 0, 0, VERIFICATION_FAILED]
+        );
+    }
+}
@@ -2,29 +2,41 @@
 
 //! Functionality for establishing keep identity.
 
+mod pin;
 mod pki;
-mod platform;
+pub mod platform;
+mod seal;
 
-use pki::PrivateKeyInfoExt;
+pub use pin::Pin;
+pub use seal::unseal;
+
+use pki::{PrivateKeyInfoExt, ED25519};
 use platform::{Platform, Technology};
 
-use std::time::Duration;
+#[cfg(test)]
+use std::cell::RefCell;
+use std::net::IpAddr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use const_oid::db::rfc5280::{
-    ID_CE_BASIC_CONSTRAINTS, ID_CE_EXT_KEY_USAGE, ID_CE_KEY_USAGE, ID_KP_CLIENT_AUTH,
-    ID_KP_SERVER_AUTH,
+    ID_CE_BASIC_CONSTRAINTS, ID_CE_EXT_KEY_USAGE, ID_CE_KEY_USAGE, ID_CE_SUBJECT_ALT_NAME,
+    ID_KP_CLIENT_AUTH, ID_KP_SERVER_AUTH,
 };
 use const_oid::db::rfc5912::{SECP_256_R_1, SECP_384_R_1};
-use const_oid::AssociatedOid;
+use const_oid::{AssociatedOid, ObjectIdentifier};
+use enarx_config::{KeyAlgorithm, SubjectAltName};
 use getrandom::getrandom;
 use pkcs8::PrivateKeyInfo;
+use rustls::RootCertStore;
 use sha2::{Digest, Sha256, Sha384};
 use url::Url;
 use x509_cert::attr::Attribute;
-use x509_cert::der::asn1::{BitStringRef, UIntRef};
+use x509_cert::der::asn1::{BitStringRef, Ia5StringRef, OctetStringRef, UIntRef};
 use x509_cert::der::{AnyRef, Decode, Encode};
-use x509_cert::ext::pkix::{BasicConstraints, ExtendedKeyUsage, KeyUsage, KeyUsages};
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::pkix::{self, BasicConstraints, ExtendedKeyUsage, KeyUsage, KeyUsages};
 use x509_cert::ext::Extension;
 use x509_cert::name::RdnSequence;
 use x509_cert::request::{CertReq, CertReqInfo, ExtensionReq};
@@ -32,7 +44,61 @@ use x509_cert::time::Validity;
 use x509_cert::{Certificate, PkiPath, TbsCertificate};
 use zeroize::Zeroizing;
 
-fn csr(pki: &PrivateKeyInfo<'_>, exts: Vec<Extension<'_>>) -> anyhow::Result<Vec<u8>> {
+/// Default subject of self-signed certificates
+const SELFSIGNED_SUBJECT: &str = "CN=localhost";
+
+/// Default validity period of self-signed certificates
+pub const DEFAULT_SELFSIGNED_VALIDITY: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Response header of the Steward carrying a base64-encoded DER OCSP response for the issued
+/// certificate, which is stapled to the TLS handshakes of listeners
+const OCSP_RESPONSE_HEADER: &str = "X-Ocsp-Response";
+
+/// Certificate chain issued by the Steward
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Issued {
+    /// DER-encoded certificates, starting with the leaf certificate
+    pub certs: Vec<Vec<u8>>,
+
+    /// DER-encoded OCSP response for the leaf certificate provided by the Steward, if any
+    pub ocsp: Option<Vec<u8>>,
+}
+
+/// Returns the DER-encoded distinguished name, which is empty if none is given
+fn subject(name: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    match name {
+        Some(name) => RdnSequence::encode_from_string(name)
+            .with_context(|| format!("invalid subject `{name}`")),
+        None => Ok(RdnSequence::default().to_vec()?),
+    }
+}
+
+/// Returns the DER-encoded value of the subject alternative name extension
+fn subject_alt_name(san: &[SubjectAltName]) -> anyhow::Result<Vec<u8>> {
+    let ips = san
+        .iter()
+        .map(|name| match name {
+            SubjectAltName::Dns(..) => vec![],
+            SubjectAltName::Ip(IpAddr::V4(ip)) => ip.octets().to_vec(),
+            SubjectAltName::Ip(IpAddr::V6(ip)) => ip.octets().to_vec(),
+        })
+        .collect::<Vec<_>>();
+    let names = san
+        .iter()
+        .zip(&ips)
+        .map(|(name, ip)| match name {
+            SubjectAltName::Dns(dns) => Ok(GeneralName::DnsName(Ia5StringRef::new(dns)?)),
+            SubjectAltName::Ip(..) => Ok(GeneralName::IpAddress(OctetStringRef::new(ip)?)),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(pkix::SubjectAltName(names).to_vec()?)
+}
+
+fn csr(
+    pki: &PrivateKeyInfo<'_>,
+    subject: RdnSequence<'_>,
+    exts: Vec<Extension<'_>>,
+) -> anyhow::Result<Vec<u8>> {
     // Request the extensions.
     let req = ExtensionReq::from(exts).to_vec()?;
 
@@ -47,7 +113,7 @@ fn csr(pki: &PrivateKeyInfo<'_>, exts: Vec<Extension<'_>>) -> anyhow::Result<Vec
     let cri = CertReqInfo {
         version: x509_cert::request::Version::V1,
         attributes: vec![att].try_into()?,
-        subject: RdnSequence::default(),
+        subject,
         public_key: pki.public_key()?,
     };
 
@@ -62,14 +128,40 @@ fn csr(pki: &PrivateKeyInfo<'_>, exts: Vec<Extension<'_>>) -> anyhow::Result<Vec
     Ok(req.to_vec()?)
 }
 
-/// Generates a new private key and corresponding CSR
-pub fn generate() -> anyhow::Result<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+/// Returns the OID of the key algorithm passed to [`PrivateKeyInfo::generate`]
+///
+/// If no algorithm is given, the default of the platform is chosen.
+fn key_oid(algorithm: Option<KeyAlgorithm>, technology: Technology) -> ObjectIdentifier {
+    match (algorithm, technology) {
+        (Some(KeyAlgorithm::P256), _) => SECP_256_R_1,
+        (Some(KeyAlgorithm::P384), _) => SECP_384_R_1,
+        (Some(KeyAlgorithm::Ed25519), _) => ED25519,
+        (None, Technology::Snp) => SECP_384_R_1,
+        (None, Technology::Sgx) => SECP_256_R_1,
+        (None, Technology::Kvm) => SECP_256_R_1,
+    }
+}
+
+/// Ensures that the Steward URLs can issue certificates for keys of the algorithm
+pub fn check_key_algorithm(algorithm: Option<KeyAlgorithm>, steward: &[Url]) -> anyhow::Result<()> {
+    match algorithm {
+        Some(KeyAlgorithm::Ed25519) if !steward.is_empty() => {
+            bail!("the Steward does not issue certificates for Ed25519 keys")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Generates a new private key of the algorithm and corresponding CSR
+///
+/// The CSR requests the subject and subject alternative names, if given.
+pub fn generate(
+    algorithm: Option<KeyAlgorithm>,
+    subject_name: Option<&str>,
+    san: &[SubjectAltName],
+) -> anyhow::Result<(Zeroizing<Vec<u8>>, Vec<u8>)> {
     let platform = Platform::get()?;
-    let cert_algo = match platform.technology() {
-        Technology::Snp => SECP_384_R_1,
-        Technology::Sgx => SECP_256_R_1,
-        Technology::Kvm => SECP_256_R_1,
-    };
+    let cert_algo = key_oid(algorithm, platform.technology());
 
     // Generate a keypair.
     let raw = PrivateKeyInfo::generate(cert_algo)?;
@@ -91,27 +183,234 @@ pub fn generate() -> anyhow::Result<(Zeroizing<Vec<u8>>, Vec<u8>)> {
     let attestation_report = platform.attest(&key_hash)?;
 
     // Create extensions.
-    let ext = vec![Extension {
+    let mut ext = vec![Extension {
         extn_id: platform.technology().into(),
         critical: false,
         extn_value: &attestation_report,
     }];
+    let rdns = subject(subject_name)?;
+    let alt_names = subject_alt_name(san)?;
+    if !san.is_empty() {
+        ext.push(Extension {
+            extn_id: ID_CE_SUBJECT_ALT_NAME,
+            // The extension must be critical, if the subject is empty.
+            critical: subject_name.is_none(),
+            extn_value: &alt_names,
+        });
+    }
 
     // Make a certificate signing request.
-    let req = csr(&pki, ext)?;
+    let req = csr(&pki, RdnSequence::from_der(&rdns)?, ext)?;
 
     Ok((raw, req))
 }
 
-pub fn steward(url: &Url, csr: impl AsRef<[u8]>) -> anyhow::Result<Vec<Vec<u8>>> {
+#[cfg(test)]
+thread_local! {
+    /// Trust anchors of the Steward mocked for the current thread by [mock_steward]
+    static MOCK_ROOTS: RefCell<Option<RootCertStore>> = RefCell::new(None);
+}
+
+/// Returns the trust anchors of the Steward, which are those of the Web PKI, unless mocked
+fn steward_roots() -> RootCertStore {
+    #[cfg(test)]
+    if let Some(roots) = MOCK_ROOTS.with(|mock| mock.borrow().clone()) {
+        return roots;
+    }
+    pin::webpki_roots()
+}
+
+/// Serves `body` to a single HTTPS request, presenting `certs` with the private key `key`
+///
+/// `certs` must be valid for `localhost`. Returns the URL of the server.
+#[cfg(test)]
+pub fn mock_https(certs: &[Vec<u8>], key: &[u8], content_type: &'static str, body: Vec<u8>) -> Url {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::sync::Arc;
+
+    use rustls::{PrivateKey, ServerConfig, ServerConnection, StreamOwned};
+
+    let cfg = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            certs.iter().cloned().map(rustls::Certificate).collect(),
+            PrivateKey(key.to_vec()),
+        )
+        .unwrap();
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || -> std::io::Result<()> {
+        let (tcp, _) = listener.accept()?;
+        let tls = ServerConnection::new(Arc::new(cfg)).unwrap();
+        let mut stream = BufReader::new(StreamOwned::new(tls, tcp));
+
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line)?;
+            let line = line.trim_end().to_ascii_lowercase();
+            if line.is_empty() {
+                break;
+            } else if let Some(value) = line.strip_prefix("content-length:") {
+                len = value.trim().parse().unwrap();
+            }
+        }
+        stream.read_exact(&mut vec![0; len])?;
+
+        let stream = stream.get_mut();
+        write!(stream, "HTTP/1.1 200 OK\r\n")?;
+        write!(stream, "Content-Type: {content_type}\r\n")?;
+        write!(stream, "Content-Length: {}\r\n\r\n", body.len())?;
+        stream.write_all(&body)?;
+        stream.flush()
+    });
+    format!("https://localhost:{port}/").parse().unwrap()
+}
+
+/// Mocks a Steward for the current thread, which issues the certificate chain `certs`
+///
+/// The Steward is served by [mock_https] and its certificates are trusted by [steward]
+/// on the current thread. Returns the URL of the Steward.
+#[cfg(test)]
+pub fn mock_steward(certs: &[Vec<u8>], key: &[u8]) -> Url {
+    let path = certs
+        .iter()
+        .rev()
+        .map(|cert| Certificate::from_der(cert).unwrap())
+        .collect::<PkiPath>()
+        .to_vec()
+        .unwrap();
+
+    let mut roots = RootCertStore::empty();
+    for cert in certs {
+        roots.add(&rustls::Certificate(cert.clone())).unwrap();
+    }
+    MOCK_ROOTS.with(|mock| mock.replace(Some(roots)));
+
+    mock_https(certs, key, "application/pkix-cert", path)
+}
+
+/// Initial delay between attempts to attest to a Steward URL
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Maximum delay between attempts to attest to a Steward URL
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Policy for retrying failed attestations to a Steward URL
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+    /// Number of retries after the first attempt failed
+    pub retries: u32,
+
+    /// Maximum total time spent attesting to all Steward URLs
+    pub deadline: Option<Duration>,
+
+    /// Delay before the first retry, which doubles after every retry
+    pub initial_backoff: Duration,
+
+    /// Maximum delay between retries
+    pub max_backoff: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            deadline: None,
+            initial_backoff: INITIAL_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+        }
+    }
+}
+
+/// Attests to the Steward URLs in order, until one of them issues a certificate chain
+///
+/// Failed attempts are retried with exponential backoff according to `retry`.
+/// The errors of all Steward URLs are reported, if none of them issues a certificate chain.
+/// If `pins` are given, a certificate of the chain presented by the Steward must match one of them.
+pub fn steward(
+    urls: &[Url],
+    csr: impl AsRef<[u8]>,
+    retry: Retry,
+    pins: &[Pin],
+) -> anyhow::Result<Issued> {
+    let agent = ureq::AgentBuilder::new()
+        .tls_config(pin::client_config(steward_roots(), pins.to_vec()))
+        .build();
+    let deadline = retry.deadline.map(|deadline| Instant::now() + deadline);
+    let mut errors = vec![];
+    for url in urls {
+        let mut backoff = retry.initial_backoff.min(retry.max_backoff);
+        let mut attempts = 0;
+        let error = loop {
+            attempts += 1;
+            let e = match attest(url, csr.as_ref(), &agent) {
+                Ok(issued) => {
+                    tracing::info!("attested to Steward `{url}`");
+                    return Ok(issued);
+                }
+                Err(e) => e,
+            };
+            tracing::warn!("attempt {attempts} to attest to Steward `{url}` failed: {e:#}");
+
+            // Do not retry, if the backoff would exceed the deadline.
+            let expired = deadline.map_or(false, |deadline| Instant::now() + backoff > deadline);
+            if attempts > retry.retries || expired {
+                break e;
+            }
+            thread::sleep(backoff);
+            backoff = backoff.saturating_mul(2).min(retry.max_backoff);
+        };
+        errors.push(format!(
+            "`{url}` failed after {attempts} attempt(s): {error:#}"
+        ));
+
+        if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            if errors.len() < urls.len() {
+                errors.push("the deadline expired before trying the remaining URLs".into());
+            }
+            break;
+        }
+    }
+    if errors.is_empty() {
+        bail!("no Steward URL specified");
+    }
+    bail!(
+        "failed to attest to any Steward URL:\n{}",
+        errors.join("\n")
+    )
+}
+
+/// Shuffles the Steward URLs to spread the load across them
+pub fn shuffle(urls: &mut [Url]) -> anyhow::Result<()> {
+    for i in (1..urls.len()).rev() {
+        let mut rand = [0u8; 8];
+        getrandom(&mut rand)?;
+        let j = u64::from_ne_bytes(rand) % (i as u64 + 1);
+        urls.swap(i, j as usize);
+    }
+    Ok(())
+}
+
+fn attest(url: &Url, csr: &[u8], agent: &ureq::Agent) -> anyhow::Result<Issued> {
     if url.scheme() != "https" {
         bail!("refusing to use an unencrypted steward url");
     }
 
     // Send the attestation to the steward.
-    let response = ureq::post(url.as_str())
+    let response = agent
+        .post(url.as_str())
         .set("Content-Type", "application/pkcs10")
-        .send_bytes(csr.as_ref())?;
+        .send_bytes(csr)?;
+
+    let ocsp = response
+        .header(OCSP_RESPONSE_HEADER)
+        .map(base64::decode)
+        .transpose()
+        .context("Steward returned an invalid OCSP response")?;
 
     // Read the result.
     let mut body = Vec::new();
@@ -119,18 +418,45 @@ pub fn steward(url: &Url, csr: impl AsRef<[u8]>) -> anyhow::Result<Vec<Vec<u8>>>
 
     // Decode the certificate chain.
     let path = PkiPath::from_der(&body)?;
-    path.iter().rev().map(|c| Ok(c.to_vec()?)).collect()
+    let certs = path
+        .iter()
+        .rev()
+        .map(|c| Ok(c.to_vec()?))
+        .collect::<anyhow::Result<_>>()?;
+    Ok(Issued { certs, ocsp })
+}
+
+/// Returns the DER-encoded `SubjectPublicKeyInfo` of the DER-encoded private key
+pub fn public_key(key: impl AsRef<[u8]>) -> anyhow::Result<Vec<u8>> {
+    let pki = PrivateKeyInfo::from_der(key.as_ref())?;
+    Ok(pki.public_key()?.to_vec()?)
+}
+
+/// Returns the expiry time of the DER-encoded certificate
+pub fn expiry(cert: impl AsRef<[u8]>) -> anyhow::Result<SystemTime> {
+    let cert = Certificate::from_der(cert.as_ref())?;
+    Ok(cert.tbs_certificate.validity.not_after.to_system_time())
 }
 
-pub fn selfsigned(key: impl AsRef<[u8]>) -> anyhow::Result<Vec<Vec<u8>>> {
+/// Generates a self-signed certificate chain for the DER-encoded private key
+///
+/// The subject defaults to `CN=localhost`, if none is given.
+/// The certificate is valid from now on for the given period.
+pub fn selfsigned(
+    key: impl AsRef<[u8]>,
+    subject_name: Option<&str>,
+    san: &[SubjectAltName],
+    validity: Duration,
+) -> anyhow::Result<Vec<Vec<u8>>> {
     let pki = PrivateKeyInfo::from_der(key.as_ref())?;
 
     // Create a relative distinguished name.
-    let rdns = RdnSequence::encode_from_string("CN=localhost")?;
+    let rdns = subject(Some(subject_name.unwrap_or(SELFSIGNED_SUBJECT)))?;
 
     // Create the extensions.
     let ku = KeyUsage(KeyUsages::DigitalSignature | KeyUsages::KeyEncipherment).to_vec()?;
     let eu = ExtendedKeyUsage(vec![ID_KP_SERVER_AUTH, ID_KP_CLIENT_AUTH]).to_vec()?;
+    let alt_names = subject_alt_name(san)?;
     let bc = BasicConstraints {
         ca: false,
         path_len_constraint: None,
@@ -142,34 +468,43 @@ pub fn selfsigned(key: impl AsRef<[u8]>) -> anyhow::Result<Vec<Vec<u8>>> {
     let mut serial = [0u8; 16];
     getrandom(&mut serial)?;
 
+    let mut extensions = vec![
+        x509_cert::ext::Extension {
+            extn_id: ID_CE_KEY_USAGE,
+            critical: true,
+            extn_value: &ku,
+        },
+        x509_cert::ext::Extension {
+            extn_id: ID_CE_BASIC_CONSTRAINTS,
+            critical: true,
+            extn_value: &bc,
+        },
+        x509_cert::ext::Extension {
+            extn_id: ID_CE_EXT_KEY_USAGE,
+            critical: false,
+            extn_value: &eu,
+        },
+    ];
+    if !san.is_empty() {
+        extensions.push(x509_cert::ext::Extension {
+            extn_id: ID_CE_SUBJECT_ALT_NAME,
+            critical: false,
+            extn_value: &alt_names,
+        });
+    }
+
     // Create the certificate body.
     let tbs = TbsCertificate {
         version: x509_cert::Version::V3,
         serial_number: UIntRef::new(&serial)?,
         signature: pki.signs_with()?,
         issuer: RdnSequence::from_der(&rdns)?,
-        validity: Validity::from_now(Duration::from_secs(60 * 60 * 24 * 365))?,
+        validity: Validity::from_now(validity)?,
         subject: RdnSequence::from_der(&rdns)?,
         subject_public_key_info: pki.public_key()?,
         issuer_unique_id: None,
         subject_unique_id: None,
-        extensions: Some(vec![
-            x509_cert::ext::Extension {
-                extn_id: ID_CE_KEY_USAGE,
-                critical: true,
-                extn_value: &ku,
-            },
-            x509_cert::ext::Extension {
-                extn_id: ID_CE_BASIC_CONSTRAINTS,
-                critical: true,
-                extn_value: &bc,
-            },
-            x509_cert::ext::Extension {
-                extn_id: ID_CE_EXT_KEY_USAGE,
-                critical: false,
-                extn_value: &eu,
-            },
-        ]),
+        extensions: Some(extensions),
     };
 
     // Self-sign the certificate.
@@ -183,3 +518,120 @@ pub fn selfsigned(key: impl AsRef<[u8]>) -> anyhow::Result<Vec<Vec<u8>>> {
 
     Ok(vec![crt.to_vec()?])
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_algorithms() {
+        for algorithm in [
+            KeyAlgorithm::P256,
+            KeyAlgorithm::P384,
+            KeyAlgorithm::Ed25519,
+        ] {
+            Platform::mock(vec![0xaa; 16]);
+            let (key, req) = generate(Some(algorithm), None, &[]).unwrap();
+            CertReq::from_der(&req).unwrap();
+
+            let certs = selfsigned(&key, None, &[], DEFAULT_SELFSIGNED_VALIDITY).unwrap();
+            let cert = Certificate::from_der(&certs[0]).unwrap();
+            assert_eq!(
+                cert.tbs_certificate
+                    .subject_public_key_info
+                    .to_vec()
+                    .unwrap(),
+                public_key(&key).unwrap()
+            );
+            rustls::sign::any_supported_type(&rustls::PrivateKey(key.to_vec())).unwrap();
+        }
+    }
+
+    #[test]
+    fn steward_retries() {
+        // Nothing listens on the port, so every attempt fails right away.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "https://localhost:{}/",
+            listener.local_addr().unwrap().port()
+        );
+        drop(listener);
+
+        let retry = Retry {
+            retries: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let err = steward(&[url.parse().unwrap()], b"", retry, &[]).unwrap_err();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        let err = err.to_string();
+        assert!(
+            err.contains(&format!("`{url}` failed after 4 attempt(s)")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn subject_alt_names() {
+        let san = [
+            SubjectAltName::try_from("example.com").unwrap(),
+            SubjectAltName::try_from("192.0.2.1").unwrap(),
+        ];
+
+        Platform::mock(vec![0xaa; 16]);
+        let (key, req) = generate(None, Some("CN=example.com"), &san).unwrap();
+        let req = CertReq::from_der(&req).unwrap();
+        assert_eq!(req.info.subject.to_string(), "CN=example.com");
+
+        let certs = selfsigned(&key, None, &san, DEFAULT_SELFSIGNED_VALIDITY).unwrap();
+        let cert = Certificate::from_der(&certs[0]).unwrap();
+        assert_eq!(cert.tbs_certificate.subject.to_string(), "CN=localhost");
+        let ext = cert
+            .tbs_certificate
+            .extensions
+            .unwrap()
+            .into_iter()
+            .find(|ext| ext.extn_id == ID_CE_SUBJECT_ALT_NAME)
+            .unwrap();
+        let pkix::SubjectAltName(names) = pkix::SubjectAltName::from_der(ext.extn_value).unwrap();
+        assert_eq!(
+            names,
+            vec![
+                GeneralName::DnsName(Ia5StringRef::new("example.com").unwrap()),
+                GeneralName::IpAddress(OctetStringRef::new(&[192, 0, 2, 1]).unwrap()),
+            ]
+        );
+
+        assert!(selfsigned(&key, Some("not a name"), &[], DEFAULT_SELFSIGNED_VALIDITY).is_err());
+    }
+
+    #[test]
+    fn selfsigned_validity() {
+        Platform::mock(vec![0xaa; 16]);
+        let (key, _) = generate(None, None, &[]).unwrap();
+        for validity in [Duration::from_secs(60), DEFAULT_SELFSIGNED_VALIDITY] {
+            let certs = selfsigned(&key, None, &[], validity).unwrap();
+            let remaining = expiry(&certs[0])
+                .unwrap()
+                .duration_since(SystemTime::now())
+                .unwrap();
+            // The expiry is encoded with a precision of seconds.
+            assert!(remaining <= validity, "{remaining:?}");
+            assert!(
+                remaining + Duration::from_secs(5) > validity,
+                "{remaining:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn steward_key_algorithm() {
+        let steward = ["https://attest.profian.com".parse().unwrap()];
+        assert!(check_key_algorithm(Some(KeyAlgorithm::Ed25519), &[]).is_ok());
+        assert!(check_key_algorithm(Some(KeyAlgorithm::Ed25519), &steward).is_err());
+        assert!(check_key_algorithm(Some(KeyAlgorithm::P384), &steward).is_ok());
+        assert!(check_key_algorithm(None, &steward).is_ok());
+    }
+}
@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pinning of the TLS certificate of the Steward
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context};
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+use sha2::{Digest, Sha256};
+use x509_cert::der::{Decode, Encode};
+
+/// SHA-256 digest of the DER-encoded `SubjectPublicKeyInfo` of a certificate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pin([u8; 32]);
+
+impl FromStr for Pin {
+    type Err = anyhow::Error;
+
+    fn from_str(pin: &str) -> anyhow::Result<Self> {
+        let digest =
+            base64::decode(pin).with_context(|| format!("pin `{pin}` is not valid base64"))?;
+        let digest = digest
+            .try_into()
+            .map_err(|_| anyhow!("pin `{pin}` is not a SHA-256 digest"))?;
+        Ok(Self(digest))
+    }
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", base64::encode(self.0))
+    }
+}
+
+impl Pin {
+    /// Computes the pin of the DER-encoded certificate
+    pub fn of(cert: impl AsRef<[u8]>) -> anyhow::Result<Self> {
+        let cert = x509_cert::Certificate::from_der(cert.as_ref())?;
+        let spki = cert.tbs_certificate.subject_public_key_info.to_vec()?;
+        Ok(Self(Sha256::digest(spki).into()))
+    }
+}
+
+/// Joins the pins for error messages
+fn join(pins: &[Pin]) -> String {
+    pins.iter()
+        .map(Pin::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Verifier requiring a certificate of the chain presented by the server to match one of the pins
+///
+/// The chain is validated against the trust anchors first. Without pins, any valid chain is
+/// accepted.
+struct PinnedVerifier {
+    inner: WebPkiVerifier,
+    pins: Vec<Pin>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        if self.pins.is_empty() {
+            return Ok(verified);
+        }
+        let observed = [end_entity]
+            .into_iter()
+            .chain(intermediates)
+            .map(|cert| Pin::of(&cert.0))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(|_| rustls::Error::InvalidCertificateEncoding)?;
+        if observed.iter().any(|pin| self.pins.contains(pin)) {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::General(format!(
+                "Steward certificate pin mismatch: observed [{}], expected one of [{}]",
+                join(&observed),
+                join(&self.pins)
+            )))
+        }
+    }
+}
+
+/// Returns the trust anchors of the Web PKI
+pub fn webpki_roots() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+    roots
+}
+
+/// Returns a TLS client config trusting `roots`, which requires a certificate matching any `pins`
+pub fn client_config(roots: RootCertStore, pins: Vec<Pin>) -> Arc<ClientConfig> {
+    let verifier = PinnedVerifier {
+        inner: WebPkiVerifier::new(roots, None),
+        pins,
+    };
+    let cfg = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    Arc::new(cfg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::platform::Platform;
+    use super::super::{attest, generate, mock_steward, selfsigned, DEFAULT_SELFSIGNED_VALIDITY};
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let pin: Pin = "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+            .parse()
+            .unwrap();
+        assert_eq!(pin.0[..], Sha256::digest(b"")[..]);
+        assert_eq!(
+            pin.to_string(),
+            "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+        );
+
+        assert!("not base64!".parse::<Pin>().is_err());
+        assert!("AAAA".parse::<Pin>().is_err());
+    }
+
+    #[test]
+    fn pinned_steward() {
+        Platform::mock(vec![0xaa; 16]);
+        let san = ["localhost".try_into().unwrap()];
+        let (key, csr) = generate(None, None, &san).unwrap();
+        let certs = selfsigned(&key, None, &san, DEFAULT_SELFSIGNED_VALIDITY).unwrap();
+        let pin = Pin::of(&certs[0]).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(&Certificate(certs[0].clone())).unwrap();
+        let agent = |pins| {
+            ureq::AgentBuilder::new()
+                .tls_config(client_config(roots.clone(), pins))
+                .build()
+        };
+
+        let url = mock_steward(&certs, &key);
+        assert_eq!(attest(&url, &csr, &agent(vec![pin])).unwrap().certs, certs);
+
+        let other = Pin([0; 32]);
+        let url = mock_steward(&certs, &key);
+        let err = attest(&url, &csr, &agent(vec![other])).unwrap_err();
+        let err = format!("{err:#}");
+        assert!(
+            err.contains(&format!("observed [{pin}], expected one of [{other}]")),
+            "{err}"
+        );
+    }
+}
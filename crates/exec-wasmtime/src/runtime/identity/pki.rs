@@ -14,6 +14,9 @@ use const_oid::db::rfc5912::{
     SECP_384_R_1 as P384,
 };
 
+/// Object identifier of Ed25519 keys and signatures as defined in RFC 8410
+pub const ED25519: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
 const ES256: AlgorithmIdentifier<'static> = AlgorithmIdentifier {
     oid: ECDSA_WITH_SHA_256,
     parameters: None,
@@ -24,6 +27,11 @@ const ES384: AlgorithmIdentifier<'static> = AlgorithmIdentifier {
     parameters: None,
 };
 
+const EDDSA: AlgorithmIdentifier<'static> = AlgorithmIdentifier {
+    oid: ED25519,
+    parameters: None,
+};
+
 pub trait PrivateKeyInfoExt {
     /// Generates a keypair
     ///
@@ -63,6 +71,8 @@ impl<'a> PrivateKeyInfoExt for PrivateKeyInfo<'a> {
                 EcdsaKeyPair::generate_pkcs8(&ALG, &rand)?
             }
 
+            ED25519 => ring::signature::Ed25519KeyPair::generate_pkcs8(&rand)?,
+
             _ => return Err(anyhow!("unsupported")),
         };
 
@@ -79,6 +89,15 @@ impl<'a> PrivateKeyInfoExt for PrivateKeyInfo<'a> {
                     subject_public_key: pk,
                 })
             }
+            (ED25519, None) => {
+                let pk = self
+                    .public_key
+                    .ok_or_else(|| anyhow!("missing public key"))?;
+                Ok(SubjectPublicKeyInfo {
+                    algorithm: self.algorithm,
+                    subject_public_key: pk,
+                })
+            }
             _ => Err(anyhow!("unsupported")),
         }
     }
@@ -87,6 +106,7 @@ impl<'a> PrivateKeyInfoExt for PrivateKeyInfo<'a> {
         match self.algorithm.oids()? {
             (ECPK, Some(P256)) => Ok(ES256),
             (ECPK, Some(P384)) => Ok(ES384),
+            (ED25519, None) => Ok(EDDSA),
             _ => Err(anyhow!("unsupported")),
         }
     }
@@ -106,6 +126,12 @@ impl<'a> PrivateKeyInfoExt for PrivateKeyInfo<'a> {
                 Ok(kp.sign(&rng, body)?.as_ref().to_vec())
             }
 
+            ((ED25519, None), EDDSA) => {
+                use ring::signature::Ed25519KeyPair;
+                let kp = Ed25519KeyPair::from_pkcs8(&self.to_vec()?)?;
+                Ok(kp.sign(body).as_ref().to_vec())
+            }
+
             _ => Err(anyhow!("unsupported")),
         }
     }
@@ -2,6 +2,8 @@
 
 //! Platform-specific functionality.
 
+#[cfg(test)]
+use std::cell::{Cell, RefCell};
 use std::io::{ErrorKind, Result};
 
 use const_oid::ObjectIdentifier;
@@ -29,11 +31,22 @@ impl From<Technology> for ObjectIdentifier {
     }
 }
 
+#[cfg(test)]
+thread_local! {
+    /// Report of the platform mocked for the current thread by [Platform::mock]
+    static MOCK_REPORT: RefCell<Option<Vec<u8>>> = RefCell::new(None);
+
+    /// Number of reports produced by the platform mocked for the current thread
+    static MOCK_ATTESTATIONS: Cell<usize> = Cell::new(0);
+
+    /// Key of the platform mocked for the current thread by [Platform::mock_key]
+    static MOCK_KEY: RefCell<Option<Vec<u8>>> = RefCell::new(None);
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Platform {
     technology: Technology,
     report_size: usize,
-    #[allow(dead_code)]
     key_size: usize,
 }
 
@@ -120,7 +133,43 @@ impl Platform {
         }
     }
 
+    /// Mocks the platform for the current thread, which is returned by [Platform::get]
+    ///
+    /// The reports of the mocked platform are `report` XORed with the repeated nonce.
+    #[cfg(test)]
+    pub fn mock(report: Vec<u8>) -> Self {
+        let platform = Self {
+            technology: Technology::Kvm,
+            report_size: report.len(),
+            key_size: 0,
+        };
+        MOCK_REPORT.with(|mock| mock.replace(Some(report)));
+        MOCK_ATTESTATIONS.with(|count| count.set(0));
+        platform
+    }
+
+    /// Mocks the key returned by [Platform::key] for the current thread
+    #[cfg(test)]
+    pub fn mock_key(key: Vec<u8>) {
+        MOCK_KEY.with(|mock| mock.replace(Some(key)));
+    }
+
+    /// Returns the number of reports produced by the mocked platform of the current thread
+    #[cfg(test)]
+    pub fn mock_attestations() -> usize {
+        MOCK_ATTESTATIONS.with(Cell::get)
+    }
+
     pub fn get() -> Result<Self> {
+        #[cfg(test)]
+        if let Some(report_size) = MOCK_REPORT.with(|mock| mock.borrow().as_ref().map(Vec::len)) {
+            return Ok(Self {
+                technology: Technology::Kvm,
+                report_size,
+                key_size: 0,
+            });
+        }
+
         let (technology, report_size) = Self::get_att(None, None)?;
         let key_size = Self::get_key(None)?;
 
@@ -135,8 +184,20 @@ impl Platform {
         self.technology
     }
 
-    #[allow(dead_code)]
+    pub fn report_size(&self) -> usize {
+        self.report_size
+    }
+
+    pub fn key_size(&self) -> usize {
+        self.key_size
+    }
+
     pub fn key(&self) -> Result<Vec<u8>> {
+        #[cfg(test)]
+        if let Some(key) = MOCK_KEY.with(|mock| mock.borrow().clone()) {
+            return Ok(key);
+        }
+
         let mut buf = vec![0; self.key_size];
 
         let size = Self::get_key(Some(&mut buf))?;
@@ -148,6 +209,16 @@ impl Platform {
     }
 
     pub fn attest(&self, nonce: &[u8]) -> Result<Vec<u8>> {
+        #[cfg(test)]
+        if let Some(mut report) = MOCK_REPORT.with(|mock| mock.borrow().clone()) {
+            MOCK_ATTESTATIONS.with(|count| count.set(count.get() + 1));
+            report
+                .iter_mut()
+                .zip(nonce.iter().cycle())
+                .for_each(|(r, n)| *r ^= n);
+            return Ok(report);
+        }
+
         let mut buf = vec![0; self.report_size];
 
         let (_, size) = Self::get_att(Some(nonce), Some(&mut buf))?;
@@ -169,3 +240,14 @@ fn test() {
     let report = platform.attest(b"00000000").unwrap();
     assert!(report.is_empty());
 }
+
+#[test]
+fn mock() {
+    let platform = Platform::mock(vec![0xff; 4]);
+    assert_eq!(platform.report_size(), 4);
+    assert_eq!(Platform::get().unwrap().report_size(), 4);
+    assert_eq!(
+        platform.attest(b"\x0f\xf0").unwrap(),
+        vec![0xf0, 0x0f, 0xf0, 0x0f]
+    );
+}
@@ -0,0 +1,83 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Unsealing of data sealed to the platform
+
+use super::platform::Platform;
+
+use anyhow::{anyhow, ensure, Context};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use zeroize::Zeroizing;
+
+/// HKDF salt used to derive the AES key of sealed files from the sealing key of the platform
+const SALT: &[u8] = b"enarx-sealed-file";
+
+/// Derives the AES-256-GCM key from the sealing key of the platform
+fn sealing_key() -> anyhow::Result<LessSafeKey> {
+    let platform = Platform::get().context("failed to query platform")?;
+    let key = Zeroizing::new(platform.key().context("failed to get sealing key")?);
+    ensure!(!key.is_empty(), "platform does not provide a sealing key");
+
+    let prk = Salt::new(HKDF_SHA256, SALT).extract(&key);
+    let okm = prk
+        .expand(&[], &AES_256_GCM)
+        .map_err(|_| anyhow!("failed to derive sealing key"))?;
+    Ok(LessSafeKey::new(UnboundKey::from(okm)))
+}
+
+/// Decrypts `ciphertext` with the sealing key of the platform
+///
+/// `ciphertext` consists of a 12-byte nonce followed by the AES-256-GCM encrypted data and tag.
+pub fn unseal(ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+    ensure!(ciphertext.len() >= NONCE_LEN, "sealed data is truncated");
+    let (nonce, ciphertext) = ciphertext.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).expect("invalid nonce length");
+
+    let mut data = ciphertext.to_vec();
+    let len = sealing_key()?
+        .open_in_place(nonce, Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("data was not sealed to this platform or is corrupted"))?
+        .len();
+    data.truncate(len);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn seal(data: &[u8]) -> Vec<u8> {
+        let nonce = [0x42; NONCE_LEN];
+        let mut sealed = data.to_vec();
+        sealing_key()
+            .unwrap()
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .unwrap();
+        [&nonce[..], &sealed].concat()
+    }
+
+    #[test]
+    fn roundtrip() {
+        Platform::mock_key(vec![0xaa; 16]);
+        let sealed = seal(b"secret");
+        assert_eq!(unseal(&sealed).unwrap(), b"secret");
+
+        let mut corrupted = sealed.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(unseal(&corrupted).is_err());
+        assert!(unseal(&sealed[..NONCE_LEN - 1]).is_err());
+
+        Platform::mock_key(vec![0xbb; 16]);
+        assert!(unseal(&sealed).is_err());
+    }
+
+    #[test]
+    fn no_sealing_key() {
+        let err = unseal(&[0; 32]).unwrap_err();
+        assert_eq!(err.to_string(), "platform does not provide a sealing key");
+    }
+}
@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! A WasiFile capturing the written data in memory
+
+use std::any::Any;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use wasi_common::file::{Advice, FdFlags, FileType};
+use wasi_common::{Error, ErrorExt, WasiFile};
+
+/// A write-only file appending all written data to a buffer shared by its clones
+#[derive(Clone, Default)]
+pub struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Capture {
+    /// Returns the data captured so far
+    pub fn contents(&self) -> Vec<u8> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Capture {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        Ok(FileType::Pipe)
+    }
+
+    async fn advise(&mut self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn allocate(&mut self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        Ok(FdFlags::APPEND)
+    }
+
+    async fn read_vectored<'a>(
+        &mut self,
+        _bufs: &mut [std::io::IoSliceMut<'a>],
+    ) -> Result<u64, Error> {
+        Ok(0)
+    }
+
+    async fn write_vectored<'a>(&mut self, bufs: &[std::io::IoSlice<'a>]) -> Result<u64, Error> {
+        let mut data = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let n = bufs.iter().map(|b| b.len()).sum::<usize>();
+        data.reserve(n);
+        bufs.iter().for_each(|b| data.extend_from_slice(b));
+        Ok(n as _)
+    }
+
+    async fn peek(&mut self, _buf: &mut [u8]) -> Result<u64, Error> {
+        Ok(0)
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Directories pre-opened from the host
+
+use anyhow::{Context, Result};
+use cap_std::ambient_authority;
+use enarx_config::DirFile;
+use once_cell::sync::Lazy;
+use wasi_common::dir::DirCaps;
+use wasi_common::file::FileCaps;
+use wasi_common::WasiDir;
+use wasmtime_wasi::Dir;
+
+/// Directory capabilities, which allow modification of the directory
+static DIR_WRITE_CAPS: Lazy<DirCaps> = Lazy::new(|| {
+    DirCaps::CREATE_DIRECTORY
+        | DirCaps::CREATE_FILE
+        | DirCaps::LINK_SOURCE
+        | DirCaps::LINK_TARGET
+        | DirCaps::RENAME_SOURCE
+        | DirCaps::RENAME_TARGET
+        | DirCaps::FILESTAT_SET_SIZE
+        | DirCaps::FILESTAT_SET_TIMES
+        | DirCaps::SYMLINK
+        | DirCaps::REMOVE_DIRECTORY
+        | DirCaps::UNLINK_FILE
+});
+
+/// File capabilities, which allow modification of a file
+static FILE_WRITE_CAPS: Lazy<FileCaps> = Lazy::new(|| {
+    FileCaps::DATASYNC
+        | FileCaps::SYNC
+        | FileCaps::WRITE
+        | FileCaps::ALLOCATE
+        | FileCaps::FILESTAT_SET_SIZE
+        | FileCaps::FILESTAT_SET_TIMES
+});
+
+pub fn dir_file(file: &DirFile) -> Result<(Box<dyn WasiDir>, DirCaps, FileCaps)> {
+    let dir = cap_std::fs::Dir::open_ambient_dir(&file.host_path, ambient_authority())
+        .with_context(|| format!("failed to open `{}`", file.host_path.display()))?;
+    let dir = Box::new(Dir::from_cap_std(dir));
+    if file.read_only {
+        Ok((
+            dir,
+            DirCaps::all().difference(*DIR_WRITE_CAPS),
+            FileCaps::all().difference(*FILE_WRITE_CAPS),
+        ))
+    } else {
+        Ok((dir, DirCaps::all(), FileCaps::all()))
+    }
+}
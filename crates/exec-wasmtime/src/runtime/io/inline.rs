@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! A read-only WasiFile backed by a buffer in memory
+
+use super::super::identity;
+use super::READ_CAPS;
+
+use std::any::Any;
+use std::io::{Cursor, IoSlice, IoSliceMut, Read, Seek, SeekFrom};
+
+use anyhow::{bail, Context};
+use enarx_config::{InlineFile, SealedFile};
+use wasi_common::file::{Advice, FdFlags, FileCaps, FileType, Filestat};
+use wasi_common::{Error, ErrorExt, WasiFile};
+
+/// A read-only seekable file with fixed contents
+pub struct Inline(Cursor<Vec<u8>>);
+
+impl Inline {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self(Cursor::new(data))
+    }
+
+    /// Returns the data after the current position
+    fn remaining(&self) -> &[u8] {
+        let data = self.0.get_ref();
+        let pos = self.0.position().try_into().unwrap_or(usize::MAX);
+        data.get(pos..).unwrap_or_default()
+    }
+}
+
+pub fn inline_file(file: &InlineFile) -> anyhow::Result<(Box<dyn WasiFile>, FileCaps)> {
+    let data = match file {
+        InlineFile {
+            data: Some(data),
+            host_path: None,
+            ..
+        } => base64::decode(data).context("failed to decode inline file data")?,
+        InlineFile {
+            data: None,
+            host_path: Some(path),
+            ..
+        } => std::fs::read(path).with_context(|| format!("failed to read `{}`", path.display()))?,
+        _ => bail!("exactly one of `data` and `host_path` must be specified"),
+    };
+    Ok((Box::new(Inline::new(data)), READ_CAPS))
+}
+
+pub fn sealed_file(file: &SealedFile) -> anyhow::Result<(Box<dyn WasiFile>, FileCaps)> {
+    let ciphertext =
+        base64::decode(&file.ciphertext).context("failed to decode sealed file ciphertext")?;
+    let data = identity::unseal(&ciphertext).context("failed to unseal file")?;
+    Ok((Box::new(Inline::new(data)), READ_CAPS))
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Inline {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+
+    async fn advise(&mut self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn allocate(&mut self, offset: u64, len: u64) -> Result<(), Error> {
+        // Like `posix_fallocate`, the file is extended with zeros, but never truncated.
+        let end = offset
+            .checked_add(len)
+            .and_then(|end| usize::try_from(end).ok())
+            .ok_or_else(Error::overflow)?;
+        let data = self.0.get_mut();
+        if let Some(additional) = end.checked_sub(data.len()) {
+            data.try_reserve(additional)
+                .map_err(|e| Error::overflow().context(e))?;
+            data.resize(end, 0);
+        }
+        Ok(())
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        Ok(FdFlags::empty())
+    }
+
+    async fn get_filestat(&mut self) -> Result<Filestat, Error> {
+        Ok(Filestat {
+            device_id: 0,
+            inode: 0,
+            filetype: FileType::RegularFile,
+            nlink: 1,
+            size: self.0.get_ref().len() as _,
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+
+    async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        let n = self.0.read_vectored(bufs)?;
+        Ok(n as _)
+    }
+
+    async fn read_vectored_at<'a>(
+        &mut self,
+        bufs: &mut [IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let offset = offset.try_into().unwrap_or(usize::MAX);
+        let mut data = self.0.get_ref().get(offset..).unwrap_or_default();
+        let n = data.read_vectored(bufs)?;
+        Ok(n as _)
+    }
+
+    async fn write_vectored<'a>(&mut self, _bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        Err(Error::badf())
+    }
+
+    async fn write_vectored_at<'a>(
+        &mut self,
+        _bufs: &[IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::badf())
+    }
+
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        self.0
+            .seek(pos)
+            .map_err(|e| Error::invalid_argument().context(e))
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> Result<u64, Error> {
+        let data = self.remaining();
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok(n as _)
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(self.remaining().len() as _)
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        Err(Error::badf())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+
+    #[test]
+    fn allocate() {
+        let mut file = Inline::new(b"data".to_vec());
+        block_on(file.allocate(2, 6)).unwrap();
+        assert_eq!(file.0.get_ref(), b"data\0\0\0\0");
+        // Allocating a range within the file does not truncate it.
+        block_on(file.allocate(0, 1)).unwrap();
+        assert_eq!(block_on(file.get_filestat()).unwrap().size, 8);
+        assert!(block_on(file.allocate(u64::MAX, 1)).is_err());
+
+        block_on(file.advise(0, 8, Advice::WillNeed)).unwrap();
+    }
+}
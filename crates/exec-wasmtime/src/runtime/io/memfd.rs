@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Read-only in-memory files backed by an anonymous `memfd`
+
+#[cfg(target_os = "linux")]
+use super::READ_CAPS;
+
+use anyhow::Result;
+use enarx_config::MemFdFile;
+use wasi_common::file::FileCaps;
+use wasi_common::WasiFile;
+
+#[cfg(target_os = "linux")]
+pub fn memfd_file(file: &MemFdFile) -> Result<(Box<dyn WasiFile>, FileCaps)> {
+    use std::io::{Seek, Write};
+
+    use anyhow::Context;
+    use rustix::fs::{fcntl_add_seals, memfd_create, MemfdFlags, SealFlags};
+
+    let fd = memfd_create(&*file.name, MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING)
+        .context("failed to create memfd")?;
+    let mut memfd = std::fs::File::from(fd);
+    memfd
+        .write_all(&file.data)
+        .context("failed to write memfd")?;
+    if file.sealed {
+        fcntl_add_seals(
+            &memfd,
+            SealFlags::WRITE | SealFlags::SHRINK | SealFlags::GROW | SealFlags::SEAL,
+        )
+        .context("failed to seal memfd")?;
+    }
+    memfd.rewind().context("failed to rewind memfd")?;
+
+    let memfd = wasmtime_wasi::File::from_cap_std(cap_std::fs::File::from_std(memfd));
+    Ok((Box::new(memfd), READ_CAPS))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn memfd_file(_file: &MemFdFile) -> Result<(Box<dyn WasiFile>, FileCaps)> {
+    anyhow::bail!("memfd files are not supported on this platform")
+}
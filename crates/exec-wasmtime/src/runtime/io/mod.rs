@@ -2,11 +2,26 @@
 
 //! I/O functionality for keeps
 
+pub mod capture;
+pub mod dir;
+pub mod inline;
+pub mod memfd;
 pub mod null;
+pub mod pipe;
 
+use enarx_config::FileCap;
 use wasi_common::file::FileCaps;
 use wasi_common::WasiFile;
 
+/// File capabilities of a read-only seekable file
+pub const READ_CAPS: FileCaps = FileCaps::READ
+    .union(FileCaps::SEEK)
+    .union(FileCaps::TELL)
+    .union(FileCaps::ADVISE)
+    .union(FileCaps::FDSTAT_SET_FLAGS)
+    .union(FileCaps::FILESTAT_GET)
+    .union(FileCaps::POLL_READWRITE);
+
 pub fn stdio_file(mut file: impl WasiFile + 'static) -> (Box<dyn WasiFile>, FileCaps) {
     // Ensure wasmtime can detect the TTY.
     let caps = if file.isatty() {
@@ -16,3 +31,50 @@ pub fn stdio_file(mut file: impl WasiFile + 'static) -> (Box<dyn WasiFile>, File
     };
     (Box::new(file), caps)
 }
+
+/// Restricts `caps` to the capabilities `allowed` by the config, if any
+pub fn restrict_caps(caps: FileCaps, allowed: Option<&[FileCap]>) -> FileCaps {
+    let allowed = match allowed {
+        Some(allowed) => allowed,
+        None => return caps,
+    };
+    let allowed = allowed
+        .iter()
+        .map(|cap| match cap {
+            FileCap::Read => FileCaps::READ,
+            FileCap::Write => FileCaps::WRITE,
+            FileCap::Seek => FileCaps::SEEK,
+            FileCap::Tell => FileCaps::TELL,
+            FileCap::Poll => FileCaps::POLL_READWRITE,
+            FileCap::Datasync => FileCaps::DATASYNC,
+            FileCap::Sync => FileCaps::SYNC,
+            FileCap::Advise => FileCaps::ADVISE,
+            FileCap::Allocate => FileCaps::ALLOCATE,
+            FileCap::FdstatSetFlags => FileCaps::FDSTAT_SET_FLAGS,
+            FileCap::FilestatGet => FileCaps::FILESTAT_GET,
+            FileCap::FilestatSetSize => FileCaps::FILESTAT_SET_SIZE,
+            FileCap::FilestatSetTimes => FileCaps::FILESTAT_SET_TIMES,
+        })
+        .fold(FileCaps::empty(), |all, cap| all | cap);
+    caps & allowed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn restrict() {
+        assert_eq!(restrict_caps(READ_CAPS, None), READ_CAPS);
+        assert_eq!(restrict_caps(READ_CAPS, Some(&[])), FileCaps::empty());
+        // Capabilities not granted by the file are not added.
+        assert_eq!(
+            restrict_caps(READ_CAPS, Some(&[FileCap::Read, FileCap::Write])),
+            FileCaps::READ
+        );
+        assert_eq!(
+            restrict_caps(FileCaps::all(), Some(&[FileCap::Poll, FileCap::Tell])),
+            FileCaps::POLL_READWRITE | FileCaps::TELL
+        );
+    }
+}
@@ -4,8 +4,8 @@
 
 use std::any::Any;
 
-use wasi_common::file::{FdFlags, FileType};
-use wasi_common::{Error, WasiFile};
+use wasi_common::file::{Advice, FdFlags, FileType};
+use wasi_common::{Error, ErrorExt, WasiFile};
 
 pub struct Null;
 
@@ -19,6 +19,14 @@ impl WasiFile for Null {
         Ok(FileType::Pipe)
     }
 
+    async fn advise(&mut self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn allocate(&mut self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+
     async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
         Ok(FdFlags::APPEND | FdFlags::NONBLOCK)
     }
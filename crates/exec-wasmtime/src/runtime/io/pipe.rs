@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Anonymous pipes connecting file descriptors of a keep
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use enarx_config::PipeFile;
+use wasi_common::file::FileCaps;
+use wasi_common::pipe::{ReadPipe, WritePipe};
+use wasi_common::WasiFile;
+
+/// Write ends of the pipes, keyed by pair index, which are handed out to the second file of a pair
+#[derive(Default)]
+pub struct Pipes(HashMap<u32, Option<std::fs::File>>);
+
+impl Pipes {
+    /// Returns the read end of a new pipe for the first file of a pair
+    /// and the write end for the second one.
+    pub fn file(&mut self, file: &PipeFile) -> Result<(Box<dyn WasiFile>, FileCaps)> {
+        match self.0.entry(file.pair) {
+            Entry::Vacant(entry) => {
+                let (read, write) = pipe()?;
+                entry.insert(Some(write));
+                Ok((
+                    Box::new(ReadPipe::new(read)),
+                    FileCaps::READ | FileCaps::FILESTAT_GET,
+                ))
+            }
+            Entry::Occupied(mut entry) => {
+                let write = entry
+                    .get_mut()
+                    .take()
+                    .with_context(|| format!("pipe `{}` has more than two ends", file.pair))?;
+                Ok((
+                    Box::new(WritePipe::new(write)),
+                    FileCaps::WRITE | FileCaps::FILESTAT_GET,
+                ))
+            }
+        }
+    }
+
+    /// Ensures that every pipe has both of its ends assigned
+    pub fn finish(self) -> Result<()> {
+        if let Some(pair) = self
+            .0
+            .into_iter()
+            .find_map(|(pair, write)| write.map(|_| pair))
+        {
+            bail!("pipe `{pair}` has no write end")
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn pipe() -> Result<(std::fs::File, std::fs::File)> {
+    let (read, write) = rustix::io::pipe().context("failed to create pipe")?;
+    Ok((read.into(), write.into()))
+}
+
+#[cfg(not(unix))]
+fn pipe() -> Result<(std::fs::File, std::fs::File)> {
+    bail!("pipes are not supported on this platform")
+}
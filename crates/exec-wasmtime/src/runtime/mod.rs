@@ -2,24 +2,59 @@
 
 //! The Enarx Wasm runtime and all related functionality
 
+mod compression;
+mod deterministic;
+mod host;
 mod identity;
 mod io;
 mod net;
+mod signal;
+mod wasmhelper;
 
+use self::identity::platform::Platform;
+use self::io::capture::Capture;
+use self::io::dir::dir_file;
+use self::io::inline::{inline_file, sealed_file};
+use self::io::memfd::memfd_file;
 use self::io::null::Null;
-use self::io::stdio_file;
-use self::net::{connect_file, listen_file};
+use self::io::pipe::Pipes;
+use self::io::{restrict_caps, stdio_file};
+use self::net::tls::{RotatingCert, Sched, Stream};
+use self::net::{connect_file, listen_file, ConnectOptions};
 
-use super::{Package, Workload};
+pub use self::net::egress::EgressPolicy;
+pub use self::net::resolver::Resolver;
 
-use anyhow::{bail, Context};
-use enarx_config::{Config, File};
+use super::{Cancelled, ExecutionOptions, ExecutionTimeout, FuelExhausted, Package, Workload};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::future::{poll_fn, Future};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::task::Poll;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, bail, ensure, Context};
+use enarx_config::{Config, File, OptLevel};
 use once_cell::sync::Lazy;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use sha2::{Digest, Sha256};
+use tracing::field::{self, Empty};
+use tracing::{debug_span, Instrument, Span};
+use url::Url;
 use wasi_common::file::FileCaps;
 use wasi_common::WasiFile;
-use wasmtime::{AsContextMut, Engine, Linker, Module, Store, Trap, Val};
+use wasmtime::{
+    AsContextMut, Engine, Extern, Func, Linker, Module, Store, StoreLimits, StoreLimitsBuilder,
+    Trap, TrapCode, Val,
+};
 use wasmtime_wasi::stdio::{stderr, stdin, stdout};
-use wasmtime_wasi::{add_to_linker, WasiCtxBuilder};
+use wasmtime_wasi::{add_to_linker, WasiCtx, WasiCtxBuilder};
 
 /// Wasmtime config
 static WASMTIME_CONFIG: Lazy<wasmtime::Config> = Lazy::new(|| {
@@ -29,93 +64,1172 @@ static WASMTIME_CONFIG: Lazy<wasmtime::Config> = Lazy::new(|| {
     config.static_memory_guard_size(0);
     config.dynamic_memory_guard_size(0);
     config.dynamic_memory_reserved_for_growth(16 * 1024 * 1024);
+    config.epoch_interruption(true);
     config
 });
 
-// The Enarx Wasm runtime
-pub struct Runtime;
+/// Wasmtime config with async support enabled
+static ASYNC_WASMTIME_CONFIG: Lazy<wasmtime::Config> = Lazy::new(|| {
+    let mut config = WASMTIME_CONFIG.clone();
+    config.async_support(true);
+    config
+});
+
+/// Maximum amount of elements of each table of a workload
+const MAX_TABLE_ELEMENTS: u32 = 1_000_000;
+
+/// Maximum amount of instances created by a workload
+const MAX_INSTANCES: usize = 100;
+
+/// Modules providing the imports of a workload, i.e. the WASI snapshots, the host functions
+/// and the wasi-crypto host functions
+const IMPORT_MODULES: &[&str] = &[
+    "wasi_snapshot_preview1",
+    "wasi_unstable",
+    "host",
+    "wasi_ephemeral_crypto_common",
+    "wasi_ephemeral_crypto_asymmetric_common",
+    "wasi_ephemeral_crypto_signatures",
+    "wasi_ephemeral_crypto_symmetric",
+];
+
+/// Interval, in which an asynchronous execution yields to the executor
+const YIELD_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Minimum time between attempts to renew the certificate chain of the listeners
+const MIN_RENEWAL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Data of the store, in which a workload is executed
+pub struct State {
+    /// WASI context of the workload
+    pub wasi: WasiCtx,
+
+    /// Resource limits of the workload
+    limits: StoreLimits,
+
+    /// Certificate chain of the workload identity
+    certs: Vec<rustls::Certificate>,
+
+    /// DER-encoded `SubjectPublicKeyInfo` of the workload identity
+    public_key: Vec<u8>,
+
+    /// Key of the workload identity and the objects of the wasi-crypto host functions
+    crypto: host::crypto::Crypto,
+
+    /// Files capturing the written data indexed by file descriptor
+    captures: BTreeMap<u32, Capture>,
+
+    /// Kinds and names of the configured files indexed by file descriptor
+    fds: Vec<host::FdInfo>,
+
+    /// Durations of the phases of the execution
+    profile: ExecutionProfile,
+
+    /// Attestation reports requested by the workload cached by their nonce
+    reports: host::ReportCache,
+
+    /// Callback notified by `host::ready`, which is taken by the first call
+    ready: Option<ReadyCallback>,
+
+    /// Whether the workload is executed deterministically
+    deterministic: bool,
+
+    /// Renewal of the certificate chain of the listeners, stopped when dropped
+    _renewal: Option<Sender<()>>,
+}
+
+/// Durations of the phases of an execution of a workload
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExecutionProfile {
+    /// Time spent compiling the Wasm module or loading the precompiled module
+    pub compile_duration: Duration,
+
+    /// Time spent generating the key pair and obtaining the certificates
+    pub attestation_duration: Duration,
+
+    /// Time spent setting up the pre-opened files including connecting and accepting sockets
+    pub file_setup_duration: Duration,
+
+    /// Time spent instantiating and executing the Wasm module
+    pub wasm_execution_duration: Duration,
+}
+
+/// Outcome of an execution of a workload, which did not trap
+#[derive(Debug)]
+pub enum Exit {
+    /// The default function returned the values
+    Returned(Vec<Val>),
+
+    /// The workload exited with the exit code by calling `proc_exit`
+    Exited(i32),
+}
+
+impl Exit {
+    /// Returns the values returned by the default function, which are empty, if the workload exited
+    pub fn values(&self) -> &[Val] {
+        match self {
+            Self::Returned(values) => values,
+            Self::Exited(..) => &[],
+        }
+    }
+
+    /// Returns the exit code of the workload, which is `0`, if the default function returned
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Returned(..) => 0,
+            Self::Exited(code) => *code,
+        }
+    }
+}
+
+/// Output of an execution of a workload, which did not trap
+#[derive(Debug)]
+pub struct Output {
+    /// How the workload exited
+    pub exit: Exit,
+
+    /// Data written to the files of kind `capture` indexed by file descriptor
+    pub captured: BTreeMap<u32, Vec<u8>>,
+
+    /// Durations of the phases of the execution
+    #[cfg(feature = "profiling")]
+    pub profile: ExecutionProfile,
+}
+
+/// Handle cancelling an execution started by [Runtime::execute_interruptible]
+#[derive(Clone)]
+pub struct Interrupt {
+    engine: Engine,
+    deadline: Deadline,
+}
+
+impl Interrupt {
+    fn new(engine: &Engine) -> Self {
+        Self {
+            engine: engine.clone(),
+            deadline: Default::default(),
+        }
+    }
+
+    /// Cancel the execution
+    ///
+    /// The Wasm module traps the next time it executes a loop header or function entry.
+    /// A host call blocking the Wasm module, e.g. on I/O, is not interrupted.
+    pub fn cancel(&self) {
+        self.deadline.cancelled.store(true, Ordering::SeqCst);
+        self.engine.increment_epoch();
+    }
+}
+
+/// Deadline of an execution, which is checked each time the epoch of the engine is incremented
+///
+/// The engine of a [CompiledModule] is shared by concurrent executions, whose timeouts and
+/// cancellations increment its epoch as well, so the epoch alone does not interrupt a store.
+#[derive(Clone, Default)]
+struct Deadline {
+    /// Set by [Interrupt::cancel]
+    cancelled: Arc<AtomicBool>,
+    /// Set once the timeout of the execution expires
+    expired: Arc<AtomicBool>,
+}
+
+impl Deadline {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expired.load(Ordering::SeqCst)
+    }
+
+    fn is_reached(&self) -> bool {
+        self.is_cancelled() || self.is_expired()
+    }
+}
+
+/// Wasm module compiled and validated by [Runtime::compile]
+///
+/// It can be executed repeatedly by [Runtime::execute_compiled] without being recompiled.
+#[derive(Clone)]
+pub struct CompiledModule {
+    module: Module,
+    /// SHA-256 digest of the Wasm module it was compiled from
+    digest: [u8; 32],
+}
+
+/// Callback notified, when a workload signals its readiness
+#[derive(Clone)]
+struct ReadyCallback(Arc<dyn Fn(u8) + Send + Sync>);
+
+impl fmt::Debug for ReadyCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ReadyCallback")
+    }
+}
+
+/// Serves `body` to a single HTTPS request with a certificate trusted by the returned roots
+#[cfg(test)]
+pub(crate) fn mock_https(body: Vec<u8>) -> (Url, rustls::RootCertStore) {
+    Platform::mock(vec![0xaa; 16]);
+    let san = ["localhost".try_into().unwrap()];
+    let (key, _) = identity::generate(None, None, &san).unwrap();
+    let certs =
+        identity::selfsigned(&key, None, &san, identity::DEFAULT_SELFSIGNED_VALIDITY).unwrap();
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(&rustls::Certificate(certs[0].clone())).unwrap();
+    let url = identity::mock_https(&certs, &key, "application/wasm", body);
+    (url, roots)
+}
+
+/// The Enarx Wasm runtime
+#[derive(Clone, Debug, Default)]
+pub struct Runtime {
+    cache: Option<PathBuf>,
+    fuel: Option<u64>,
+    memory_limit_bytes: Option<u64>,
+    env_passthrough: Vec<String>,
+    on_ready: Option<ReadyCallback>,
+    egress: Option<EgressPolicy>,
+    resolver: Resolver,
+}
+
+/// Builder of a [Runtime] with policies applying to all workloads it executes
+#[derive(Clone, Debug, Default)]
+pub struct RuntimeBuilder {
+    runtime: Runtime,
+}
+
+impl RuntimeBuilder {
+    /// Cache compiled modules in the directory at `path`, see [Runtime::with_cache]
+    pub fn cache(mut self, path: impl Into<PathBuf>) -> Self {
+        self.runtime.cache = Some(path.into());
+        self
+    }
+
+    /// Limit the fuel budget of workloads
+    ///
+    /// Workloads without a fuel budget or with a larger one are given this budget.
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.runtime.fuel = Some(fuel);
+        self
+    }
+
+    /// Limit the size of each linear memory of workloads in bytes
+    ///
+    /// Workloads without a memory limit or with a larger one are given this limit.
+    pub fn memory_limit_bytes(mut self, limit: u64) -> Self {
+        self.runtime.memory_limit_bytes = Some(limit);
+        self
+    }
+
+    /// Pass the host environment variables `names` through to all workloads
+    ///
+    /// The variables are passed in addition to the `env_passthrough` of the workload config.
+    /// Only these variables may be referenced in the workload config, see [Workload::from_package].
+    pub fn env_passthrough(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.runtime
+            .env_passthrough
+            .extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Call `callback` with the status passed by a workload to `host::ready`
+    ///
+    /// The callback is called at most once per execution, on the thread executing the workload,
+    /// so it should not block, e.g. only send a notification.
+    pub fn on_ready(mut self, callback: impl Fn(u8) + Send + Sync + 'static) -> Self {
+        self.runtime.on_ready = Some(ReadyCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Restrict the destinations of connect files of workloads to those allowed by `policy`
+    ///
+    /// Without a policy, workloads may connect to any destination.
+    pub fn egress(mut self, policy: EgressPolicy) -> Self {
+        self.runtime.egress = Some(policy);
+        self
+    }
+
+    /// Resolve the names of the destinations of connect files of workloads with `resolver`
+    ///
+    /// Without a resolver, names are resolved by the resolver of the host.
+    pub fn resolver(mut self, resolver: Resolver) -> Self {
+        self.runtime.resolver = resolver;
+        self
+    }
+
+    /// Build the runtime
+    pub fn build(self) -> Runtime {
+        self.runtime
+    }
+}
 
 impl Runtime {
-    // Execute an Enarx [Package]
-    pub fn execute(package: Package) -> anyhow::Result<Vec<Val>> {
-        let (prvkey, crtreq) = identity::generate()?;
+    /// Create a builder of a runtime
+    pub fn builder() -> RuntimeBuilder {
+        RuntimeBuilder::default()
+    }
+
+    /// Create a runtime, which caches compiled modules in the directory at `path`
+    ///
+    /// Modules are cached by the digests of the Wasm module and the engine configuration,
+    /// so that modules compiled by a different engine are not loaded. The cached modules
+    /// are executed as native code, so the directory must be located in trusted storage.
+    pub fn with_cache(path: impl Into<PathBuf>) -> Self {
+        Self::builder().cache(path).build()
+    }
+
+    // Load the workload of the package and apply the limits of the runtime to its config
+    fn workload(&self, package: Package) -> anyhow::Result<Workload> {
+        let mut workload = Workload::from_package(package, &self.env_passthrough)?;
+        if self.fuel.is_none()
+            && self.memory_limit_bytes.is_none()
+            && self.env_passthrough.is_empty()
+        {
+            return Ok(workload);
+        }
+
+        let min = |limit: Option<u64>, max: Option<u64>| match (limit, max) {
+            (Some(limit), Some(max)) => Some(limit.min(max)),
+            (limit, max) => limit.or(max),
+        };
+        let config = workload.config.get_or_insert_with(Default::default);
+        config.fuel = min(config.fuel, self.fuel);
+        config.memory_limit_bytes = min(config.memory_limit_bytes, self.memory_limit_bytes);
+        config
+            .env_passthrough
+            .extend(self.env_passthrough.iter().cloned());
+        Ok(workload)
+    }
+
+    /// Execute an Enarx [Package]
+    pub fn execute(&self, package: Package, options: ExecutionOptions) -> anyhow::Result<Output> {
+        let span = Self::execute_span();
+        let _span = span.enter();
+        let workload = self.workload(package)?;
+        let engine = Self::engine(&WASMTIME_CONFIG, &workload, &options)?;
+        self.run(&engine, workload, options, None, None)
+    }
+
+    /// Execute an Enarx [Package] deterministically
+    ///
+    /// The random number generator of the Wasm module is seeded with `seed`, the system clock
+    /// reports `deterministic_time_secs` of the config and the monotonic clock does not advance.
+    /// NaNs produced by floating-point operations are canonicalized. Given the same package
+    /// and seed, the execution produces the same output, unless the Wasm module depends on
+    /// other inputs of the host, e.g. sockets or attestation reports.
+    pub fn execute_deterministic(
+        &self,
+        package: Package,
+        seed: [u8; 32],
+    ) -> anyhow::Result<Output> {
+        let options = ExecutionOptions {
+            deterministic_seed: Some(seed),
+            ..Default::default()
+        };
+        self.execute(package, options)
+    }
+
+    /// Compile and validate the Wasm module `wasm` without executing it
+    ///
+    /// The Wasm module is compiled by the engine, which executes workloads without a config,
+    /// and its imports are checked to be provided by the runtime. No store is created,
+    /// so no files are opened and no attestation takes place.
+    pub fn compile(&self, wasm: &[u8]) -> anyhow::Result<CompiledModule> {
+        let workload = self.workload(Package::Bundle {
+            wasm: wasm.to_vec(),
+            conf: None,
+        })?;
+        let engine = Self::engine(&WASMTIME_CONFIG, &workload, &Default::default())?;
+        let memory_limit_bytes = workload
+            .config
+            .as_ref()
+            .and_then(|config| config.memory_limit_bytes);
+        let module = self.module(&engine, &workload.webasm, memory_limit_bytes, None)?;
+        Self::check_imports(&module, &[])?;
+        Ok(CompiledModule {
+            module,
+            digest: Sha256::digest(wasm).into(),
+        })
+    }
 
-        let Workload { webasm, config } = package.try_into()?;
+    /// Execute an Enarx [Package] with the Wasm module `compiled` from it by [Runtime::compile]
+    ///
+    /// The Wasm module of the package must be identical to the one `compiled` was compiled from.
+    /// A fuel budget in the config of the package requires the runtime to limit the fuel budget
+    /// as well, as the Wasm module only consumes fuel, if it was compiled by such a runtime.
+    /// A `cranelift_opt_level` in the config of the package is rejected, as the Wasm module
+    /// is compiled already, and so are the `compiled` and `deterministic_seed` options.
+    ///
+    /// Concurrent executions of the same compiled module share its engine, but the timeout
+    /// of each execution only interrupts the execution itself.
+    pub fn execute_compiled(
+        &self,
+        compiled: &CompiledModule,
+        package: Package,
+        options: ExecutionOptions,
+    ) -> anyhow::Result<Output> {
+        let span = Self::execute_span();
+        let _span = span.enter();
+        let workload = self.workload(package)?;
+        ensure!(
+            Sha256::digest(&workload.webasm)[..] == compiled.digest,
+            "Wasm module of the package differs from the compiled one"
+        );
+        let fuel = workload.config.as_ref().and_then(|config| config.fuel);
+        ensure!(
+            fuel.is_none() || self.fuel.is_some(),
+            "fuel budget of the config requires a runtime with a fuel budget"
+        );
+        ensure!(
+            workload
+                .config
+                .as_ref()
+                .and_then(|config| config.cranelift_opt_level)
+                .is_none(),
+            "`cranelift_opt_level` of the config cannot be applied to a compiled Wasm module"
+        );
+        ensure!(
+            options.compiled.is_none(),
+            "a precompiled module cannot be loaded for a compiled Wasm module"
+        );
+        ensure!(
+            options.deterministic_seed.is_none(),
+            "a compiled Wasm module cannot be executed deterministically"
+        );
+        let engine = compiled.module.engine().clone();
+        self.run(&engine, workload, options, None, Some(&compiled.module))
+    }
+
+    /// Execute an Enarx [Package] on a new thread, which can be cancelled by the returned [Interrupt]
+    ///
+    /// The execution fails with [Cancelled], if it is cancelled before the Wasm module finished.
+    pub fn execute_interruptible(
+        &self,
+        package: Package,
+        options: ExecutionOptions,
+    ) -> anyhow::Result<(Interrupt, JoinHandle<anyhow::Result<Output>>)> {
+        let span = Self::execute_span();
+        let _span = span.enter();
+        let workload = self.workload(package)?;
+        let engine = Self::engine(&WASMTIME_CONFIG, &workload, &options)?;
+        let interrupt = Interrupt::new(&engine);
+        let runtime = self.clone();
+        let execution = {
+            let interrupt = interrupt.clone();
+            let span = Span::current();
+            thread::Builder::new()
+                .name("enarx-wasm".into())
+                .spawn(move || {
+                    span.in_scope(|| {
+                        runtime.run(&engine, workload, options, Some(&interrupt), None)
+                    })
+                })
+                .context("failed to spawn execution thread")?
+        };
+        Ok((interrupt, execution))
+    }
+
+    // Create the span of an execution, whose fields are recorded once the workload is set up
+    fn execute_span() -> Span {
+        debug_span!(
+            "execute",
+            wasm_size_bytes = Empty,
+            file_count = Empty,
+            platform = Empty,
+            steward_url = Empty
+        )
+    }
+
+    // Execute the workload with the engine until it finishes, traps or is interrupted
+    //
+    // The Wasm module of the workload is compiled, unless it was `compiled` already.
+    fn run(
+        &self,
+        engine: &Engine,
+        workload: Workload,
+        options: ExecutionOptions,
+        interrupt: Option<&Interrupt>,
+        compiled: Option<&Module>,
+    ) -> anyhow::Result<Output> {
+        let fuel = workload.config.as_ref().and_then(|config| config.fuel);
+        let entrypoint = workload
+            .config
+            .as_ref()
+            .and_then(|config| config.entrypoint.clone());
+        let deadline =
+            interrupt.map_or_else(Deadline::default, |interrupt| interrupt.deadline.clone());
+        let (mut linker, mut wstore, module) = self.setup(engine, workload, &options, compiled)?;
+        wstore.set_epoch_deadline(1);
+        wstore.epoch_deadline_callback({
+            let deadline = deadline.clone();
+            move |_| {
+                ensure!(!deadline.is_reached(), "execution interrupted");
+                Ok(1)
+            }
+        });
+        // The epoch deadline is set by now, so a later cancellation interrupts the execution.
+        if deadline.is_cancelled() {
+            bail!(Cancelled);
+        }
+        let _timer = Self::start_timer(engine, &options, &deadline);
+        let start = Instant::now();
+
+        debug_span!("link")
+            .in_scope(|| linker.module(&mut wstore, "", &module))
+            .context("failed to link module")?;
+
+        let func = Self::entrypoint(&linker, &mut wstore, entrypoint.as_deref())?;
+
+        let mut values = vec![Val::null(); func.ty(&wstore).results().len()];
+        let res = debug_span!("call")
+            .in_scope(|| func.call(&mut wstore, Default::default(), &mut values));
+        wstore.data_mut().profile.wasm_execution_duration = start.elapsed();
+        Self::finish(res, values, &options, fuel, &deadline, wstore.data())
+    }
+
+    /// Execute an Enarx [Package] asynchronously
+    ///
+    /// The Wasm module yields to the executor every 10 milliseconds, so that dropping the
+    /// returned future aborts its execution. Setting up the workload, i.e. attesting to the
+    /// Steward and connecting or accepting the sockets of the config, blocks the thread
+    /// polling the future until it is done, as do host calls blocking the Wasm module.
+    pub async fn execute_async(
+        &self,
+        package: Package,
+        options: ExecutionOptions,
+    ) -> anyhow::Result<Output> {
+        self.run_async(package, options)
+            .instrument(Self::execute_span())
+            .await
+    }
+
+    // Execute the workload asynchronously until it finishes or traps
+    async fn run_async(
+        &self,
+        package: Package,
+        options: ExecutionOptions,
+    ) -> anyhow::Result<Output> {
+        let workload = self.workload(package)?;
+        let fuel = workload.config.as_ref().and_then(|config| config.fuel);
+        let engine = Self::engine(&ASYNC_WASMTIME_CONFIG, &workload, &options)?;
+        let entrypoint = workload
+            .config
+            .as_ref()
+            .and_then(|config| config.entrypoint.clone());
+        let (mut linker, mut wstore, module) = self.setup(&engine, workload, &options, None)?;
+        let deadline = Deadline::default();
+        wstore.set_epoch_deadline(1);
+        wstore.epoch_deadline_async_yield_and_update(1);
+        let _ticker = Self::start_ticker(&engine);
+        let _timer = Self::start_timer(&engine, &options, &deadline);
+        let start = Instant::now();
+
+        let link = linker
+            .module_async(&mut wstore, "", &module)
+            .instrument(debug_span!("link"));
+        Self::until_deadline(link, &deadline)
+            .await
+            .context("failed to link module")?;
+
+        let func = Self::entrypoint(&linker, &mut wstore, entrypoint.as_deref())?;
+
+        let mut values = vec![Val::null(); func.ty(&wstore).results().len()];
+        let call = func
+            .call_async(&mut wstore, Default::default(), &mut values)
+            .instrument(debug_span!("call"));
+        let res = Self::until_deadline(call, &deadline).await;
+        wstore.data_mut().profile.wasm_execution_duration = start.elapsed();
+        Self::finish(res, values, &options, fuel, &deadline, wstore.data())
+    }
+
+    // Poll the execution `future` until it completes or its deadline is reached
+    //
+    // The Wasm module yields each time the epoch of the engine is incremented by the ticker,
+    // at which point the deadline is checked.
+    async fn until_deadline<T>(
+        future: impl Future<Output = anyhow::Result<T>>,
+        deadline: &Deadline,
+    ) -> anyhow::Result<T> {
+        let mut future = Box::pin(future);
+        poll_fn(|cx| {
+            if deadline.is_reached() {
+                return Poll::Ready(Err(anyhow!("execution interrupted")));
+            }
+            future.as_mut().poll(cx)
+        })
+        .await
+    }
+
+    // Get the function executing the workload, which is `entrypoint` or the default function
+    //
+    // A reactor module exporting `_initialize` is initialized by the linker already and
+    // has no default function, for which a no-op function is returned.
+    fn entrypoint(
+        linker: &Linker<State>,
+        wstore: &mut Store<State>,
+        entrypoint: Option<&str>,
+    ) -> anyhow::Result<Func> {
+        match entrypoint {
+            Some(name) => linker
+                .get(wstore, "", name)
+                .and_then(Extern::into_func)
+                .with_context(|| format!("Wasm module does not export function `{name}`")),
+            None => linker
+                .get_default(wstore, "")
+                .context("failed to get default function"),
+        }
+    }
+
+    // Create an engine for the workload
+    //
+    // The engine consumes fuel, if a fuel budget is set, and compiles with the configured
+    // optimization level. NaNs are canonicalized in deterministic executions, as their bit
+    // patterns differ between hardware.
+    fn engine(
+        config: &wasmtime::Config,
+        workload: &Workload,
+        options: &ExecutionOptions,
+    ) -> anyhow::Result<Engine> {
+        let fuel = workload.config.as_ref().and_then(|config| config.fuel);
+        let opt_level = workload
+            .config
+            .as_ref()
+            .and_then(|config| config.cranelift_opt_level);
+        let deterministic = options.deterministic_seed.is_some();
+        let engine = if fuel.is_some() || opt_level.is_some() || deterministic {
+            let mut config = config.clone();
+            config.consume_fuel(fuel.is_some());
+            config.cranelift_nan_canonicalization(deterministic);
+            if let Some(opt_level) = opt_level {
+                config.cranelift_opt_level(match opt_level {
+                    OptLevel::None => wasmtime::OptLevel::None,
+                    OptLevel::Speed => wasmtime::OptLevel::Speed,
+                    OptLevel::SpeedAndSize => wasmtime::OptLevel::SpeedAndSize,
+                });
+            }
+            Engine::new(&config)
+        } else {
+            Engine::new(config)
+        };
+        engine.context("failed to create execution engine")
+    }
+
+    // Set up the linker and store for the execution of the workload
+    fn setup(
+        &self,
+        engine: &Engine,
+        workload: Workload,
+        options: &ExecutionOptions,
+        compiled: Option<&Module>,
+    ) -> anyhow::Result<(Linker<State>, Store<State>, Module)> {
+        let mut profile = ExecutionProfile::default();
+
+        workload.validate().context("invalid config")?;
+        let Workload { webasm, config } = workload;
         let Config {
-            steward,
+            mut steward,
+            steward_pin,
+            steward_shuffle,
+            steward_retries,
+            steward_backoff_ms,
+            steward_max_backoff_ms,
+            steward_deadline_secs,
+            key_algorithm,
+            subject,
+            san,
+            self_signed_cert_validity_secs,
+            attestation_cache_size,
             args,
+            entrypoint: _,
             files,
             env,
+            fuel,
+            memory_limit_bytes,
+            deterministic_time_secs,
+            cranelift_opt_level: _,
+            module_signing_key,
+            denied_imports,
+            env_passthrough,
         } = config.unwrap_or_default();
+        Self::verify(&webasm, options, module_signing_key.as_deref())?;
 
-        let certs = if let Some(url) = steward {
-            identity::steward(&url, crtreq).context("failed to attest to Steward")?
-        } else {
-            identity::selfsigned(&prvkey).context("failed to generate self-signed certificates")?
+        let span = Span::current();
+        if !span.is_disabled() {
+            span.record("wasm_size_bytes", webasm.len());
+            span.record("file_count", files.len());
+            if let Ok(platform) = Platform::get() {
+                span.record("platform", field::debug(platform.technology()));
+            }
+            if let Some(url) = steward.first() {
+                span.record("steward_url", url.as_str());
+            }
         }
-        .into_iter()
-        .map(rustls::Certificate)
-        .collect::<Vec<_>>();
 
-        let engine = Engine::new(&WASMTIME_CONFIG).context("failed to create execution engine")?;
+        let start = Instant::now();
+        identity::check_key_algorithm(key_algorithm, &steward)?;
+        let pins = steward_pin
+            .iter()
+            .map(|pin| pin.parse())
+            .collect::<anyhow::Result<Vec<identity::Pin>>>()
+            .context("invalid Steward pin")?;
+        let (prvkey, crtreq) = debug_span!("generate")
+            .in_scope(|| identity::generate(key_algorithm, subject.as_deref(), &san))?;
 
-        let mut linker = Linker::new(&engine);
-        add_to_linker(&mut linker, |s| s).context("failed to setup linker and add WASI")?;
+        if steward_shuffle {
+            identity::shuffle(&mut steward).context("failed to shuffle Steward URLs")?;
+        }
+        let default = identity::Retry::default();
+        let retry = identity::Retry {
+            retries: steward_retries,
+            deadline: steward_deadline_secs.map(Duration::from_secs),
+            initial_backoff: steward_backoff_ms
+                .map_or(default.initial_backoff, Duration::from_millis),
+            max_backoff: steward_max_backoff_ms.map_or(default.max_backoff, Duration::from_millis),
+        };
+        let identity::Issued { certs, ocsp } = if steward.is_empty() {
+            let validity = self_signed_cert_validity_secs
+                .map(Duration::from_secs)
+                .unwrap_or(identity::DEFAULT_SELFSIGNED_VALIDITY);
+            let certs = identity::selfsigned(&prvkey, subject.as_deref(), &san, validity)
+                .context("failed to generate self-signed certificates")?;
+            identity::Issued { certs, ocsp: None }
+        } else {
+            debug_span!("steward")
+                .in_scope(|| identity::steward(&steward, &crtreq, retry, &pins))
+                .context("failed to attest to Steward")?
+        };
+        let certs = certs
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect::<Vec<_>>();
+        let server_cert =
+            RotatingCert::new(certs.clone(), ocsp, &rustls::PrivateKey(prvkey.to_vec()))
+                .context("failed to load the workload identity")?;
+        let server_cert = Arc::new(server_cert);
+        let renewal = if steward.is_empty() {
+            None
+        } else {
+            Self::start_renewal(steward, crtreq, retry, pins, &certs, server_cert.clone())
+        };
+        profile.attestation_duration = start.elapsed();
 
-        let mut wstore = Store::new(&engine, WasiCtxBuilder::new().build());
+        let mut linker = Linker::new(engine);
+        add_to_linker(&mut linker, |s: &mut State| &mut s.wasi)
+            .context("failed to setup linker and add WASI")?;
+        signal::add_to_linker(&mut linker).context("failed to add signal emulation to linker")?;
+        host::add_to_linker(&mut linker).context("failed to add host functions to linker")?;
+        host::crypto::add_to_linker(&mut linker)
+            .context("failed to add wasi-crypto host functions to linker")?;
 
-        let module =
-            Module::from_binary(&engine, &webasm).context("failed to compile Wasm module")?;
-        linker
-            .module(&mut wstore, "", &module)
-            .context("failed to link module")?;
+        let mut limits = StoreLimitsBuilder::new()
+            .table_elements(MAX_TABLE_ELEMENTS)
+            .instances(MAX_INSTANCES);
+        if let Some(limit) = memory_limit_bytes {
+            let limit = limit
+                .try_into()
+                .context("memory limit exceeds the address space")?;
+            limits = limits.memory_size(limit);
+        }
+        let mut wasi = WasiCtxBuilder::new().build();
+        // Reads of TLS streams with buffered plaintext must not wait for their sockets.
+        wasi.sched = Box::new(Sched(wasmtime_wasi::sched::sched_ctx()));
+        if let Some(seed) = options.deterministic_seed {
+            let time = deterministic_time_secs.map_or(Duration::ZERO, Duration::from_secs);
+            wasi.clocks = deterministic::clocks(time);
+            wasi.random = deterministic::random(seed);
+        }
+        let state = State {
+            wasi,
+            limits: limits.build(),
+            certs: certs.clone(),
+            public_key: identity::public_key(&prvkey).context("failed to encode public key")?,
+            crypto: host::crypto::Crypto::new(&prvkey).context("failed to load private key")?,
+            captures: BTreeMap::new(),
+            fds: files.iter().map(host::FdInfo::from).collect(),
+            profile: ExecutionProfile::default(),
+            reports: host::ReportCache::new(attestation_cache_size),
+            ready: self.on_ready.clone(),
+            deterministic: options.deterministic_seed.is_some(),
+            _renewal: renewal,
+        };
+        let mut wstore = Store::new(engine, state);
+        wstore.limiter(|state| &mut state.limits);
+        if let Some(fuel) = fuel {
+            wstore
+                .add_fuel(fuel)
+                .context("failed to add fuel to the store")?;
+        }
+
+        let start = Instant::now();
+        let module = match compiled {
+            Some(module) => module.clone(),
+            None => self.module(
+                engine,
+                &webasm,
+                memory_limit_bytes,
+                options.compiled.as_deref(),
+            )?,
+        };
+        profile.compile_duration = start.elapsed();
+        Self::check_imports(&module, &denied_imports)?;
 
         let mut ctx = wstore.as_context_mut();
-        let ctx = ctx.data_mut();
+        let ctx = &mut ctx.data_mut().wasi;
 
+        let start = Instant::now();
         let mut names = vec![];
+        let mut pipes = Pipes::default();
+        let mut captures = BTreeMap::new();
         for (fd, file) in files.iter().enumerate() {
             names.push(file.name());
+            let allowed = file.caps();
+            let fd = fd.try_into().context("too many open files")?;
             let (file, caps): (Box<dyn WasiFile>, _) = match file {
                 File::Null(..) => (Box::new(Null), FileCaps::all()),
                 File::Stdin(..) => stdio_file(stdin()),
                 File::Stdout(..) => stdio_file(stdout()),
                 File::Stderr(..) => stdio_file(stderr()),
-                File::Listen(file) => listen_file(file, certs.clone(), &prvkey)
+                File::Listen(file) => listen_file(file, &certs, server_cert.clone())
                     .context("failed to setup listening socket")?,
-                File::Connect(file) => connect_file(file, certs.clone(), &prvkey)
-                    .context("failed to setup connection stream")?,
+                File::Connect(file) => {
+                    let (file, caps, peer) = connect_file(
+                        file,
+                        ConnectOptions::from(file),
+                        certs.clone(),
+                        &prvkey,
+                        &self.resolver,
+                        self.egress.as_ref(),
+                    )
+                    .context("failed to setup connection stream")?;
+                    if let Some(peer) = peer {
+                        let var = format!("FD_{fd}_REMOTE_ADDR");
+                        ctx.push_env(&var, &peer.to_string()).with_context(|| {
+                            format!("failed to set environment variable `{var}`")
+                        })?;
+                    }
+                    (file, caps)
+                }
+                File::Dir(file) => {
+                    let (dir, caps, file_caps) =
+                        dir_file(file).context("failed to pre-open directory")?;
+                    let file_caps = restrict_caps(file_caps, allowed);
+                    ctx.insert_dir(fd, dir, caps, file_caps, file.name.to_string().into());
+                    continue;
+                }
+                File::Pipe(file) => pipes.file(file).context("failed to setup pipe")?,
+                File::Capture(..) => {
+                    let capture = Capture::default();
+                    captures.insert(fd, capture.clone());
+                    (Box::new(capture), FileCaps::all())
+                }
+                File::MemFd(file) => memfd_file(file).context("failed to setup memfd file")?,
+                File::Inline(file) => inline_file(file).context("failed to setup inline file")?,
+                File::Sealed(file) => sealed_file(file).context("failed to setup sealed file")?,
             };
-            let fd = fd.try_into().context("too many open files")?;
-            ctx.insert_file(fd, file, caps);
+            if let Some(protocol) = file
+                .as_any()
+                .downcast_ref::<Stream>()
+                .and_then(Stream::alpn_protocol)
+            {
+                let var = format!("FD_{fd}_ALPN");
+                ctx.push_env(&var, &String::from_utf8_lossy(protocol))
+                    .with_context(|| format!("failed to set environment variable `{var}`"))?;
+            }
+            ctx.insert_file(fd, file, restrict_caps(caps, allowed));
         }
+        pipes.finish().context("failed to setup pipes")?;
+        profile.file_setup_duration = start.elapsed();
         ctx.push_env("FD_COUNT", &names.len().to_string())
             .context("failed to set environment variable `FD_COUNT`")?;
         ctx.push_env("FD_NAMES", &names.join(":"))
             .context("failed to set environment variable `FD_NAMES`")?;
 
+        for k in env_passthrough {
+            if env.contains_key(&k) {
+                continue;
+            }
+            if let Ok(v) = std::env::var(&k) {
+                ctx.push_env(&k, &v)
+                    .with_context(|| format!("failed to set environment variable `{k}`"))?;
+            }
+        }
+
         for (k, v) in env {
+            let v = v
+                .resolve()
+                .with_context(|| format!("failed to read environment variable `{k}`"))?;
             ctx.push_env(&k, &v)
-                .context("failed to set environment variable `{k}`")?;
+                .with_context(|| format!("failed to set environment variable `{k}`"))?;
         }
 
         ctx.push_arg("main.wasm")
             .context("failed to push argv[0]")?;
         for arg in args {
+            let arg = arg.resolve().context("failed to read argument")?;
             ctx.push_arg(&arg).context("failed to push argument")?;
         }
 
-        let func = linker
-            .get_default(&mut wstore, "")
-            .context("failed to get default function")?;
+        wstore.data_mut().captures = captures;
+        wstore.data_mut().profile = profile;
 
-        let mut values = vec![Val::null(); func.ty(&wstore).results().len()];
-        if let Err(e) = func.call(wstore, Default::default(), &mut values) {
-            match e.downcast_ref::<Trap>().map(Trap::i32_exit_status) {
-                Some(Some(0)) => {} // function exited with a code of 0, treat as success
-                _ => bail!(e.context("failed to execute default function")),
+        Ok((linker, wstore, module))
+    }
+
+    // Verify the digest and signature of `webasm` as distributed, i.e. before decompression
+    fn verify(
+        webasm: &[u8],
+        options: &ExecutionOptions,
+        signing_key: Option<&str>,
+    ) -> anyhow::Result<()> {
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{b:02x}")).collect()
+        }
+
+        if let Some(expected) = options.expected_sha256 {
+            let digest = Sha256::digest(webasm);
+            ensure!(
+                digest[..] == expected[..],
+                "SHA-256 digest `{}` of the Wasm module does not match `{}`",
+                hex(&digest),
+                hex(&expected)
+            );
+        }
+        match (signing_key, &options.signature) {
+            (Some(key), Some(signature)) => {
+                let key = base64::decode(key).context("invalid module signing key")?;
+                UnparsedPublicKey::new(&ED25519, key)
+                    .verify(webasm, signature)
+                    .map_err(|_| anyhow!("invalid signature of the Wasm module"))
+            }
+            (Some(_), None) => bail!("Wasm module is not signed, but `module_signing_key` is set"),
+            (None, Some(_)) => bail!("Wasm module is signed, but `module_signing_key` is not set"),
+            (None, None) => Ok(()),
+        }
+    }
+
+    // Reject imports of `module` not provided by the runtime or denied by the config
+    fn check_imports(module: &Module, denied: &[String]) -> anyhow::Result<()> {
+        for import in module.imports() {
+            let (module, name) = (import.module(), import.name());
+            ensure!(
+                IMPORT_MODULES.contains(&module),
+                "Wasm module imports `{module}::{name}`, which is not provided by the runtime"
+            );
+            ensure!(
+                !denied
+                    .iter()
+                    .any(|entry| *entry == module || *entry == format!("{module}::{name}")),
+                "Wasm module imports `{module}::{name}`, which is denied by `denied_imports`"
+            );
+        }
+        Ok(())
+    }
+
+    // Decompress and compile `webasm`, loading the precompiled module at `compiled`
+    // or in the cache directory, if any
+    fn module(
+        &self,
+        engine: &Engine,
+        webasm: &[u8],
+        memory_limit_bytes: Option<u64>,
+        compiled: Option<&Path>,
+    ) -> anyhow::Result<Module> {
+        let limit = memory_limit_bytes.map_or(compression::MAX_MODULE_SIZE, |limit| {
+            limit.min(compression::MAX_MODULE_SIZE)
+        });
+        let webasm = compression::decompress(webasm, limit)?;
+        debug_span!("compile").in_scope(|| match (compiled, &self.cache) {
+            (Some(path), _) => Self::compile_cached(engine, &webasm, path),
+            (None, Some(dir)) => {
+                let path = Self::cache_path(engine, &webasm, dir);
+                Self::compile_cached(engine, &webasm, &path)
+            }
+            (None, None) => {
+                Module::from_binary(engine, &webasm).context("failed to compile Wasm module")
+            }
+        })
+    }
+
+    // Load the module precompiled from `webasm` at `path` or compile and store it there
+    //
+    // The precompiled module is prefixed by the SHA-256 digest of `webasm` it was compiled from.
+    // Wasmtime itself rejects modules precompiled by an incompatible engine.
+    fn compile_cached(engine: &Engine, webasm: &[u8], path: &Path) -> anyhow::Result<Module> {
+        let digest = Sha256::digest(webasm);
+        if let Ok(artifact) = fs::read(path) {
+            if let Some(compiled) = artifact.strip_prefix(digest.as_slice()) {
+                // SAFETY: The precompiled module was serialized by `Module::serialize` and is
+                // required to be stored in trusted storage by `ExecutionOptions::compiled`,
+                // which the embedder sets, as it is never deserialized from the host's `Args`.
+                match unsafe { Module::deserialize(engine, compiled) } {
+                    Ok(module) => return Ok(module),
+                    Err(e) => tracing::debug!("failed to load precompiled module: {e:#}"),
+                }
+            }
+        }
+
+        let module =
+            Module::from_binary(engine, webasm).context("failed to compile Wasm module")?;
+        let compiled = module
+            .serialize()
+            .context("failed to serialize compiled module")?;
+        if let Err(e) = fs::write(path, [digest.as_slice(), &compiled].concat()) {
+            tracing::warn!(
+                "failed to store precompiled module at `{}`: {e}",
+                path.display()
+            );
+        }
+        Ok(module)
+    }
+
+    // Path of the module compiled from `webasm` by `engine` in the cache directory `dir`
+    //
+    // The file name consists of the SHA-256 digests of `webasm` and of the configuration of
+    // `engine` and this crate version, which changes with the version of Wasmtime.
+    fn cache_path(engine: &Engine, webasm: &[u8], dir: &Path) -> PathBuf {
+        let module = Sha256::digest(webasm);
+        let engine = Sha256::new()
+            .chain_update(env!("CARGO_PKG_VERSION"))
+            .chain_update(format!("{:?}", engine.config()))
+            .finalize();
+        dir.join(format!("{module:x}-{engine:x}.cwasm"))
+    }
+
+    // Start a timer reaching the deadline of the execution once the timeout expires
+    //
+    // The timer is stopped, when the returned [Sender] is dropped.
+    fn start_timer(
+        engine: &Engine,
+        options: &ExecutionOptions,
+        deadline: &Deadline,
+    ) -> Option<Sender<()>> {
+        let timeout = options.timeout?;
+        let engine = engine.clone();
+        let expired = deadline.expired.clone();
+        let (stop, stopped) = channel();
+        thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(timeout) {
+                expired.store(true, Ordering::SeqCst);
+                engine.increment_epoch();
+            }
+        });
+        Some(stop)
+    }
+
+    // Start a ticker incrementing the epoch of the engine every [YIELD_INTERVAL]
+    //
+    // The ticker is stopped, when the returned [Sender] is dropped.
+    fn start_ticker(engine: &Engine) -> Sender<()> {
+        let engine = engine.clone();
+        let (stop, stopped) = channel();
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(YIELD_INTERVAL) {
+                engine.increment_epoch();
+            }
+        });
+        stop
+    }
+
+    // Start renewing the certificate chain of the listeners before it expires
+    //
+    // The renewal is stopped, when the returned [Sender] is dropped.
+    fn start_renewal(
+        steward: Vec<Url>,
+        crtreq: Vec<u8>,
+        retry: identity::Retry,
+        pins: Vec<identity::Pin>,
+        certs: &[rustls::Certificate],
+        server_cert: Arc<RotatingCert>,
+    ) -> Option<Sender<()>> {
+        let mut expiry = match certs.first().map(|cert| identity::expiry(&cert.0)) {
+            Some(Ok(expiry)) => expiry,
+            Some(Err(e)) => {
+                tracing::warn!("not renewing the certificate chain: {e:#}");
+                return None;
+            }
+            None => return None,
+        };
+        let (stop, stopped) = channel();
+        thread::spawn(move || loop {
+            // Renew after two thirds of the remaining validity.
+            let remaining = expiry.duration_since(SystemTime::now()).unwrap_or_default();
+            let wait = (remaining * 2 / 3).max(MIN_RENEWAL_INTERVAL);
+            if let Err(RecvTimeoutError::Disconnected) | Ok(()) = stopped.recv_timeout(wait) {
+                break;
+            }
+
+            let renewed = identity::steward(&steward, &crtreq, retry, &pins).and_then(|issued| {
+                let expiry = issued
+                    .certs
+                    .first()
+                    .map(identity::expiry)
+                    .context("Steward returned an empty certificate chain")??;
+                Ok((issued, expiry))
+            });
+            match renewed {
+                Ok((identity::Issued { certs, ocsp }, renewed)) => {
+                    let certs = certs.into_iter().map(rustls::Certificate).collect();
+                    server_cert.update(certs, ocsp);
+                    expiry = renewed;
+                    tracing::info!("renewed the certificate chain");
+                }
+                Err(e) => tracing::warn!("failed to renew the certificate chain: {e:#}"),
+            }
+        });
+        Some(stop)
+    }
+
+    // Map the result of the default function call to the execution result
+    fn finish(
+        res: anyhow::Result<()>,
+        values: Vec<Val>,
+        options: &ExecutionOptions,
+        fuel: Option<u64>,
+        deadline: &Deadline,
+        state: &State,
+    ) -> anyhow::Result<Output> {
+        let profile = state.profile;
+        tracing::debug!(
+            compile = ?profile.compile_duration,
+            attestation = ?profile.attestation_duration,
+            file_setup = ?profile.file_setup_duration,
+            wasm_execution = ?profile.wasm_execution_duration,
+            "execution profile"
+        );
+
+        let exit = match res {
+            Ok(()) => Exit::Returned(values),
+            Err(e) => {
+                let trap = e.downcast_ref::<Trap>();
+                match (
+                    trap.map(Trap::i32_exit_status),
+                    trap.and_then(Trap::trap_code),
+                ) {
+                    (Some(Some(code)), _) => Exit::Exited(code), // `proc_exit` was called
+                    _ if deadline.is_cancelled() => bail!(Cancelled),
+                    _ if deadline.is_expired() => match options.timeout {
+                        Some(timeout) => bail!(ExecutionTimeout(timeout)),
+                        None => bail!(e.context("execution interrupted")),
+                    },
+                    (_, Some(TrapCode::OutOfFuel)) => match fuel {
+                        Some(fuel) => bail!(FuelExhausted(fuel)),
+                        None => bail!(e.context("execution ran out of fuel")),
+                    },
+                    _ => bail!(e.context("failed to execute default function")),
+                }
             }
         };
-        Ok(values)
+        let captured = state
+            .captures
+            .iter()
+            .map(|(fd, capture)| (*fd, capture.contents()))
+            .collect();
+        Ok(Output {
+            exit,
+            captured,
+            #[cfg(feature = "profiling")]
+            profile,
+        })
     }
 }
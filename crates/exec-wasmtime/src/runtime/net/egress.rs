@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Policy of the destinations connections may be established to
+
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
+
+/// Networks, which DNS name patterns do not match, so that a name cannot be pointed at them
+///
+/// These are the unspecified, loopback, private and link-local networks, e.g. the
+/// `169.254.169.254` instance metadata endpoint of cloud providers.
+static INTERNAL_NETS: Lazy<Vec<IpNet>> = Lazy::new(|| {
+    [
+        "0.0.0.0/8",
+        "10.0.0.0/8",
+        "100.64.0.0/10",
+        "127.0.0.0/8",
+        "169.254.0.0/16",
+        "172.16.0.0/12",
+        "192.168.0.0/16",
+        "::/128",
+        "::1/128",
+        "fc00::/7",
+        "fe80::/10",
+    ]
+    .into_iter()
+    .map(|net| net.parse().expect("invalid internal network"))
+    .collect()
+});
+
+/// Returns whether `ip` belongs to one of the [INTERNAL_NETS]
+///
+/// IPv4-mapped IPv6 addresses are matched as IPv4 addresses.
+fn is_internal(ip: IpAddr) -> bool {
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(..) => ip,
+    };
+    INTERNAL_NETS.iter().any(|net| net.contains(&ip))
+}
+
+/// Host of an allowed destination
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Host {
+    /// Any host
+    Any,
+
+    /// Any subdomain of the DNS name, which starts with a `.`
+    Subdomain(String),
+
+    /// The DNS name
+    Name(String),
+
+    /// Any address in the network
+    Net(IpNet),
+}
+
+/// Destination matched by a `host:port` pattern
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Destination {
+    host: Host,
+    /// Port of the destination, any port if not set
+    port: Option<u16>,
+}
+
+impl FromStr for Destination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (host, port) = s.rsplit_once(':').context("missing port")?;
+        let port = match port {
+            "*" => None,
+            port => Some(port.parse().context("invalid port")?),
+        };
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        let host = if host == "*" {
+            Host::Any
+        } else if let Ok(net) = host.parse() {
+            Host::Net(net)
+        } else if let Ok(ip) = host.parse::<IpAddr>() {
+            Host::Net(ip.into())
+        } else {
+            let name = host.strip_prefix("*.").unwrap_or(host);
+            if name.is_empty()
+                || !name.split('.').all(|label| {
+                    !label.is_empty()
+                        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                })
+            {
+                bail!("invalid host `{host}`");
+            }
+            let name = name.to_ascii_lowercase();
+            if host.starts_with("*.") {
+                Host::Subdomain(format!(".{name}"))
+            } else {
+                Host::Name(name)
+            }
+        };
+        Ok(Self { host, port })
+    }
+}
+
+impl Destination {
+    fn matches(&self, host: &str, addr: SocketAddr) -> bool {
+        if matches!(self.port, Some(port) if port != addr.port()) {
+            return false;
+        }
+        match &self.host {
+            Host::Any => true,
+            Host::Subdomain(suffix) => {
+                host.to_ascii_lowercase().ends_with(suffix) && !is_internal(addr.ip())
+            }
+            Host::Name(name) => host.eq_ignore_ascii_case(name) && !is_internal(addr.ip()),
+            Host::Net(net) => net.contains(&addr.ip()),
+        }
+    }
+}
+
+/// Policy of the destinations connect files may reach, which denies all unless allowed
+///
+/// Destinations are allowed by `host:port` patterns, where `host` is either
+///
+/// - a DNS name, e.g. `example.com`, matched case-insensitively,
+/// - a DNS name prefixed with `*.`, which matches all of its subdomains, e.g. `*.example.com`,
+/// - an IP address or network in CIDR notation, e.g. `192.0.2.1` or `[2001:db8::/32]`,
+///   which is matched against the resolved addresses of the destination,
+/// - or `*`, which matches any host and address,
+///
+/// and `port` is either a port number or `*`, which matches any port.
+/// DNS name patterns do not match names resolved to an unspecified, loopback, private or
+/// link-local address, which must be allowed by an IP address or network pattern instead.
+/// Unix domain sockets are not subject to the policy.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EgressPolicy {
+    allow: Vec<Destination>,
+}
+
+impl EgressPolicy {
+    /// Creates a policy allowing the destinations matched by `patterns`
+    pub fn new(patterns: impl IntoIterator<Item = impl AsRef<str>>) -> anyhow::Result<Self> {
+        let allow = patterns
+            .into_iter()
+            .map(|pattern| {
+                let pattern = pattern.as_ref();
+                pattern
+                    .parse()
+                    .with_context(|| format!("invalid egress pattern `{pattern}`"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { allow })
+    }
+
+    /// Returns whether a connection to `host` may be established to its resolved address `addr`
+    ///
+    /// The address is checked on every resolution, so that a DNS name allowed by the policy
+    /// cannot be pointed at an internal network, e.g. `169.254.169.254`, which is not allowed
+    /// by an IP address or network pattern.
+    pub fn allows(&self, host: &str, addr: SocketAddr) -> bool {
+        self.allow.iter().any(|dst| dst.matches(host, addr))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn allows() {
+        let addr = |addr: &str| addr.parse::<SocketAddr>().unwrap();
+        let policy = EgressPolicy::new([
+            "api.example.com:443",
+            "*.example.net:*",
+            "10.0.0.0/8:5432",
+            "[2001:db8::1]:80",
+        ])
+        .unwrap();
+
+        assert!(policy.allows("API.example.com", addr("192.0.2.1:443")));
+        assert!(!policy.allows("api.example.com", addr("192.0.2.1:80")));
+        assert!(!policy.allows("www.example.com", addr("192.0.2.1:443")));
+        assert!(policy.allows("a.b.example.net", addr("192.0.2.1:8080")));
+        assert!(!policy.allows("example.net", addr("192.0.2.1:8080")));
+        assert!(policy.allows("db.internal", addr("10.1.2.3:5432")));
+        assert!(!policy.allows("db.internal", addr("192.0.2.1:5432")));
+        assert!(policy.allows("2001:db8::1", addr("[2001:db8::1]:80")));
+
+        // Allowed names cannot be pointed at internal networks.
+        for ip in [
+            "169.254.169.254",
+            "127.0.0.1",
+            "192.168.1.1",
+            "[::1]",
+            "[fe80::1]",
+            "[::ffff:169.254.169.254]",
+        ] {
+            assert!(
+                !policy.allows("api.example.com", addr(&format!("{ip}:443"))),
+                "{ip}"
+            );
+            assert!(
+                !policy.allows("a.example.net", addr(&format!("{ip}:443"))),
+                "{ip}"
+            );
+        }
+
+        // Unless they are allowed by an IP network pattern.
+        assert!(!policy.allows("api.example.com", addr("10.1.2.3:443")));
+        assert!(policy.allows("api.example.com", addr("10.1.2.3:5432")));
+
+        assert!(!EgressPolicy::default().allows("api.example.com", addr("192.0.2.1:443")));
+        assert!(EgressPolicy::new(["*:*"])
+            .unwrap()
+            .allows("api.example.com", addr("192.0.2.1:443")));
+
+        for pattern in [
+            "example.com",
+            "example.com:https",
+            "exa mple.com:443",
+            "*.:443",
+        ] {
+            assert!(EgressPolicy::new([pattern]).is_err(), "{pattern}");
+        }
+    }
+}
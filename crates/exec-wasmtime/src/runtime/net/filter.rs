@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Filter of the clients allowed to connect to a listener
+
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+
+/// Filter of the IP addresses of clients by the networks they belong to
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IpFilter {
+    /// Networks of the allowed clients, all clients are allowed if not set
+    allow: Option<Vec<IpNet>>,
+
+    /// Networks of the denied clients, which take precedence over `allow`
+    deny: Vec<IpNet>,
+}
+
+impl IpFilter {
+    pub fn new(allow: Option<Vec<IpNet>>, deny: Option<Vec<IpNet>>) -> Self {
+        Self {
+            allow,
+            deny: deny.unwrap_or_default(),
+        }
+    }
+
+    /// Returns whether a client with the address `ip` may connect
+    ///
+    /// IPv4-mapped IPv6 addresses of IPv4 clients connecting to an IPv6 socket
+    /// are matched as IPv4 addresses.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            IpAddr::V4(..) => ip,
+        };
+        let contains = |nets: &[IpNet]| nets.iter().any(|net| net.contains(&ip));
+        !contains(&self.deny) && self.allow.as_deref().map_or(true, contains)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn nets(nets: &[&str]) -> Vec<IpNet> {
+        nets.iter().map(|net| net.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn allows() {
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+
+        let filter = IpFilter::default();
+        assert!(filter.allows(ip("192.0.2.1")));
+        assert!(filter.allows(ip("2001:db8::1")));
+
+        let filter = IpFilter::new(
+            Some(nets(&["192.0.2.0/24", "2001:db8::/32"])),
+            Some(nets(&["192.0.2.1/32"])),
+        );
+        assert!(filter.allows(ip("192.0.2.2")));
+        assert!(filter.allows(ip("::ffff:192.0.2.2")));
+        assert!(filter.allows(ip("2001:db8::1")));
+        assert!(!filter.allows(ip("192.0.2.1")));
+        assert!(!filter.allows(ip("::ffff:192.0.2.1")));
+        assert!(!filter.allows(ip("198.51.100.1")));
+        assert!(!filter.allows(ip("2001:db9::1")));
+
+        let filter = IpFilter::new(None, Some(nets(&["198.51.100.0/24"])));
+        assert!(filter.allows(ip("192.0.2.1")));
+        assert!(!filter.allows(ip("198.51.100.1")));
+    }
+}
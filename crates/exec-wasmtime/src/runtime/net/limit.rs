@@ -0,0 +1,136 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Limits of the connections accepted by a listener
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Token bucket limiting the rate of events to `rate` per second
+///
+/// Bursts of up to `rate` events are allowed after an idle period.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: u32,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate.into(),
+            last: now,
+        }
+    }
+
+    /// Takes a token or returns the time until the next token is available
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let rate = f64::from(self.rate);
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+/// An accepted connection counted towards the concurrency limit until it is dropped
+#[derive(Debug)]
+pub struct Permit(Arc<AtomicU32>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Reason why a connection may not be accepted right now
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limited {
+    /// The rate limit is exceeded, a connection may be accepted after the given time
+    Rate(Duration),
+
+    /// The maximum amount of concurrent connections is open
+    Concurrency,
+}
+
+/// Rate and concurrency limits of the connections accepted by a listener
+#[derive(Debug, Default)]
+pub struct ConnectionLimits {
+    bucket: Option<TokenBucket>,
+    max_concurrent: Option<u32>,
+    open: Arc<AtomicU32>,
+}
+
+impl ConnectionLimits {
+    pub fn new(per_second: Option<u32>, max_concurrent: Option<u32>) -> Self {
+        Self {
+            bucket: per_second.map(|rate| TokenBucket::new(rate, Instant::now())),
+            max_concurrent,
+            open: Default::default(),
+        }
+    }
+
+    /// Reserves a connection, which is released by dropping the returned permit
+    pub fn acquire(&mut self) -> Result<Option<Permit>, Limited> {
+        let max = match self.max_concurrent {
+            Some(max) => max,
+            None => return self.take_token().map(|_| None),
+        };
+        self.open
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .map_err(|_| Limited::Concurrency)?;
+        let permit = Permit(self.open.clone());
+        // The permit is released again, if the rate limit is exceeded.
+        self.take_token().map(|_| Some(permit))
+    }
+
+    fn take_token(&mut self) -> Result<(), Limited> {
+        match &mut self.bucket {
+            Some(bucket) => bucket.take(Instant::now()).map_err(Limited::Rate),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2, start);
+        assert_eq!(bucket.take(start), Ok(()));
+        assert_eq!(bucket.take(start), Ok(()));
+        assert_eq!(bucket.take(start), Err(Duration::from_millis(500)));
+
+        // Tokens refill over time, but do not exceed the rate.
+        assert_eq!(bucket.take(start + Duration::from_millis(500)), Ok(()));
+        let later = start + Duration::from_secs(60);
+        assert_eq!(bucket.take(later), Ok(()));
+        assert_eq!(bucket.take(later), Ok(()));
+        assert!(bucket.take(later).is_err());
+    }
+
+    #[test]
+    fn concurrency() {
+        let mut limits = ConnectionLimits::new(None, Some(1));
+        let permit = limits.acquire().unwrap();
+        assert!(permit.is_some());
+        assert_eq!(limits.acquire().unwrap_err(), Limited::Concurrency);
+        drop(permit);
+        assert!(limits.acquire().unwrap().is_some());
+
+        let mut limits = ConnectionLimits::default();
+        assert!(limits.acquire().unwrap().is_none());
+    }
+}
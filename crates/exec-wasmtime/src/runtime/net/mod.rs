@@ -2,22 +2,44 @@
 
 //! Networking functionality for keeps
 
+pub mod egress;
+mod filter;
+mod limit;
+pub mod resolver;
 pub mod tls;
+pub mod udp;
 
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use self::egress::EgressPolicy;
+use self::filter::IpFilter;
+use self::limit::ConnectionLimits;
+use self::resolver::Resolver;
+use self::tls::{SniResolver, StapledCert};
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
 use std::ops::Deref;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use cap_std::net::{TcpListener, TcpStream};
-use enarx_config::{ConnectFile, ListenFile};
+use enarx_config::{CertAndKey, CipherSuite, ConnectFile, ListenFile, TcpKeepalive, TlsVersion};
 use once_cell::sync::Lazy;
 use rustls::cipher_suite::{
     TLS13_AES_128_GCM_SHA256, TLS13_AES_256_GCM_SHA384, TLS13_CHACHA20_POLY1305_SHA256,
+    TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256, TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+    TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256, TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+    TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384, TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
 };
+use rustls::client::{ClientSessionMemoryCache, StoresClientSessions};
 use rustls::kx_group::{SECP256R1, SECP384R1, X25519};
-use rustls::version::TLS13;
+use rustls::server::{AllowAnyAuthenticatedClient, NoClientAuth, ResolvesServerCert};
+use rustls::version::{TLS12, TLS13};
 use rustls::{Certificate, PrivateKey, RootCertStore};
+use rustls_pemfile::Item;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
 use wasi_common::file::FileCaps;
 use wasi_common::WasiFile;
 use zeroize::Zeroizing;
@@ -25,6 +47,9 @@ use zeroize::Zeroizing;
 static DEFAULT_TLS_PROTOCOL_VERSIONS: Lazy<[&'static rustls::SupportedProtocolVersion; 1]> =
     Lazy::new(|| [&TLS13]);
 
+static TLS12_PROTOCOL_VERSIONS: Lazy<[&'static rustls::SupportedProtocolVersion; 2]> =
+    Lazy::new(|| [&TLS13, &TLS12]);
+
 static DEFAULT_TLS_KX_GROUPS: Lazy<[&'static rustls::SupportedKxGroup; 3]> =
     Lazy::new(|| [&X25519, &SECP384R1, &SECP256R1]);
 
@@ -36,6 +61,24 @@ static DEFAULT_TLS_CIPHER_SUITES: Lazy<[rustls::SupportedCipherSuite; 3]> = Lazy
     ]
 });
 
+/// Maximum amount of TLS sessions cached for resumption
+const SESSION_CACHE_SIZE: usize = 256;
+
+/// TLS session cache shared by all outgoing connections, which opted in to session resumption
+static SESSION_CACHE: Lazy<Arc<ClientSessionMemoryCache>> =
+    Lazy::new(|| ClientSessionMemoryCache::new(SESSION_CACHE_SIZE));
+
+static DEFAULT_TLS12_CIPHER_SUITES: Lazy<[rustls::SupportedCipherSuite; 6]> = Lazy::new(|| {
+    [
+        TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+        TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+        TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+        TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+        TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+        TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+    ]
+});
+
 static LISTEN_CAPS: Lazy<FileCaps> = Lazy::new(|| {
     FileCaps::FILESTAT_GET | FileCaps::FDSTAT_SET_FLAGS | FileCaps::POLL_READWRITE | FileCaps::READ
 });
@@ -48,53 +91,605 @@ static CONNECT_CAPS: Lazy<FileCaps> = Lazy::new(|| {
         | FileCaps::WRITE
 });
 
+static DATAGRAM_CAPS: Lazy<FileCaps> = Lazy::new(|| {
+    FileCaps::FILESTAT_GET
+        | FileCaps::FDSTAT_SET_FLAGS
+        | FileCaps::POLL_READWRITE
+        | FileCaps::READ
+        | FileCaps::WRITE
+});
+
+/// Default maximum length of the queue of pending connections, as used by the standard library
+const DEFAULT_BACKLOG: u32 = 128;
+
+/// TCP socket options of connections
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TcpOptions {
+    /// Enable `TCP_NODELAY`
+    pub nodelay: bool,
+
+    /// Enable TCP keepalive probes with the given parameters
+    pub keepalive: Option<TcpKeepalive>,
+
+    /// Maximum time a read waits for data, which sets `SO_RCVTIMEO`
+    pub read_timeout: Option<Duration>,
+}
+
+impl TcpOptions {
+    /// Applies the options to a TCP socket
+    pub fn apply<'s>(&self, socket: impl Into<SockRef<'s>>) -> std::io::Result<()> {
+        let socket = socket.into();
+        if self.nodelay {
+            socket.set_nodelay(true)?;
+        }
+        if let Some(keepalive) = &self.keepalive {
+            socket.set_tcp_keepalive(&tcp_keepalive(keepalive)?)?;
+        }
+        if self.read_timeout.is_some() {
+            socket.set_read_timeout(self.read_timeout)?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts the configured keepalive parameters
+fn tcp_keepalive(config: &TcpKeepalive) -> std::io::Result<socket2::TcpKeepalive> {
+    let mut keepalive = socket2::TcpKeepalive::new();
+    if let Some(idle) = config.idle_secs {
+        keepalive = keepalive.with_time(Duration::from_secs(idle));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(interval) = config.interval_secs {
+            keepalive = keepalive.with_interval(Duration::from_secs(interval));
+        }
+        if let Some(count) = config.count {
+            keepalive = keepalive.with_retries(count);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    if config.interval_secs.is_some() || config.count.is_some() {
+        return Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "keepalive `interval_secs` and `count` are only supported on Linux",
+        ));
+    }
+    Ok(keepalive)
+}
+
+/// Options for listen sockets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct ListenOptions {
+    /// Maximum length of the queue of pending connections
+    backlog: Option<u32>,
+
+    /// Value of `SO_REUSEADDR`, the platform default is kept if not set
+    reuse_addr: Option<bool>,
+
+    /// Enable `SO_REUSEPORT`
+    reuse_port: bool,
+
+    /// Options of accepted connections
+    tcp: TcpOptions,
+}
+
+impl From<&ListenFile> for ListenOptions {
+    fn from(file: &ListenFile) -> Self {
+        match file {
+            ListenFile::Tls {
+                backlog,
+                reuse_addr,
+                reuse_port,
+                nodelay,
+                keepalive,
+                read_timeout_ms,
+                ..
+            } => Self {
+                backlog: *backlog,
+                reuse_addr: *reuse_addr,
+                reuse_port: *reuse_port,
+                tcp: TcpOptions {
+                    nodelay: *nodelay,
+                    keepalive: *keepalive,
+                    read_timeout: read_timeout_ms.map(Duration::from_millis),
+                },
+            },
+            ListenFile::Tcp {
+                backlog,
+                reuse_addr,
+                reuse_port,
+                nodelay,
+                keepalive,
+                ..
+            } => Self {
+                backlog: *backlog,
+                reuse_addr: *reuse_addr,
+                reuse_port: *reuse_port,
+                tcp: TcpOptions {
+                    nodelay: *nodelay,
+                    keepalive: *keepalive,
+                    read_timeout: None,
+                },
+            },
+            ListenFile::Udp { .. } | ListenFile::Unix { .. } => Self::default(),
+        }
+    }
+}
+
+/// Options for outgoing connections
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConnectOptions {
+    /// Resume TLS sessions established by previous connections
+    pub session_resumption: bool,
+
+    /// Options of the TCP connection
+    pub tcp: TcpOptions,
+}
+
+impl From<&ConnectFile> for ConnectOptions {
+    fn from(file: &ConnectFile) -> Self {
+        match file {
+            ConnectFile::Tls {
+                session_resumption,
+                nodelay,
+                keepalive,
+                read_timeout_ms,
+                ..
+            } => Self {
+                session_resumption: *session_resumption,
+                tcp: TcpOptions {
+                    nodelay: *nodelay,
+                    keepalive: *keepalive,
+                    read_timeout: read_timeout_ms.map(Duration::from_millis),
+                },
+            },
+            ConnectFile::Tcp {
+                nodelay, keepalive, ..
+            } => Self {
+                session_resumption: false,
+                tcp: TcpOptions {
+                    nodelay: *nodelay,
+                    keepalive: *keepalive,
+                    read_timeout: None,
+                },
+            },
+            ConnectFile::Udp { .. } | ConnectFile::Unix { .. } => Self::default(),
+        }
+    }
+}
+
+impl ConnectOptions {
+    /// Returns the TLS session storage to use for a connection
+    fn session_storage(&self) -> Option<Arc<dyn StoresClientSessions>> {
+        if self.session_resumption {
+            Some(SESSION_CACHE.clone())
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the TLS protocol versions and cipher suites to use
+///
+/// The default cipher suites for the enabled protocol versions are used, if `cipher_suites` is empty.
+fn tls_parameters(
+    min_version: TlsVersion,
+    cipher_suites: &[CipherSuite],
+) -> (
+    &'static [&'static rustls::SupportedProtocolVersion],
+    Vec<rustls::SupportedCipherSuite>,
+) {
+    let versions: &[_] = match min_version {
+        TlsVersion::Tls12 => TLS12_PROTOCOL_VERSIONS.deref(),
+        TlsVersion::Tls13 => DEFAULT_TLS_PROTOCOL_VERSIONS.deref(),
+    };
+    let suites = if !cipher_suites.is_empty() {
+        cipher_suites
+            .iter()
+            .map(|suite| match suite {
+                CipherSuite::Tls13Aes256GcmSha384 => TLS13_AES_256_GCM_SHA384,
+                CipherSuite::Tls13Aes128GcmSha256 => TLS13_AES_128_GCM_SHA256,
+                CipherSuite::Tls13Chacha20Poly1305Sha256 => TLS13_CHACHA20_POLY1305_SHA256,
+                CipherSuite::TlsEcdheEcdsaWithAes256GcmSha384 => {
+                    TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384
+                }
+                CipherSuite::TlsEcdheEcdsaWithAes128GcmSha256 => {
+                    TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256
+                }
+                CipherSuite::TlsEcdheEcdsaWithChacha20Poly1305Sha256 => {
+                    TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256
+                }
+                CipherSuite::TlsEcdheRsaWithAes256GcmSha384 => {
+                    TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384
+                }
+                CipherSuite::TlsEcdheRsaWithAes128GcmSha256 => {
+                    TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256
+                }
+                CipherSuite::TlsEcdheRsaWithChacha20Poly1305Sha256 => {
+                    TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256
+                }
+            })
+            .collect()
+    } else if min_version == TlsVersion::Tls12 {
+        DEFAULT_TLS_CIPHER_SUITES
+            .iter()
+            .chain(DEFAULT_TLS12_CIPHER_SUITES.iter())
+            .copied()
+            .collect()
+    } else {
+        DEFAULT_TLS_CIPHER_SUITES.to_vec()
+    };
+    (versions, suites)
+}
+
+/// Encodes the application protocols to offer during the TLS handshake
+fn alpn_protocols(protocols: &[String]) -> Vec<Vec<u8>> {
+    protocols.iter().map(|p| p.as_bytes().to_vec()).collect()
+}
+
+/// Parses a PEM-encoded certificate chain and the matching private key
+fn pem_identity(cert: &str, key: &str) -> Result<(Vec<Certificate>, PrivateKey)> {
+    let certs = rustls_pemfile::certs(&mut cert.as_bytes())
+        .context("failed to parse certificate chain")?
+        .into_iter()
+        .map(Certificate)
+        .collect::<Vec<_>>();
+    ensure!(!certs.is_empty(), "certificate chain is empty");
+
+    let mut key = key.as_bytes();
+    loop {
+        match rustls_pemfile::read_one(&mut key).context("failed to parse private key")? {
+            Some(Item::PKCS8Key(key) | Item::ECKey(key) | Item::RSAKey(key)) => {
+                return Ok((certs, PrivateKey(key)))
+            }
+            Some(..) => continue,
+            None => bail!("no private key found"),
+        }
+    }
+}
+
+/// Loads the DER-encoded OCSP response stapled to the keep certificate, if configured
+fn ocsp_response(data: Option<&str>, path: Option<&Path>) -> Result<Option<Vec<u8>>> {
+    match (data, path) {
+        (None, None) => Ok(None),
+        (Some(data), None) => base64::decode(data)
+            .map(Some)
+            .context("failed to decode OCSP response"),
+        (None, Some(path)) => std::fs::read(path)
+            .map(Some)
+            .with_context(|| format!("failed to read OCSP response `{}`", path.display())),
+        (Some(..), Some(..)) => {
+            bail!("at most one of `ocsp_response` and `ocsp_response_path` may be specified")
+        }
+    }
+}
+
+/// Builds the resolver of the certificates presented to clients requesting a server name via SNI
+fn sni_resolver(
+    sni_certs: &HashMap<String, CertAndKey>,
+    identity: Arc<dyn ResolvesServerCert>,
+) -> Result<SniResolver> {
+    let mut resolver = SniResolver::new(identity);
+    for (name, CertAndKey { cert, key }) in sni_certs {
+        pem_identity(cert, key)
+            .and_then(|(certs, key)| resolver.add(name, certs, &key))
+            .with_context(|| format!("invalid certificate for server name `{name}`"))?;
+    }
+    Ok(resolver)
+}
+
+/// Loads the trust anchors for client certificates
+///
+/// The root of the keep certificate chain is used, if no PEM-encoded trust anchors are given.
+fn client_roots(ca: Option<&str>, certs: &[Certificate]) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    if let Some(ca) = ca {
+        let anchors =
+            rustls_pemfile::certs(&mut ca.as_bytes()).context("failed to parse trust anchors")?;
+        let (added, _) = roots.add_parsable_certificates(&anchors);
+        ensure!(added > 0, "no valid trust anchor found");
+    } else {
+        let root = certs.last().context("keep certificate chain is empty")?;
+        roots
+            .add(root)
+            .context("failed to add the root of the keep certificate chain")?;
+    }
+    Ok(roots)
+}
+
+/// Connects a TCP stream to a remote endpoint
+///
+/// If a timeout is given, it applies to each of the resolved addresses, which are tried in turn.
+/// Resolved addresses not allowed by the egress policy are skipped.
+fn connect_tcp(
+    host: &str,
+    port: u16,
+    timeout: Option<Duration>,
+    resolver: &Resolver,
+    egress: Option<&EgressPolicy>,
+) -> Result<std::net::TcpStream> {
+    let mut addrs = resolver.resolve(host, port)?;
+    if let Some(egress) = egress {
+        ensure!(
+            addrs.is_empty() || addrs.iter().any(|&addr| egress.allows(host, addr)),
+            "connecting to `{host}:{port}` is not allowed by the egress policy"
+        );
+        addrs.retain(|&addr| egress.allows(host, addr));
+    }
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => {
+            return std::net::TcpStream::connect(addrs.as_slice())
+                .context("failed to connect to endpoint")
+        }
+    };
+    let mut res = Err(anyhow!("`{host}` resolved to no address"));
+    for addr in addrs {
+        res = std::net::TcpStream::connect_timeout(&addr, timeout).map_err(|e| match e.kind() {
+            ErrorKind::TimedOut => anyhow::Error::new(e).context(format!(
+                "connecting to `{addr}` timed out after {timeout:?}"
+            )),
+            _ => anyhow::Error::new(e).context(format!("failed to connect to `{addr}`")),
+        });
+        if res.is_ok() {
+            break;
+        }
+    }
+    res
+}
+
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+fn set_reuse_port(socket: &Socket) -> std::io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+fn set_reuse_port(_socket: &Socket) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        ErrorKind::Unsupported,
+        "`SO_REUSEPORT` is not supported on this platform",
+    ))
+}
+
+/// Binds a TCP listen socket to an address
+fn bind_tcp(
+    addr: SocketAddr,
+    backlog: i32,
+    options: ListenOptions,
+) -> std::io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // `std::net::TcpListener::bind` enables `SO_REUSEADDR` on Unix, keep doing so by default.
+    if let Some(reuse) = options.reuse_addr.or(cfg!(unix).then_some(true)) {
+        socket.set_reuse_address(reuse)?;
+    }
+    if options.reuse_port {
+        set_reuse_port(&socket)?;
+    }
+    // Linux passes the options on to accepted connections, the TLS listener sets them explicitly.
+    // A read timeout is only set on accepted connections, as it would let `accept` time out.
+    let tcp = TcpOptions {
+        read_timeout: None,
+        ..options.tcp
+    };
+    tcp.apply(&socket)?;
+    socket.bind(&addr.into())?;
+    socket.listen(backlog)?;
+    Ok(socket.into())
+}
+
+/// Binds a TCP listen socket to the first resolved address, which succeeds
+fn listen_tcp(addr: &str, port: u16, options: ListenOptions) -> Result<std::net::TcpListener> {
+    let backlog = options
+        .backlog
+        .unwrap_or(DEFAULT_BACKLOG)
+        .try_into()
+        .context("listen backlog is too large")?;
+    let addrs = (addr, port)
+        .to_socket_addrs()
+        .with_context(|| format!("failed to resolve `{addr}`"))?;
+    let mut res = Err(anyhow!("`{addr}` resolved to no address"));
+    for addr in addrs {
+        res = bind_tcp(addr, backlog, options)
+            .with_context(|| format!("failed to listen on `{addr}`"));
+        if res.is_ok() {
+            break;
+        }
+    }
+    res
+}
+
+fn listen_udp(addr: &str, port: u16) -> Result<(Box<dyn WasiFile>, FileCaps)> {
+    let udp = UdpSocket::bind((addr, port)).context("failed to bind UDP socket")?;
+    Ok((udp::Socket::bound(udp).into(), *DATAGRAM_CAPS))
+}
+
+#[cfg(unix)]
+fn listen_unix(path: &Path) -> Result<(Box<dyn WasiFile>, FileCaps)> {
+    let unix = std::os::unix::net::UnixListener::bind(path)
+        .with_context(|| format!("failed to bind Unix socket `{}`", path.display()))?;
+    let unix = cap_std::os::unix::net::UnixListener::from_std(unix);
+    Ok((wasmtime_wasi::net::Socket::from(unix).into(), *LISTEN_CAPS))
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &Path) -> Result<(Box<dyn WasiFile>, FileCaps, Option<SocketAddr>)> {
+    let unix = std::os::unix::net::UnixStream::connect(path)
+        .with_context(|| format!("failed to connect to Unix socket `{}`", path.display()))?;
+    let unix = cap_std::os::unix::net::UnixStream::from_std(unix);
+    Ok((
+        wasmtime_wasi::net::Socket::from(unix).into(),
+        *CONNECT_CAPS,
+        None,
+    ))
+}
+
+#[cfg(not(unix))]
+fn listen_unix(_path: &Path) -> Result<(Box<dyn WasiFile>, FileCaps)> {
+    bail!("Unix domain sockets are not supported on this platform")
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_path: &Path) -> Result<(Box<dyn WasiFile>, FileCaps, Option<SocketAddr>)> {
+    bail!("Unix domain sockets are not supported on this platform")
+}
+
+fn connect_udp(
+    host: &str,
+    port: u16,
+    resolver: &Resolver,
+    egress: Option<&EgressPolicy>,
+) -> Result<(Box<dyn WasiFile>, FileCaps, Option<SocketAddr>)> {
+    let addr = resolver
+        .resolve(host, port)?
+        .into_iter()
+        .next()
+        .with_context(|| format!("`{host}` resolved to no address"))?;
+    if let Some(egress) = egress {
+        ensure!(
+            egress.allows(host, addr),
+            "connecting to `{host}:{port}` is not allowed by the egress policy"
+        );
+    }
+    let local = match addr {
+        SocketAddr::V4(..) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(..) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    };
+    let udp = UdpSocket::bind(local).context("failed to bind UDP socket")?;
+    udp.connect(addr).context("failed to connect to endpoint")?;
+    Ok((
+        udp::Socket::connected(udp).into(),
+        *DATAGRAM_CAPS,
+        Some(addr),
+    ))
+}
+
 pub fn listen_file(
     file: &ListenFile,
-    certs: Vec<Certificate>,
-    key: &Zeroizing<Vec<u8>>,
+    certs: &[Certificate],
+    identity: Arc<dyn ResolvesServerCert>,
 ) -> Result<(Box<dyn WasiFile>, FileCaps)> {
     let (addr, port) = match file {
         ListenFile::Tcp { addr, port, .. } | ListenFile::Tls { addr, port, .. } => (addr, port),
+        ListenFile::Udp { addr, port, .. } => return listen_udp(addr, *port),
+        ListenFile::Unix { path, .. } => return listen_unix(path),
     };
-    let tcp = std::net::TcpListener::bind((addr.as_str(), *port))?;
+    let options = ListenOptions::from(file);
+    let tcp = listen_tcp(addr, *port, options)?;
     let tcp = TcpListener::from_std(tcp);
     let file = match file {
-        ListenFile::Tcp { .. } => wasmtime_wasi::net::Socket::from(tcp).into(),
-        ListenFile::Tls { .. } => {
-            let cfg = rustls::ServerConfig::builder()
-                .with_cipher_suites(DEFAULT_TLS_CIPHER_SUITES.deref())
+        ListenFile::Tcp { .. } | ListenFile::Udp { .. } | ListenFile::Unix { .. } => {
+            wasmtime_wasi::net::Socket::from(tcp).into()
+        }
+        ListenFile::Tls {
+            alpn,
+            min_version,
+            cipher_suites,
+            client_auth,
+            client_ca,
+            max_connections_per_second,
+            max_concurrent_connections,
+            drain_timeout_ms,
+            sni_certs,
+            allow_ips,
+            deny_ips,
+            ocsp_response: ocsp,
+            ocsp_response_path,
+            ..
+        } => {
+            let identity: Arc<dyn ResolvesServerCert> =
+                match ocsp_response(ocsp.as_deref(), ocsp_response_path.as_deref())? {
+                    Some(ocsp) => Arc::new(StapledCert::new(identity, ocsp)),
+                    None => identity,
+                };
+            let (versions, suites) = tls_parameters(*min_version, cipher_suites);
+            let verifier = if *client_auth {
+                let roots = client_roots(client_ca.as_deref(), certs)
+                    .context("failed to load client trust anchors")?;
+                AllowAnyAuthenticatedClient::new(roots)
+            } else {
+                NoClientAuth::new()
+            };
+            let mut cfg = rustls::ServerConfig::builder()
+                .with_cipher_suites(&suites)
                 .with_kx_groups(DEFAULT_TLS_KX_GROUPS.deref())
-                .with_protocol_versions(DEFAULT_TLS_PROTOCOL_VERSIONS.deref())?
-                .with_no_client_auth() // TODO: https://github.com/enarx/enarx/issues/1547
-                .with_single_cert(certs, PrivateKey(key.deref().clone()))?;
-            tls::Listener::new(tcp, Arc::new(cfg)).into()
+                .with_protocol_versions(versions)
+                .context("invalid TLS configuration")?
+                .with_client_cert_verifier(verifier)
+                .with_cert_resolver(identity.clone());
+            cfg.alpn_protocols = alpn_protocols(alpn);
+            let limits =
+                ConnectionLimits::new(*max_connections_per_second, *max_concurrent_connections);
+            let mut listener = tls::Listener::new(tcp, Arc::new(cfg), options.tcp, limits)
+                .with_ip_filter(IpFilter::new(allow_ips.clone(), deny_ips.clone()));
+            if let Some(ms) = drain_timeout_ms {
+                listener = listener.with_drain_timeout(Duration::from_millis(*ms));
+            }
+            if sni_certs.is_empty() {
+                listener.into()
+            } else {
+                let resolver = sni_resolver(sni_certs, identity)?;
+                listener.with_sni_resolver(Arc::new(resolver)).into()
+            }
         }
     };
     Ok((file, *LISTEN_CAPS))
 }
 
+/// Connects a stream or datagram socket and returns it along with the address of the peer
+///
+/// Unix domain sockets have no peer address and are not subject to the egress policy.
 pub fn connect_file(
     file: &ConnectFile,
+    options: ConnectOptions,
     certs: Vec<Certificate>,
     key: &Zeroizing<Vec<u8>>,
-) -> Result<(Box<dyn WasiFile>, FileCaps)> {
-    let (host, port) = match &file {
-        ConnectFile::Tcp { host, port, .. } | ConnectFile::Tls { host, port, .. } => (host, port),
-    };
-    let tcp = match (host.as_str(), *port) {
-        ("localhost", port) => std::net::TcpStream::connect(SocketAddr::V4(SocketAddrV4::new(
-            Ipv4Addr::LOCALHOST,
+    resolver: &Resolver,
+    egress: Option<&EgressPolicy>,
+) -> Result<(Box<dyn WasiFile>, FileCaps, Option<SocketAddr>)> {
+    let (host, port, timeout) = match &file {
+        ConnectFile::Tcp {
+            host,
             port,
-        ))),
-        // TODO: Handle DNS in the keep
-        // https://github.com/enarx/enarx/issues/1511
-        addr => std::net::TcpStream::connect(addr),
-    }
-    .map(TcpStream::from_std)
-    .context("failed to connect to endpoint")?;
+            connect_timeout_ms,
+            ..
+        }
+        | ConnectFile::Tls {
+            host,
+            port,
+            connect_timeout_ms,
+            ..
+        } => (host, port, connect_timeout_ms.map(Duration::from_millis)),
+        ConnectFile::Udp { host, port, .. } => return connect_udp(host, *port, resolver, egress),
+        ConnectFile::Unix { path, .. } => return connect_unix(path),
+    };
+    let tcp = connect_tcp(host, *port, timeout, resolver, egress)?;
+    let peer = tcp.peer_addr().context("failed to get peer address")?;
+    options
+        .tcp
+        .apply(&tcp)
+        .context("failed to set TCP socket options")?;
+    let tcp = TcpStream::from_std(tcp);
     let file = match file {
-        ConnectFile::Tcp { .. } => wasmtime_wasi::net::Socket::from(tcp).into(),
-        ConnectFile::Tls { .. } => {
+        ConnectFile::Tcp { .. } | ConnectFile::Udp { .. } | ConnectFile::Unix { .. } => {
+            wasmtime_wasi::net::Socket::from(tcp).into()
+        }
+        ConnectFile::Tls {
+            alpn,
+            min_version,
+            cipher_suites,
+            client_cert,
+            client_key,
+            ..
+        } => {
+            // Authenticate with the keep identity, unless a client identity is configured
+            let (certs, key) = match (client_cert, client_key) {
+                (Some(cert), Some(key)) => {
+                    pem_identity(cert, key).context("invalid client identity")?
+                }
+                (None, None) => (certs, PrivateKey(key.deref().clone())),
+                _ => bail!("`client_cert` and `client_key` must be specified together"),
+            };
+            let (versions, suites) = tls_parameters(*min_version, cipher_suites);
             let mut server_roots = RootCertStore::empty();
             server_roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(
                 |ta| {
@@ -105,15 +700,271 @@ pub fn connect_file(
                     )
                 },
             ));
-            let cfg = rustls::ClientConfig::builder()
-                .with_cipher_suites(DEFAULT_TLS_CIPHER_SUITES.deref())
+            let mut cfg = rustls::ClientConfig::builder()
+                .with_cipher_suites(&suites)
                 .with_kx_groups(DEFAULT_TLS_KX_GROUPS.deref())
-                .with_protocol_versions(DEFAULT_TLS_PROTOCOL_VERSIONS.deref())?
+                .with_protocol_versions(versions)
+                .context("invalid TLS configuration")?
                 .with_root_certificates(server_roots)
-                .with_single_cert(certs, PrivateKey(key.deref().clone()))?;
+                .with_single_cert(certs, key)?;
+            cfg.alpn_protocols = alpn_protocols(alpn);
+            if let Some(storage) = options.session_storage() {
+                cfg.session_storage = storage;
+            }
 
             tls::Stream::connect(tcp, host, Arc::new(cfg))?.into()
         }
     };
-    Ok((file, *CONNECT_CAPS))
+    Ok((file, *CONNECT_CAPS, Some(peer)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tls_parameters_default() {
+        let (versions, suites) = tls_parameters(Default::default(), &[]);
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![rustls::ProtocolVersion::TLSv1_3]
+        );
+        assert_eq!(suites.len(), DEFAULT_TLS_CIPHER_SUITES.len());
+
+        let (versions, suites) = tls_parameters(TlsVersion::Tls12, &[]);
+        assert_eq!(versions.len(), 2);
+        assert_eq!(
+            suites.len(),
+            DEFAULT_TLS_CIPHER_SUITES.len() + DEFAULT_TLS12_CIPHER_SUITES.len()
+        );
+    }
+
+    #[test]
+    fn connect_timeout() {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        connect_tcp(
+            "localhost",
+            port,
+            Some(Duration::from_secs(5)),
+            &Resolver::default(),
+            None,
+        )
+        .unwrap();
+
+        // A non-routable address never completes the handshake.
+        let start = std::time::Instant::now();
+        assert!(connect_tcp(
+            "10.255.255.1",
+            80,
+            Some(Duration::from_millis(100)),
+            &Resolver::default(),
+            None
+        )
+        .is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn connect_peer_addr() {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let file = ConnectFile::Tcp {
+            name: None,
+            caps: None,
+            host: "localhost".into(),
+            port: addr.port(),
+            connect_timeout_ms: None,
+            nodelay: false,
+            keepalive: None,
+        };
+        let (_, _, peer) = connect_file(
+            &file,
+            Default::default(),
+            vec![],
+            &Zeroizing::new(vec![]),
+            &Resolver::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(peer, Some(addr));
+    }
+
+    #[test]
+    fn connect_resolver() {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let file = ConnectFile::Tcp {
+            name: None,
+            caps: None,
+            host: "service.internal".into(),
+            port: addr.port(),
+            connect_timeout_ms: None,
+            nodelay: false,
+            keepalive: None,
+        };
+        let resolver = Resolver::default().with_host("service.internal", [addr.ip()]);
+        let (_, _, peer) = connect_file(
+            &file,
+            Default::default(),
+            vec![],
+            &Zeroizing::new(vec![]),
+            &resolver,
+            None,
+        )
+        .unwrap();
+        assert_eq!(peer, Some(addr));
+    }
+
+    #[test]
+    fn connect_egress() {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let egress = EgressPolicy::new([format!("127.0.0.1:{port}")]).unwrap();
+        connect_tcp("localhost", port, None, &Resolver::default(), Some(&egress)).unwrap();
+
+        // A name pattern does not allow connecting to a loopback address.
+        let err = connect_tcp(
+            "localhost",
+            port,
+            None,
+            &Resolver::default(),
+            Some(&EgressPolicy::new([format!("localhost:{port}")]).unwrap()),
+        )
+        .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("not allowed by the egress policy"),
+            "{err:#}"
+        );
+
+        let err = connect_tcp(
+            "localhost",
+            port + 1,
+            None,
+            &Resolver::default(),
+            Some(&egress),
+        )
+        .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("not allowed by the egress policy"),
+            "{err:#}"
+        );
+        let err =
+            connect_udp("127.0.0.1", port + 1, &Resolver::default(), Some(&egress)).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("not allowed by the egress policy"),
+            "{err:#}"
+        );
+        assert!(connect_tcp(
+            "localhost",
+            port,
+            None,
+            &Resolver::default(),
+            Some(&EgressPolicy::default())
+        )
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn connect_unix_socket() {
+        use futures::executor::block_on;
+        use std::io::{IoSlice, IoSliceMut, Read, Write};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("echo.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 5];
+            stream.read_exact(&mut buf).unwrap();
+            stream.write_all(&buf).unwrap();
+        });
+
+        let file = ConnectFile::Unix {
+            name: "echo".try_into().unwrap(),
+            caps: None,
+            path,
+        };
+        let (mut file, _, peer) = connect_file(
+            &file,
+            Default::default(),
+            vec![],
+            &Zeroizing::new(vec![]),
+            &Resolver::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(peer, None);
+        let n = block_on(file.write_vectored(&[IoSlice::new(b"hello")])).unwrap();
+        assert_eq!(n, 5);
+        let mut buf = [0; 5];
+        let n = block_on(file.read_vectored(&mut [IoSliceMut::new(&mut buf)])).unwrap();
+        assert_eq!(&buf[..n as _], b"hello");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn listen_options() {
+        let options = ListenOptions {
+            backlog: Some(1024),
+            reuse_addr: None,
+            reuse_port: cfg!(unix),
+            ..Default::default()
+        };
+        let listener = listen_tcp("127.0.0.1", 0, options).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Sockets with `SO_REUSEPORT` can share a port.
+        if cfg!(unix) {
+            listen_tcp("127.0.0.1", port, options).unwrap();
+        }
+
+        let err = listen_tcp("127.0.0.1", port, ListenOptions::default()).unwrap_err();
+        assert!(
+            format!("{err:#}").contains(&format!("127.0.0.1:{port}")),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn tcp_options() {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let tcp = connect_tcp("localhost", port, None, &Resolver::default(), None).unwrap();
+        let options = TcpOptions {
+            nodelay: true,
+            keepalive: Some(TcpKeepalive {
+                idle_secs: Some(42),
+                interval_secs: cfg!(target_os = "linux").then_some(7),
+                count: cfg!(target_os = "linux").then_some(3),
+            }),
+            read_timeout: Some(Duration::from_secs(5)),
+        };
+        options.apply(&tcp).unwrap();
+
+        let socket = SockRef::from(&tcp);
+        assert!(socket.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.read_timeout().unwrap(), Some(Duration::from_secs(5)));
+        #[cfg(not(windows))]
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(42));
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(socket.keepalive_interval().unwrap(), Duration::from_secs(7));
+            assert_eq!(socket.keepalive_retries().unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn session_storage() {
+        assert!(ConnectOptions::default().session_storage().is_none());
+
+        let options = ConnectOptions {
+            session_resumption: true,
+            ..Default::default()
+        };
+        let a = options.session_storage().unwrap();
+        let b = options.session_storage().unwrap();
+        assert_eq!(Arc::as_ptr(&a) as *const u8, Arc::as_ptr(&b) as *const u8);
+    }
 }
@@ -0,0 +1,370 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Resolution of the names of connect destinations on the host
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+/// Time to wait for the response of the DNS server
+const DNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum size of a DNS message over UDP, which is accepted
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// Type of DNS resource records holding IPv4 addresses
+const TYPE_A: u16 = 1;
+
+/// Type of DNS resource records holding IPv6 addresses
+const TYPE_AAAA: u16 = 28;
+
+/// Class of DNS resource records of the Internet
+const CLASS_IN: u16 = 1;
+
+/// Addresses resolved by the DNS server, which are valid until `expires`
+#[derive(Clone, Debug)]
+struct Cached {
+    addrs: Vec<IpAddr>,
+    expires: Instant,
+}
+
+/// Resolver of the names of connect destinations
+///
+/// Names are resolved from the static hosts map first. Other names are resolved by querying
+/// the DNS server, if one is set, and by the resolver of the host otherwise. Responses of the
+/// DNS server are cached for the shortest TTL of their records and the cache is shared by all
+/// clones of the resolver.
+#[derive(Clone, Debug, Default)]
+pub struct Resolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+    server: Option<SocketAddr>,
+    cache: Arc<Mutex<HashMap<String, Cached>>>,
+}
+
+impl Resolver {
+    /// Resolve names, which are not in the hosts map, by querying the DNS server at `server`
+    pub fn with_server(mut self, server: SocketAddr) -> Self {
+        self.server = Some(server);
+        self
+    }
+
+    /// Resolve the name `host` to `addrs` without querying DNS
+    pub fn with_host(
+        mut self,
+        host: impl AsRef<str>,
+        addrs: impl IntoIterator<Item = IpAddr>,
+    ) -> Self {
+        self.hosts
+            .entry(normalize(host.as_ref()))
+            .or_default()
+            .extend(addrs);
+        self
+    }
+
+    /// Resolves `host` to the addresses of a remote endpoint listening on `port`
+    pub fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+        let name = normalize(host);
+        let addrs = if let Some(addrs) = self.hosts.get(&name) {
+            addrs.clone()
+        } else if name == "localhost" {
+            vec![Ipv4Addr::LOCALHOST.into()]
+        } else if let Some(server) = self.server {
+            self.lookup(server, &name)
+                .with_context(|| format!("failed to resolve `{host}`"))?
+        } else {
+            // TODO: Handle DNS in the keep
+            // https://github.com/enarx/enarx/issues/1511
+            return Ok((host, port)
+                .to_socket_addrs()
+                .with_context(|| format!("failed to resolve `{host}`"))?
+                .collect());
+        };
+        Ok(addrs
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect())
+    }
+
+    /// Looks `name` up in the cache or queries the DNS server for its IPv4 and IPv6 addresses
+    fn lookup(&self, server: SocketAddr, name: &str) -> Result<Vec<IpAddr>> {
+        let now = Instant::now();
+        {
+            let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            match cache.get(name) {
+                Some(cached) if cached.expires > now => return Ok(cached.addrs.clone()),
+                Some(..) => {
+                    cache.remove(name);
+                }
+                None => {}
+            }
+        }
+
+        let (mut addrs, mut ttl) = (vec![], u32::MAX);
+        let mut error = None;
+        for qtype in [TYPE_A, TYPE_AAAA] {
+            match query(server, name, qtype) {
+                Ok(records) => {
+                    for (addr, record_ttl) in records {
+                        addrs.push(addr);
+                        ttl = ttl.min(record_ttl);
+                    }
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        if addrs.is_empty() {
+            return Err(error.unwrap_or_else(|| anyhow!("`{name}` resolved to no address")));
+        }
+
+        let cached = Cached {
+            addrs: addrs.clone(),
+            expires: now + Duration::from_secs(ttl.into()),
+        };
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.into(), cached);
+        Ok(addrs)
+    }
+}
+
+/// Normalizes a DNS name, which is case-insensitive and may be fully qualified
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Queries the DNS server at `server` for the records of type `qtype` of `name`
+///
+/// Returns the addresses of the records along with their TTL in seconds.
+fn query(server: SocketAddr, name: &str, qtype: u16) -> Result<Vec<(IpAddr, u32)>> {
+    let id = rand::random::<u16>();
+    let local = match server {
+        SocketAddr::V4(..) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(..) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    };
+    let socket = UdpSocket::bind(local).context("failed to bind UDP socket")?;
+    socket
+        .connect(server)
+        .with_context(|| format!("failed to connect to DNS server `{server}`"))?;
+    socket
+        .set_read_timeout(Some(DNS_TIMEOUT))
+        .context("failed to set DNS timeout")?;
+    socket
+        .send(&encode_query(id, name, qtype)?)
+        .context("failed to send DNS query")?;
+
+    let mut buf = [0; MAX_MESSAGE_SIZE];
+    loop {
+        let n = socket
+            .recv(&mut buf)
+            .context("failed to receive DNS response")?;
+        // Responses to other queries, e.g. late ones of a previous socket, are ignored.
+        if buf[..n].starts_with(&id.to_be_bytes()) {
+            return decode_response(&buf[..n], qtype);
+        }
+    }
+}
+
+/// Encodes a recursive query for the records of type `qtype` of `name`
+fn encode_query(id: u16, name: &str, qtype: u16) -> Result<Vec<u8>> {
+    let mut msg = Vec::with_capacity(12 + name.len() + 6);
+    msg.extend_from_slice(&id.to_be_bytes());
+    // Flags with recursion desired, followed by one question and no records
+    msg.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        ensure!(
+            !label.is_empty() && label.len() < 64,
+            "invalid DNS name `{name}`"
+        );
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend_from_slice(&qtype.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(msg)
+}
+
+/// Reader of the fields of a DNS message
+struct Reader<'a> {
+    msg: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .msg
+            .get(self.pos..self.pos + n)
+            .context("DNS response is truncated")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        self.bytes(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Skips a name, which ends with an empty label or a pointer to another name
+    fn skip_name(&mut self) -> Result<()> {
+        loop {
+            match self.bytes(1)?[0] {
+                0 => return Ok(()),
+                len if len & 0xc0 == 0xc0 => return self.bytes(1).map(|_| ()),
+                len => self.bytes(len.into())?,
+            };
+        }
+    }
+}
+
+/// Decodes the addresses in the records of type `qtype` of a DNS response
+///
+/// Other records, e.g. the `CNAME` records leading to the addresses, are skipped.
+/// A response for a name, which does not exist, contains no addresses.
+fn decode_response(msg: &[u8], qtype: u16) -> Result<Vec<(IpAddr, u32)>> {
+    let mut reader = Reader { msg, pos: 0 };
+    let _id = reader.u16()?;
+    let flags = reader.u16()?;
+    ensure!(flags & 0x8000 != 0, "DNS response is a query");
+    ensure!(flags & 0x0200 == 0, "DNS response is truncated");
+    match flags & 0x000f {
+        0 => {}
+        // The name does not exist.
+        3 => return Ok(vec![]),
+        rcode => bail!("DNS server failed with response code {rcode}"),
+    }
+    let questions = reader.u16()?;
+    let answers = reader.u16()?;
+    let _authorities = reader.u16()?;
+    let _additional = reader.u16()?;
+    for _ in 0..questions {
+        reader.skip_name()?;
+        reader.bytes(4)?;
+    }
+
+    let mut addrs = vec![];
+    for _ in 0..answers {
+        reader.skip_name()?;
+        let rtype = reader.u16()?;
+        let class = reader.u16()?;
+        let ttl = reader.u32()?;
+        let len = reader.u16()?;
+        let data = reader.bytes(len.into())?;
+        if rtype != qtype || class != CLASS_IN {
+            continue;
+        }
+        let addr = match (rtype, data.len()) {
+            (TYPE_A, 4) => IpAddr::from(<[u8; 4]>::try_from(data)?),
+            (TYPE_AAAA, 16) => IpAddr::from(<[u8; 16]>::try_from(data)?),
+            _ => bail!("DNS response contains a malformed address"),
+        };
+        addrs.push((addr, ttl));
+    }
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::thread;
+
+    /// Answers queries for A records with `addr` and a TTL of `ttl` until no query is received
+    /// for a second, returning the amount of queries received
+    fn serve(socket: UdpSocket, addr: Ipv4Addr, ttl: u32) -> usize {
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let mut queries = 0;
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+            queries += 1;
+            let query = &buf[..n];
+            let qtype = u16::from_be_bytes([query[n - 4], query[n - 3]]);
+            let answers = u16::from(qtype == TYPE_A);
+
+            let mut msg = query[..2].to_vec();
+            msg.extend_from_slice(&[0x81, 0x80, 0, 1]);
+            msg.extend_from_slice(&answers.to_be_bytes());
+            msg.extend_from_slice(&[0, 0, 0, 0]);
+            msg.extend_from_slice(&query[12..]);
+            if answers > 0 {
+                // The name is a pointer to the one of the question.
+                msg.extend_from_slice(&[0xc0, 12]);
+                msg.extend_from_slice(&TYPE_A.to_be_bytes());
+                msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+                msg.extend_from_slice(&ttl.to_be_bytes());
+                msg.extend_from_slice(&4u16.to_be_bytes());
+                msg.extend_from_slice(&addr.octets());
+            }
+            socket.send_to(&msg, peer).unwrap();
+        }
+        queries
+    }
+
+    #[test]
+    fn hosts() {
+        let resolver =
+            Resolver::default().with_host("Service.Internal", [Ipv4Addr::LOCALHOST.into()]);
+        assert_eq!(
+            resolver.resolve("service.internal.", 80).unwrap(),
+            [SocketAddr::from((Ipv4Addr::LOCALHOST, 80))]
+        );
+        assert_eq!(
+            resolver.resolve("::1", 80).unwrap(),
+            [SocketAddr::from((Ipv6Addr::LOCALHOST, 80))]
+        );
+    }
+
+    #[test]
+    fn server() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let server = socket.local_addr().unwrap();
+        let addr = Ipv4Addr::new(192, 0, 2, 1);
+        let dns = thread::spawn(move || serve(socket, addr, 60));
+
+        let resolver = Resolver::default().with_server(server);
+        for _ in 0..2 {
+            assert_eq!(
+                resolver.clone().resolve("example.com", 443).unwrap(),
+                [SocketAddr::from((addr, 443))]
+            );
+        }
+        // The second resolution is answered from the cache.
+        assert_eq!(dns.join().unwrap(), 2);
+    }
+
+    #[test]
+    fn expired() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let server = socket.local_addr().unwrap();
+        let dns = thread::spawn(move || serve(socket, Ipv4Addr::new(192, 0, 2, 1), 0));
+
+        let resolver = Resolver::default().with_server(server);
+        resolver.resolve("example.com", 443).unwrap();
+        resolver.resolve("example.com", 443).unwrap();
+        assert_eq!(dns.join().unwrap(), 4);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(encode_query(0, "exa..mple.com", TYPE_A).is_err());
+        // A response of only a header, which claims to contain an answer
+        let msg = [0, 0, 0x81, 0x80, 0, 0, 0, 1, 0, 0, 0, 0];
+        assert!(decode_response(&msg, TYPE_A).is_err());
+        // A response for a name, which does not exist
+        let msg = [0, 0, 0x81, 0x83, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(decode_response(&msg, TYPE_A).unwrap(), []);
+    }
+}
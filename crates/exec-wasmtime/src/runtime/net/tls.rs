@@ -2,10 +2,16 @@
 
 //! A WasiFile for transparent TLS
 
+use super::filter::IpFilter;
+use super::limit::{ConnectionLimits, Limited, Permit};
+use super::TcpOptions;
+
 use std::any::Any;
+use std::collections::HashMap;
 use std::io;
 use std::io::{IoSlice, IoSliceMut, Read, Write};
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, Instant};
 
 use cap_std::net::{Shutdown, TcpListener as CapListener, TcpStream as CapStream};
 #[cfg(windows)]
@@ -13,16 +19,27 @@ use io_extras::os::windows::AsRawHandleOrSocket;
 #[cfg(unix)]
 use io_lifetimes::AsFd;
 
-use rustls::{ClientConfig, ClientConnection, Connection, ServerConfig, ServerConnection};
-use wasi_common::file::{FdFlags, FileType, RiFlags, RoFlags, SdFlags, SiFlags};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::{any_supported_type, CertifiedKey};
+use rustls::{
+    Certificate, ClientConfig, ClientConnection, Connection, PrivateKey, ServerConfig,
+    ServerConnection,
+};
+use wasi_common::file::{Advice, FdFlags, FileType, RiFlags, RoFlags, SdFlags, SiFlags};
+use wasi_common::sched::subscription::{RwEventFlags, Subscription};
+use wasi_common::sched::{Poll, WasiSched};
 use wasi_common::{Context, Error, ErrorExt, ErrorKind, WasiFile};
 #[cfg(unix)]
 use wasmtime_wasi::net::get_fd_flags;
 use wasmtime_wasi::net::is_read_write;
 
+/// Time an accepted connection is drained for by default, when it is closed
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
 fn errmap(error: io::Error) -> Error {
     match error.kind() {
-        io::ErrorKind::WouldBlock => ErrorKind::WouldBlk.into(),
+        // A read timing out on a socket with `SO_RCVTIMEO` reports `TimedOut` on Windows.
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ErrorKind::WouldBlk.into(),
         io::ErrorKind::InvalidInput => ErrorKind::Inval.into(),
         io::ErrorKind::Unsupported => ErrorKind::Notsup.into(),
         io::ErrorKind::InvalidData => ErrorKind::Inval.into(),
@@ -30,6 +47,58 @@ fn errmap(error: io::Error) -> Error {
     }
 }
 
+/// Maps an error of a TLS handshake, telling apart why it failed
+///
+/// A rejected certificate is reported as `EPERM` and a lack of common protocol parameters,
+/// e.g. TLS versions, as `ENOTSUP`. Other failures, e.g. of the transport, are reported as `EIO`.
+fn handshake_errmap(error: io::Error) -> Error {
+    // `AlertDescription` is not re-exported at the top level of rustls 0.20.
+    use rustls::internal::msgs::enums::AlertDescription as Alert;
+
+    let tls = match error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<rustls::Error>())
+    {
+        Some(tls) => tls,
+        None => {
+            return match error.kind() {
+                // Pending I/O of a nonblocking or timed out handshake is no failure of the handshake
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => errmap(error),
+                _ => Error::io().context(format!("TLS handshake failed in transport: {error}")),
+            };
+        }
+    };
+    match tls {
+        rustls::Error::NoCertificatesPresented
+        | rustls::Error::InvalidCertificateEncoding
+        | rustls::Error::InvalidCertificateSignatureType
+        | rustls::Error::InvalidCertificateSignature
+        | rustls::Error::InvalidCertificateData(..)
+        | rustls::Error::InvalidSct(..)
+        | rustls::Error::AlertReceived(
+            Alert::BadCertificate
+            | Alert::UnsupportedCertificate
+            | Alert::CertificateRevoked
+            | Alert::CertificateExpired
+            | Alert::CertificateUnknown
+            | Alert::UnknownCA
+            | Alert::AccessDenied
+            | Alert::CertificateRequired,
+        ) => Error::perm().context(format!("TLS handshake rejected certificate: {tls}")),
+        rustls::Error::PeerIncompatibleError(..)
+        | rustls::Error::NoApplicationProtocol
+        | rustls::Error::AlertReceived(
+            Alert::ProtocolVersion
+            | Alert::HandshakeFailure
+            | Alert::InsufficientSecurity
+            | Alert::NoApplicationProtocol,
+        ) => Error::not_supported().context(format!(
+            "TLS handshake found no common protocol parameters: {tls}"
+        )),
+        _ => Error::io().context(format!("TLS handshake failed: {tls}")),
+    }
+}
+
 trait IOAsync {
     fn complete_io_async<T>(&mut self, io: &mut T) -> io::Result<(usize, usize)>
     where
@@ -103,6 +172,29 @@ pub struct Stream {
     tcp: CapStream,
     tls: Connection,
     nonblocking: bool,
+    /// Plaintext, which was peeked, but not yet read
+    peeked: Vec<u8>,
+    /// Amount of plaintext decrypted by the TLS connection, but not yet read
+    buffered: usize,
+    /// Whether a `close_notify` alert was sent to the peer
+    closed: bool,
+    /// Reservation of an accepted connection, which counts towards the concurrency limit
+    _permit: Option<Permit>,
+    /// Maximum time the connection is drained for, when it is dropped, see [Stream::drain]
+    drain_timeout: Duration,
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if !self.drain_timeout.is_zero() && self.drain().is_ok() {
+            return;
+        }
+        // A peer, which stopped reading, must not block the drop forever, so the alert is only
+        // sent as far as the send buffer of the socket allows. The socket is closed right after,
+        // so there is nothing left to do about an error.
+        let _ = self.tcp.set_nonblocking(true);
+        let _ = self.close_notify();
+    }
 }
 
 impl From<Stream> for Box<dyn WasiFile> {
@@ -130,6 +222,11 @@ impl Stream {
             tcp,
             tls,
             nonblocking: false, // this is only valid under assumption that this executable has opened the socket
+            peeked: vec![],
+            buffered: 0,
+            closed: false,
+            _permit: None,
+            drain_timeout: Duration::ZERO,
         };
         stream
             .complete_io()
@@ -137,13 +234,122 @@ impl Stream {
         Ok(stream)
     }
 
-    fn complete_io(&mut self) -> Result<(), Error> {
-        if self.nonblocking {
-            self.tls.complete_io_async(&mut self.tcp).map_err(errmap)?;
+    /// Returns the certificate chain presented by the peer during the handshake, if any
+    ///
+    /// The chain is not available until the handshake of a nonblocking stream completed.
+    pub fn peer_certificates(&self) -> Option<&[rustls::Certificate]> {
+        self.tls.peer_certificates()
+    }
+
+    /// Returns the application protocol negotiated during the handshake, if any
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.tls.alpn_protocol()
+    }
+
+    /// Returns the amount of plaintext, which can be read without receiving further TLS records
+    ///
+    /// The socket is not readable, while this plaintext is buffered, so polling it would block.
+    pub fn ready_plaintext(&self) -> usize {
+        self.peeked.len() + self.buffered
+    }
+
+    /// Sends a `close_notify` alert to the peer, unless it was sent already
+    ///
+    /// The alert and any TLS records still pending are flushed to the socket. A nonblocking
+    /// socket may fail to do so, if its send buffer is full, while a blocking one waits for
+    /// the peer to read.
+    fn close_notify(&mut self) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        self.tls.send_close_notify();
+        while self.tls.wants_write() {
+            self.tls.write_tls(&mut self.tcp)?;
+        }
+        self.tcp.flush()
+    }
+
+    /// Drains the connection, waiting at most for the drain timeout
+    ///
+    /// The pending TLS records and the `close_notify` alert are flushed and the write half of
+    /// the socket is shut down, so that the peer receives all data followed by a FIN. The data
+    /// still sent by the peer is discarded until it closes its write half as well, as closing
+    /// a socket with unread data resets the connection.
+    fn drain(&mut self) -> io::Result<()> {
+        let deadline = Instant::now() + self.drain_timeout;
+        self.tcp.set_nonblocking(false)?;
+        self.tcp.set_write_timeout(Some(self.drain_timeout))?;
+        self.close_notify()?;
+        self.tcp.shutdown(Shutdown::Write)?;
+        let mut buf = [0; 4096];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            self.tcp.set_read_timeout(Some(remaining))?;
+            if self.tcp.read(&mut buf)? == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Completes outstanding I/O, returning the amount of TLS bytes read and written
+    ///
+    /// Failures of the handshake are mapped by [handshake_errmap].
+    fn complete_io(&mut self) -> Result<(usize, usize), Error> {
+        let map = if self.tls.is_handshaking() {
+            handshake_errmap
+        } else {
+            errmap
+        };
+        let res = if self.nonblocking {
+            self.tls.complete_io_async(&mut self.tcp).map_err(map)
         } else {
-            self.tls.complete_io(&mut self.tcp).map_err(errmap)?;
+            self.tls.complete_io(&mut self.tcp).map_err(map)
+        };
+        self.update_buffered();
+        res
+    }
+
+    /// Updates the amount of plaintext decrypted by the TLS connection, but not yet read
+    fn update_buffered(&mut self) {
+        // The received TLS records were processed already, so this only queries the state.
+        self.buffered = self
+            .tls
+            .process_new_packets()
+            .map(|state| state.plaintext_bytes_to_read())
+            .unwrap_or_default();
+    }
+
+    /// Reads decrypted plaintext from the connection, blocking in blocking mode
+    ///
+    /// Partial TLS records are buffered until the remainder arrives. `0` is only returned
+    /// once the peer closed the connection with a `close_notify` alert, a connection
+    /// closed without it is reported as an error.
+    fn read_plaintext(&mut self, bufs: &mut [IoSliceMut<'_>]) -> Result<usize, Error> {
+        loop {
+            let (rdlen, wrlen) = self.complete_io()?;
+            match self.tls.reader().read_vectored(bufs) {
+                Ok(n) => {
+                    self.update_buffered();
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if self.nonblocking {
+                        return Err(ErrorKind::WouldBlk.into());
+                    } else if rdlen == 0 && wrlen == 0 {
+                        // A blocking read of no TLS bytes means the socket reached EOF
+                        return Err(Error::io().context("TLS stream truncated"));
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(Error::io().context("TLS stream truncated"))
+                }
+                Err(e) => return Err(errmap(e)),
+            }
         }
-        Ok(())
     }
 }
 
@@ -167,6 +373,14 @@ impl WasiFile for Stream {
         Ok(FileType::SocketStream)
     }
 
+    async fn advise(&mut self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn allocate(&mut self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+
     #[cfg(unix)]
     async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
         let fdflags = get_fd_flags(&self.tcp)?;
@@ -192,39 +406,56 @@ impl WasiFile for Stream {
     }
 
     async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
-        loop {
-            self.complete_io()?;
-            match self.tls.reader().read_vectored(bufs) {
-                Ok(n) => return n.try_into().map_err(|e| Error::range().context(e)),
-                Err(e) if !self.nonblocking && e.kind() == io::ErrorKind::WouldBlock => {}
-                Err(e) => return Err(errmap(e)),
-            }
-        }
+        let n = if self.peeked.is_empty() {
+            self.read_plaintext(bufs)?
+        } else {
+            let n = self.peeked.as_slice().read_vectored(bufs)?;
+            self.peeked.drain(..n);
+            n
+        };
+        n.try_into().map_err(|e| Error::range().context(e))
     }
 
     async fn write_vectored<'a>(&mut self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
-        match self.tls.writer().write_vectored(bufs) {
-            Ok(n) => {
-                self.complete_io()?;
-                n.try_into().map_err(|e| Error::range().context(e))
+        let n = loop {
+            let n = self.tls.writer().write_vectored(bufs).map_err(errmap)?;
+            if n > 0 || bufs.iter().all(|b| b.is_empty()) {
+                break n;
             }
-            Err(e) => Err(errmap(e)),
-        }
+            // The TLS send buffer is full, flush it to the socket before retrying
+            let (_, wrlen) = self.complete_io()?;
+            if self.nonblocking && wrlen == 0 {
+                return Err(ErrorKind::WouldBlk.into());
+            }
+        };
+        // The plaintext is accepted by now, so a failure to flush it must not be reported,
+        // since the guest would write it again. Persistent errors surface on the next call.
+        let _ignored = self.complete_io();
+        n.try_into().map_err(|e| Error::range().context(e))
     }
 
-    async fn peek(&mut self, _buf: &mut [u8]) -> Result<u64, Error> {
-        // TODO: implement
-        // https://github.com/enarx/enarx/issues/2241
-        Err(Error::badf())
+    async fn peek(&mut self, buf: &mut [u8]) -> Result<u64, Error> {
+        if self.peeked.is_empty() && !buf.is_empty() {
+            let mut peeked = vec![0; buf.len()];
+            let n = self.read_plaintext(&mut [IoSliceMut::new(&mut peeked)])?;
+            peeked.truncate(n);
+            self.peeked = peeked;
+        }
+        let n = buf.len().min(self.peeked.len());
+        buf[..n].copy_from_slice(&self.peeked[..n]);
+        n.try_into().map_err(|e| Error::range().context(e))
     }
 
     async fn num_ready_bytes(&self) -> Result<u64, Error> {
-        // TODO: implement
-        // https://github.com/enarx/enarx/issues/2242
-        Ok(0)
+        self.ready_plaintext()
+            .try_into()
+            .map_err(|e| Error::range().context(e))
     }
 
     async fn readable(&self) -> Result<(), Error> {
+        if self.ready_plaintext() > 0 {
+            return Ok(());
+        }
         let (readable, _writeable) = is_read_write(&self.tcp)?;
         if readable {
             Ok(())
@@ -246,10 +477,16 @@ impl WasiFile for Stream {
         ri_data: &mut [IoSliceMut<'a>],
         ri_flags: RiFlags,
     ) -> Result<(u64, RoFlags), Error> {
-        if ri_flags != RiFlags::empty() {
+        if ri_flags == RiFlags::RECV_PEEK {
+            let mut buf = vec![0; ri_data.iter().map(|b| b.len()).sum()];
+            let n = self.peek(&mut buf).await?;
+            buf.truncate(n as _);
+            buf.as_slice().read_vectored(ri_data)?;
+            return Ok((n, RoFlags::empty()));
+        } else if ri_flags != RiFlags::empty() {
             return Err(Error::not_supported());
         }
-        // TODO: Add support for peek and waitall
+        // TODO: Add support for waitall
         // https://github.com/enarx/enarx/issues/2243
         let n = self.read_vectored(ri_data).await?;
         Ok((n, RoFlags::empty()))
@@ -278,19 +515,234 @@ impl WasiFile for Stream {
         } else {
             return Err(Error::invalid_argument());
         };
+        if how != Shutdown::Read {
+            // Let the peer know no more data follows, before shutting down the socket.
+            self.close_notify()
+                .map_err(errmap)
+                .context("failed to send TLS close_notify")?;
+        }
         self.tcp.shutdown(how)?;
         Ok(())
     }
 }
 
+/// Scheduler reporting TLS streams with buffered plaintext as readable without polling them
+///
+/// All other subscriptions are polled by the wrapped scheduler.
+pub struct Sched(pub Box<dyn WasiSched>);
+
+#[wiggle::async_trait]
+impl WasiSched for Sched {
+    async fn poll_oneoff<'a>(&self, poll: &mut Poll<'a>) -> Result<(), Error> {
+        let mut ready = false;
+        for sub in poll.rw_subscriptions() {
+            if let Subscription::Read(sub) = sub {
+                let n = sub
+                    .file
+                    .as_any()
+                    .downcast_ref::<Stream>()
+                    .map_or(0, Stream::ready_plaintext);
+                if n > 0 {
+                    sub.complete(n as _, RwEventFlags::empty());
+                    ready = true;
+                }
+            }
+        }
+        if ready {
+            Ok(())
+        } else {
+            self.0.poll_oneoff(poll).await
+        }
+    }
+
+    async fn sched_yield(&self) -> Result<(), Error> {
+        self.0.sched_yield().await
+    }
+
+    async fn sleep(&self, duration: Duration) -> Result<(), Error> {
+        self.0.sleep(duration).await
+    }
+}
+
+/// Server certificate chain of the workload, which can be rotated while listening
+///
+/// New connections use the current certificate chain,
+/// while established connections keep using the one they were accepted with.
+pub struct RotatingCert {
+    key: Arc<dyn rustls::sign::SigningKey>,
+    certified: RwLock<Arc<CertifiedKey>>,
+}
+
+impl RotatingCert {
+    /// `ocsp` is the DER-encoded OCSP response stapled to the handshakes, if any.
+    pub fn new(
+        certs: Vec<Certificate>,
+        ocsp: Option<Vec<u8>>,
+        key: &PrivateKey,
+    ) -> anyhow::Result<Self> {
+        let key = any_supported_type(key)
+            .map_err(|e| anyhow::anyhow!("unsupported private key type: {e}"))?;
+        let mut certified = CertifiedKey::new(certs, key.clone());
+        certified.ocsp = ocsp;
+        let certified = RwLock::new(Arc::new(certified));
+        Ok(Self { key, certified })
+    }
+
+    /// Replaces the certificate chain and the stapled OCSP response for the same private key
+    pub fn update(&self, certs: Vec<Certificate>, ocsp: Option<Vec<u8>>) {
+        let mut certified = CertifiedKey::new(certs, self.key.clone());
+        certified.ocsp = ocsp;
+        let certified = Arc::new(certified);
+        *self
+            .certified
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = certified;
+    }
+}
+
+impl ResolvesServerCert for RotatingCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let certified = self
+            .certified
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        Some(certified.clone())
+    }
+}
+
+/// Resolver stapling a fixed OCSP response to the certificate chain resolved by another resolver
+///
+/// The response replaces any response stapled by the inner resolver.
+pub struct StapledCert {
+    inner: Arc<dyn ResolvesServerCert>,
+    ocsp: Vec<u8>,
+}
+
+impl StapledCert {
+    pub fn new(inner: Arc<dyn ResolvesServerCert>, ocsp: Vec<u8>) -> Self {
+        Self { inner, ocsp }
+    }
+}
+
+impl ResolvesServerCert for StapledCert {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let mut certified = CertifiedKey::clone(&self.inner.resolve(client_hello)?);
+        certified.ocsp = Some(self.ocsp.clone());
+        Some(Arc::new(certified))
+    }
+}
+
+/// Resolver of the server certificate by the server name requested by the client via SNI
+///
+/// Clients requesting an unknown server name, or none at all, are presented the default certificate.
+pub struct SniResolver {
+    default: Arc<dyn ResolvesServerCert>,
+    certs: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl SniResolver {
+    pub fn new(default: Arc<dyn ResolvesServerCert>) -> Self {
+        Self {
+            default,
+            certs: HashMap::new(),
+        }
+    }
+
+    /// Adds the certificate chain presented to clients requesting `name`
+    ///
+    /// Server names are matched case-insensitively.
+    pub fn add(
+        &mut self,
+        name: &str,
+        certs: Vec<Certificate>,
+        key: &PrivateKey,
+    ) -> anyhow::Result<()> {
+        let key = any_supported_type(key)
+            .map_err(|e| anyhow::anyhow!("unsupported private key type: {e}"))?;
+        let certified = CertifiedKey::new(certs, key);
+        self.certs
+            .insert(name.to_ascii_lowercase(), Arc::new(certified));
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let certified = client_hello
+            .server_name()
+            .and_then(|name| self.certs.get(&name.to_ascii_lowercase()));
+        match certified {
+            Some(certified) => Some(certified.clone()),
+            None => self.default.resolve(client_hello),
+        }
+    }
+}
+
 pub struct Listener {
     listener: CapListener,
     cfg: Arc<ServerConfig>,
+    tcp: TcpOptions,
+    limits: ConnectionLimits,
+    filter: IpFilter,
+    nonblocking: bool,
+    /// Maximum time each accepted connection is drained for, when it is dropped
+    drain_timeout: Duration,
 }
 
 impl Listener {
-    pub fn new(listener: CapListener, cfg: Arc<ServerConfig>) -> Self {
-        Self { listener, cfg }
+    pub fn new(
+        listener: CapListener,
+        cfg: Arc<ServerConfig>,
+        tcp: TcpOptions,
+        limits: ConnectionLimits,
+    ) -> Self {
+        Self {
+            listener,
+            cfg,
+            tcp,
+            limits,
+            filter: IpFilter::default(),
+            nonblocking: false,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+        }
+    }
+
+    /// Sets the maximum time each accepted connection is drained for, when it is dropped
+    ///
+    /// A zero timeout closes the connections right away, only sending the `close_notify` alert
+    /// as far as the send buffer of the socket allows.
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Restricts the clients allowed to connect by their IP address
+    pub fn with_ip_filter(mut self, filter: IpFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Selects the certificate presented to each client by the server name it requests via SNI
+    pub fn with_sni_resolver(mut self, resolver: Arc<dyn ResolvesServerCert>) -> Self {
+        let mut cfg = ServerConfig::clone(&self.cfg);
+        cfg.cert_resolver = resolver;
+        self.cfg = Arc::new(cfg);
+        self
+    }
+
+    /// Reserves a connection according to the limits of the listener
+    ///
+    /// Pending connections are left in the queue of the socket, while a limit is exceeded.
+    /// A blocking listener waits for the rate limit, but not for the concurrency limit,
+    /// as the guest may need to close a connection first.
+    fn acquire(&mut self) -> Result<Option<Permit>, Error> {
+        loop {
+            match self.limits.acquire() {
+                Ok(permit) => return Ok(permit),
+                Err(Limited::Rate(wait)) if !self.nonblocking => std::thread::sleep(wait),
+                Err(..) => return Err(ErrorKind::WouldBlk.into()),
+            }
+        }
     }
 }
 
@@ -317,7 +769,16 @@ impl WasiFile for Listener {
     }
 
     async fn sock_accept(&mut self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
-        let (tcp, ..) = self.listener.accept()?;
+        let permit = self.acquire()?;
+        let (tcp, addr) = self.listener.accept()?;
+        if !self.filter.allows(addr.ip()) {
+            // The connection is closed, when it is dropped.
+            return Err(Error::perm().context(format!("connection from {addr} is not allowed")));
+        }
+        self.tcp
+            .apply(&tcp)
+            .map_err(errmap)
+            .context("failed to set TCP socket options")?;
 
         let tls = ServerConnection::new(self.cfg.clone())
             .map_err(|e| Error::io().context(e))
@@ -328,18 +789,42 @@ impl WasiFile for Listener {
             tcp,
             tls,
             nonblocking: false,
+            peeked: vec![],
+            buffered: 0,
+            closed: false,
+            _permit: permit,
+            drain_timeout: self.drain_timeout,
         };
         stream
-            .set_fdflags(FdFlags::empty())
+            .set_fdflags(fdflags)
             .await
-            .context("failed to unset client stream FD flags")?;
+            .context("failed to set requested client stream FD flags")?;
+        // A nonblocking stream only progresses the handshake as far as possible without blocking.
+        // The remainder is completed by the subsequent I/O on the stream, which returns `EAGAIN`
+        // until then, so that a slow client does not block accepting further connections.
+        // A nonblocking listener must not block on the handshake either, even if the guest
+        // requests a blocking stream, e.g. by `accept` of wasi-libc, so the stream is only
+        // switched to blocking afterwards. Its first I/O then blocks until the handshake completes.
+        let lazy = self.nonblocking && !stream.nonblocking;
+        if lazy {
+            stream
+                .set_fdflags(FdFlags::NONBLOCK)
+                .await
+                .context("failed to set client stream NONBLOCK for the handshake")?;
+        }
         stream
             .complete_io()
+            .map_err(|e| {
+                tracing::debug!("TLS handshake with {addr} failed: {e:#}");
+                e
+            })
             .context("failed to complete connection I/O")?;
-        stream
-            .set_fdflags(fdflags)
-            .await
-            .context("failed to set requested client stream FD flags")?;
+        if lazy {
+            stream
+                .set_fdflags(fdflags)
+                .await
+                .context("failed to set requested client stream FD flags")?;
+        }
         Ok(Box::new(stream))
     }
 
@@ -347,6 +832,14 @@ impl WasiFile for Listener {
         Ok(FileType::SocketStream)
     }
 
+    async fn advise(&mut self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn allocate(&mut self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+
     #[cfg(unix)]
     async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
         let fdflags = get_fd_flags(&self.listener)?;
@@ -356,8 +849,10 @@ impl WasiFile for Listener {
     async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
         if fdflags == FdFlags::NONBLOCK {
             self.listener.set_nonblocking(true)?;
+            self.nonblocking = true;
         } else if fdflags.is_empty() {
             self.listener.set_nonblocking(false)?;
+            self.nonblocking = false;
         } else {
             return Err(Error::invalid_argument().context("cannot set anything else than NONBLOCK"));
         }
@@ -368,3 +863,593 @@ impl WasiFile for Listener {
         Ok(1)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::identity;
+    use crate::runtime::identity::platform::Platform;
+
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+    use std::time::Instant;
+
+    use futures::executor::block_on;
+    use rustls::client::{ServerCertVerified, ServerCertVerifier};
+    use rustls::{RootCertStore, ServerName, StreamOwned};
+    use wasi_common::sched::subscription::SubscriptionResult;
+    use wasi_common::sched::Userdata;
+
+    /// Returns a self-signed certificate chain for `localhost` and its key
+    fn localhost() -> (Vec<Certificate>, PrivateKey) {
+        Platform::mock(vec![0xaa; 16]);
+        let san = ["localhost".try_into().unwrap()];
+        let (key, _) = identity::generate(None, None, &san).unwrap();
+        let certs = identity::selfsigned(&key, None, &san, identity::DEFAULT_SELFSIGNED_VALIDITY)
+            .unwrap()
+            .into_iter()
+            .map(Certificate)
+            .collect::<Vec<_>>();
+        (certs, PrivateKey(key.to_vec()))
+    }
+
+    /// Returns a client and server config for `localhost` with a self-signed certificate
+    fn configs() -> (ClientConfig, ServerConfig) {
+        let (certs, key) = localhost();
+        let mut roots = RootCertStore::empty();
+        roots.add(&certs[0]).unwrap();
+        let client = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn poll_buffered_plaintext() {
+        let (client, server) = configs();
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let tls = ServerConnection::new(Arc::new(server)).unwrap();
+            let mut stream = StreamOwned::new(tls, tcp);
+            stream.write_all(b"hello").unwrap();
+            stream.flush().unwrap();
+            // Keep the connection open until the client closes it.
+            let _ = stream.read_to_end(&mut vec![]);
+        });
+
+        let tcp = CapStream::from_std(std::net::TcpStream::connect(addr).unwrap());
+        let mut stream = Stream::connect(tcp, "localhost", Arc::new(client)).unwrap();
+        let mut buf = [0u8; 1];
+        let n = block_on(stream.read_vectored(&mut [IoSliceMut::new(&mut buf)])).unwrap();
+        assert_eq!(&buf[..n as _], b"h");
+        assert_eq!(block_on(stream.num_ready_bytes()).unwrap(), 4);
+
+        // The remainder of the record is readable, although the socket has no data.
+        let sched = Sched(wasmtime_wasi::sched::sched_ctx());
+        let mut poll = Poll::new();
+        poll.subscribe_read(&stream, Userdata::from(0));
+        block_on(sched.poll_oneoff(&mut poll)).unwrap();
+        assert!(matches!(
+            poll.results()[..],
+            [(SubscriptionResult::Read(Ok((4, _))), _)]
+        ));
+
+        drop(stream);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn close_notify() {
+        let (client, server) = configs();
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let server = Arc::new(server);
+            let mut received = vec![];
+            for _ in 0..2 {
+                let (tcp, _) = listener.accept().unwrap();
+                let tls = ServerConnection::new(server.clone()).unwrap();
+                let mut stream = StreamOwned::new(tls, tcp);
+                let mut buf = vec![];
+                // Fails with `UnexpectedEof`, unless the connection was closed with a `close_notify`.
+                stream.read_to_end(&mut buf).unwrap();
+                received.push(buf);
+            }
+            received
+        });
+        let connect = || {
+            let tcp = CapStream::from_std(std::net::TcpStream::connect(addr).unwrap());
+            Stream::connect(tcp, "localhost", Arc::new(client.clone())).unwrap()
+        };
+
+        let mut stream = connect();
+        block_on(stream.write_vectored(&[IoSlice::new(b"shutdown")])).unwrap();
+        block_on(stream.sock_shutdown(SdFlags::WR)).unwrap();
+        // The alert is only sent once.
+        block_on(stream.sock_shutdown(SdFlags::RD | SdFlags::WR)).unwrap();
+        drop(stream);
+
+        let mut stream = connect();
+        block_on(stream.write_vectored(&[IoSlice::new(b"drop")])).unwrap();
+        drop(stream);
+
+        assert_eq!(server.join().unwrap(), [&b"shutdown"[..], b"drop"]);
+    }
+
+    #[test]
+    fn drop_unread() {
+        let (client, server) = configs();
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (done, wait) = mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            let (mut tcp, _) = listener.accept().unwrap();
+            let mut tls = ServerConnection::new(Arc::new(server)).unwrap();
+            while tls.is_handshaking() {
+                tls.complete_io(&mut tcp).unwrap();
+            }
+            // Stop reading without closing the connection.
+            let _ = wait.recv();
+        });
+
+        let tcp = CapStream::from_std(std::net::TcpStream::connect(addr).unwrap());
+        let mut stream = Stream::connect(tcp, "localhost", Arc::new(client)).unwrap();
+        // Fill the send buffers of the socket and of the TLS connection.
+        block_on(stream.set_fdflags(FdFlags::NONBLOCK)).unwrap();
+        let data = [0; 16384];
+        while block_on(stream.write_vectored(&[IoSlice::new(&data)])).is_ok() {}
+        block_on(stream.set_fdflags(FdFlags::empty())).unwrap();
+
+        let (dropped, wait_dropped) = mpsc::channel();
+        thread::spawn(move || {
+            drop(stream);
+            dropped.send(()).unwrap();
+        });
+        wait_dropped.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        drop(done);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn read_timeout() {
+        let (client, server) = configs();
+        let client = Arc::new(client);
+        let timeout = Duration::from_millis(100);
+        let connect = |addr| {
+            let tcp = std::net::TcpStream::connect(addr).unwrap();
+            let options = TcpOptions {
+                read_timeout: Some(timeout),
+                ..Default::default()
+            };
+            options.apply(&tcp).unwrap();
+            Stream::connect(CapStream::from_std(tcp), "localhost", client.clone())
+        };
+
+        // A peer, which never responds, lets the handshake time out.
+        let silent = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let start = Instant::now();
+        assert!(connect(silent.local_addr().unwrap()).is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // A peer, which completes the handshake, but never sends data, lets reads time out.
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (done, wait) = mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            let (mut tcp, _) = listener.accept().unwrap();
+            let mut tls = ServerConnection::new(Arc::new(server)).unwrap();
+            while tls.is_handshaking() {
+                tls.complete_io(&mut tcp).unwrap();
+            }
+            let _ = wait.recv();
+        });
+
+        let mut stream = connect(addr).unwrap();
+        let start = Instant::now();
+        let err = block_on(stream.read_vectored(&mut [IoSliceMut::new(&mut [0; 8])])).unwrap_err();
+        let elapsed = start.elapsed();
+        assert!(
+            matches!(err.downcast_ref(), Some(ErrorKind::WouldBlk)),
+            "{err:#}"
+        );
+        assert!(
+            timeout <= elapsed && elapsed < Duration::from_secs(5),
+            "{elapsed:?}"
+        );
+
+        drop(done);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn advise_and_allocate() {
+        let (_, server) = configs();
+        let tcp = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut listener = Listener::new(
+            CapListener::from_std(tcp),
+            Arc::new(server),
+            TcpOptions::default(),
+            ConnectionLimits::new(None, None),
+        );
+        // Advice is ignored, but sockets have no storage to allocate.
+        block_on(listener.advise(0, 0, Advice::Sequential)).unwrap();
+        let err = block_on(listener.allocate(0, 4096)).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(ErrorKind::Notsup)),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn lazy_handshake() {
+        let (client, server) = configs();
+        let client = Arc::new(client);
+        let tcp = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = tcp.local_addr().unwrap();
+        // A handshake blocking on the silent client times out instead of hanging the test.
+        let options = TcpOptions {
+            read_timeout: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        let mut listener = Listener::new(
+            CapListener::from_std(tcp),
+            Arc::new(server),
+            options,
+            ConnectionLimits::new(None, None),
+        );
+        // Completes the handshake of a connected client and sends a message
+        let handshake = |tcp: std::net::TcpStream| {
+            let client = client.clone();
+            thread::spawn(move || {
+                let tls = ClientConnection::new(client, "localhost".try_into().unwrap()).unwrap();
+                let mut stream = StreamOwned::new(tls, tcp);
+                stream.write_all(b"hello").unwrap();
+                stream.flush().unwrap();
+                // Keep the connection open until the server closes it.
+                let _ = stream.read_to_end(&mut vec![]);
+            })
+        };
+
+        // A nonblocking stream is accepted before the client sent anything.
+        let tcp = std::net::TcpStream::connect(addr).unwrap();
+        let start = Instant::now();
+        let mut stream = block_on(listener.sock_accept(FdFlags::NONBLOCK)).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let mut buf = [0u8; 5];
+        let err = block_on(stream.read_vectored(&mut [IoSliceMut::new(&mut buf)])).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(ErrorKind::WouldBlk)),
+            "{err:#}"
+        );
+
+        let peer = handshake(tcp);
+        let start = Instant::now();
+        let n = loop {
+            match block_on(stream.read_vectored(&mut [IoSliceMut::new(&mut buf)])) {
+                Ok(n) => break n,
+                Err(e) if matches!(e.downcast_ref(), Some(ErrorKind::WouldBlk)) => {
+                    assert!(start.elapsed() < Duration::from_secs(5));
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => panic!("{e:#}"),
+            }
+        };
+        assert_eq!(&buf[..n as _], b"hello");
+        drop(stream);
+        peer.join().unwrap();
+
+        // A nonblocking listener does not block on the handshake of a blocking stream either.
+        block_on(listener.set_fdflags(FdFlags::NONBLOCK)).unwrap();
+        let tcp = std::net::TcpStream::connect(addr).unwrap();
+        let start = Instant::now();
+        let mut stream = loop {
+            match block_on(listener.sock_accept(FdFlags::empty())) {
+                Ok(stream) => break stream,
+                Err(e) if matches!(e.downcast_ref(), Some(ErrorKind::WouldBlk)) => {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => panic!("{e:#}"),
+            }
+        };
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(block_on(stream.get_fdflags()).unwrap(), FdFlags::empty());
+
+        let peer = handshake(tcp);
+        let n = block_on(stream.read_vectored(&mut [IoSliceMut::new(&mut buf)])).unwrap();
+        assert_eq!(&buf[..n as _], b"hello");
+        drop(stream);
+        peer.join().unwrap();
+    }
+
+    #[test]
+    fn filestat() {
+        let (client, server) = configs();
+        let tcp = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let mut listener = Listener::new(
+            CapListener::from_std(tcp),
+            Arc::new(server),
+            TcpOptions::default(),
+            ConnectionLimits::new(None, None),
+        );
+        let client = thread::spawn(move || {
+            let tcp = CapStream::from_std(std::net::TcpStream::connect(addr).unwrap());
+            Stream::connect(tcp, "localhost", Arc::new(client)).unwrap()
+        });
+        let mut stream = block_on(listener.sock_accept(FdFlags::empty())).unwrap();
+        let mut client = client.join().unwrap();
+
+        // `fd_filestat_get` reports sockets, although the size of a stream is unknown.
+        for stat in [
+            block_on(listener.get_filestat()),
+            block_on(stream.get_filestat()),
+            block_on(client.get_filestat()),
+        ] {
+            let stat = stat.unwrap();
+            assert_eq!(stat.filetype, FileType::SocketStream);
+            assert_eq!(stat.size, 0);
+        }
+
+        drop(stream);
+        drop(client);
+    }
+
+    #[test]
+    fn drain() {
+        let (client, server) = configs();
+        let tcp = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let mut listener = Listener::new(
+            CapListener::from_std(tcp),
+            Arc::new(server),
+            TcpOptions::default(),
+            ConnectionLimits::new(None, None),
+        );
+        let client = thread::spawn(move || {
+            let tcp = std::net::TcpStream::connect(addr).unwrap();
+            let name = "localhost".try_into().unwrap();
+            let tls = ClientConnection::new(Arc::new(client), name).unwrap();
+            let mut stream = StreamOwned::new(tls, tcp);
+            stream.write_all(b"request").unwrap();
+            stream.flush().unwrap();
+            // Fails on a connection closed without `close_notify` or reset.
+            let mut received = vec![];
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+        let mut stream = block_on(listener.sock_accept(FdFlags::empty())).unwrap();
+
+        // The accepted connection survives the listener, whose socket is closed right away.
+        drop(listener);
+        assert!(std::net::TcpStream::connect(addr).is_err());
+
+        // The data written right before dropping the stream reaches the client intact,
+        // although the request was never read.
+        let data: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+        let mut written = 0;
+        while written < data.len() {
+            let n = block_on(stream.write_vectored(&[IoSlice::new(&data[written..])])).unwrap();
+            written += n as usize;
+        }
+        drop(stream);
+        assert!(client.join().unwrap() == data);
+    }
+
+    #[test]
+    fn handshake_errors() {
+        let (certs, key) = localhost();
+        let server = ServerConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certs.clone(), key)
+            .unwrap();
+        let server = Arc::new(server);
+        let client = |version: &'static rustls::SupportedProtocolVersion, trusted: bool| {
+            let mut roots = RootCertStore::empty();
+            if trusted {
+                roots.add(&certs[0]).unwrap();
+            }
+            let cfg = ClientConfig::builder()
+                .with_safe_default_cipher_suites()
+                .with_safe_default_kx_groups()
+                .with_protocol_versions(&[version])
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            Arc::new(cfg)
+        };
+
+        // Fails the handshake of `Stream::connect` with `client` against a plain rustls server
+        let connect = |client: Arc<ClientConfig>| {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = server.clone();
+            let peer = thread::spawn(move || {
+                let (mut tcp, _) = listener.accept().unwrap();
+                let mut tls = ServerConnection::new(server).unwrap();
+                assert!(tls.complete_io(&mut tcp).is_err());
+            });
+            let tcp = CapStream::from_std(std::net::TcpStream::connect(addr).unwrap());
+            let err = Stream::connect(tcp, "localhost", client)
+                .err()
+                .expect("handshake succeeded");
+            peer.join().unwrap();
+            err
+        };
+
+        // Fails the handshake of `Listener::sock_accept` with a plain rustls `client`
+        let accept = |client: Arc<ClientConfig>| {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+            let addr = listener.local_addr().unwrap();
+            let mut listener = Listener::new(
+                CapListener::from_std(listener),
+                server.clone(),
+                Default::default(),
+                Default::default(),
+            );
+            let peer = thread::spawn(move || {
+                let mut tcp = std::net::TcpStream::connect(addr).unwrap();
+                let mut tls =
+                    ClientConnection::new(client, "localhost".try_into().unwrap()).unwrap();
+                assert!(tls.complete_io(&mut tcp).is_err());
+            });
+            let err = block_on(listener.sock_accept(FdFlags::empty()))
+                .err()
+                .expect("handshake succeeded");
+            peer.join().unwrap();
+            err
+        };
+
+        let kind = |err: Error| match err.downcast_ref::<ErrorKind>() {
+            Some(ErrorKind::Perm) => "perm",
+            Some(ErrorKind::Notsup) => "notsup",
+            _ => panic!("unexpected error: {err:#}"),
+        };
+        let tls12 = &rustls::version::TLS12;
+        let tls13 = &rustls::version::TLS13;
+        assert_eq!(kind(connect(client(tls12, true))), "notsup");
+        assert_eq!(kind(accept(client(tls12, true))), "notsup");
+        assert_eq!(kind(connect(client(tls13, false))), "perm");
+        assert_eq!(kind(accept(client(tls13, false))), "perm");
+    }
+
+    /// Passes the pending TLS records of `from` on to `to`
+    fn transfer(from: &mut Connection, to: &mut Connection) {
+        let mut buf = vec![];
+        while from.wants_write() {
+            from.write_tls(&mut buf).unwrap();
+        }
+        let mut rd = buf.as_slice();
+        while !rd.is_empty() {
+            to.read_tls(&mut rd).unwrap();
+            to.process_new_packets().unwrap();
+        }
+    }
+
+    #[test]
+    fn sni_resolver() {
+        Platform::mock(vec![0xaa; 16]);
+        let identity = |name: &str| {
+            let san = [name.try_into().unwrap()];
+            let (key, _) = identity::generate(None, None, &san).unwrap();
+            let certs =
+                identity::selfsigned(&key, None, &san, identity::DEFAULT_SELFSIGNED_VALIDITY)
+                    .unwrap()
+                    .into_iter()
+                    .map(Certificate)
+                    .collect::<Vec<_>>();
+            (certs, PrivateKey(key.to_vec()))
+        };
+        let (default_certs, default_key) = identity("localhost");
+        let (sni_certs, sni_key) = identity("example.com");
+
+        let default = RotatingCert::new(default_certs.clone(), None, &default_key).unwrap();
+        let mut resolver = SniResolver::new(Arc::new(default));
+        // Server names are matched case-insensitively.
+        resolver
+            .add("Example.COM", sni_certs.clone(), &sni_key)
+            .unwrap();
+        let resolver = Arc::new(resolver);
+
+        for (name, certs) in [("localhost", &default_certs), ("example.com", &sni_certs)] {
+            let mut roots = RootCertStore::empty();
+            roots.add(&certs[0]).unwrap();
+            let client = ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let server = ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_cert_resolver(resolver.clone());
+
+            let mut client = ClientConnection::new(Arc::new(client), name.try_into().unwrap())
+                .map(Connection::Client)
+                .unwrap();
+            let mut server = ServerConnection::new(Arc::new(server))
+                .map(Connection::Server)
+                .unwrap();
+            while client.is_handshaking() || server.is_handshaking() {
+                transfer(&mut client, &mut server);
+                transfer(&mut server, &mut client);
+            }
+            assert_eq!(client.peer_certificates(), Some(certs.as_slice()));
+        }
+    }
+
+    /// Verifier accepting any server certificate, which records the stapled OCSP response
+    #[derive(Default)]
+    struct OcspRecorder(Mutex<Option<Vec<u8>>>);
+
+    impl ServerCertVerifier for OcspRecorder {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &Certificate,
+            _intermediates: &[Certificate],
+            _server_name: &ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            ocsp_response: &[u8],
+            _now: std::time::SystemTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            *self.0.lock().unwrap() = Some(ocsp_response.to_vec());
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+
+    #[test]
+    fn ocsp_stapling() {
+        let (certs, key) = localhost();
+        let rotating =
+            Arc::new(RotatingCert::new(certs.clone(), Some(b"steward".to_vec()), &key).unwrap());
+        let stapled: Arc<dyn ResolvesServerCert> =
+            Arc::new(StapledCert::new(rotating.clone(), b"configured".to_vec()));
+
+        let stapled_response = |resolver: Arc<dyn ResolvesServerCert>| {
+            let recorder = Arc::new(OcspRecorder::default());
+            let client = ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(recorder.clone())
+                .with_no_client_auth();
+            let server = ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_cert_resolver(resolver);
+
+            let mut client =
+                ClientConnection::new(Arc::new(client), "localhost".try_into().unwrap())
+                    .map(Connection::Client)
+                    .unwrap();
+            let mut server = ServerConnection::new(Arc::new(server))
+                .map(Connection::Server)
+                .unwrap();
+            while client.is_handshaking() || server.is_handshaking() {
+                transfer(&mut client, &mut server);
+                transfer(&mut server, &mut client);
+            }
+            let ocsp = recorder.0.lock().unwrap().take();
+            ocsp.unwrap()
+        };
+
+        assert_eq!(stapled_response(rotating.clone()), b"steward");
+        assert_eq!(stapled_response(stapled.clone()), b"configured");
+
+        // Renewals replace the stapled response along with the certificate chain.
+        rotating.update(certs.clone(), Some(b"renewed".to_vec()));
+        assert_eq!(stapled_response(rotating.clone()), b"renewed");
+        rotating.update(certs, None);
+        assert_eq!(stapled_response(rotating), b"");
+    }
+}
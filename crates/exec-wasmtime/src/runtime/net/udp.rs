@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! A WasiFile for UDP datagram sockets
+
+use std::any::Any;
+use std::io::{IoSlice, IoSliceMut, Read};
+use std::net::{SocketAddr, UdpSocket};
+
+#[cfg(windows)]
+use io_extras::os::windows::AsRawHandleOrSocket;
+#[cfg(unix)]
+use io_lifetimes::AsFd;
+
+use wasi_common::file::{Advice, FdFlags, FileType, RiFlags, RoFlags, SdFlags, SiFlags};
+use wasi_common::{Context, Error, ErrorExt, WasiFile};
+#[cfg(unix)]
+use wasmtime_wasi::net::get_fd_flags;
+use wasmtime_wasi::net::is_read_write;
+
+/// Maximum size of a UDP datagram payload
+const MAX_DATAGRAM_SIZE: usize = 65507;
+
+pub struct Socket {
+    udp: UdpSocket,
+    /// The peer datagrams are sent to, if the socket is not connected
+    peer: Option<SocketAddr>,
+    connected: bool,
+}
+
+impl From<Socket> for Box<dyn WasiFile> {
+    fn from(value: Socket) -> Self {
+        Box::new(value)
+    }
+}
+
+impl Socket {
+    /// Wraps a socket, which is bound, but not connected.
+    /// Datagrams are sent to the peer, which the last datagram was received from.
+    pub fn bound(udp: UdpSocket) -> Self {
+        Self {
+            udp,
+            peer: None,
+            connected: false,
+        }
+    }
+
+    /// Wraps a socket, which is connected to a remote address.
+    pub fn connected(udp: UdpSocket) -> Self {
+        Self {
+            udp,
+            peer: None,
+            connected: true,
+        }
+    }
+
+    fn recv(&mut self, bufs: &mut [IoSliceMut<'_>], peek: bool) -> Result<usize, Error> {
+        let len = bufs.iter().map(|b| b.len()).sum::<usize>();
+        let mut buf = vec![0; len.min(MAX_DATAGRAM_SIZE)];
+        let n = match (self.connected, peek) {
+            (true, false) => self.udp.recv(&mut buf)?,
+            (true, true) => self.udp.peek(&mut buf)?,
+            (false, false) => {
+                let (n, peer) = self.udp.recv_from(&mut buf)?;
+                self.peer = Some(peer);
+                n
+            }
+            (false, true) => self.udp.peek_from(&mut buf)?.0,
+        };
+        buf.truncate(n);
+        buf.as_slice().read_vectored(bufs)?;
+        Ok(n)
+    }
+
+    fn send(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize, Error> {
+        let buf = bufs
+            .iter()
+            .flat_map(|b| b.iter().copied())
+            .collect::<Vec<_>>();
+        let n = if self.connected {
+            self.udp.send(&buf)?
+        } else if let Some(peer) = self.peer {
+            self.udp.send_to(&buf, peer)?
+        } else {
+            return Err(Error::io().context("no datagram was received yet"));
+        };
+        Ok(n)
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Socket {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd<'_>> {
+        Some(self.udp.as_fd())
+    }
+
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        Some(self.udp.as_raw_handle_or_socket())
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        Ok(FileType::SocketDgram)
+    }
+
+    async fn advise(&mut self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn allocate(&mut self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+
+    #[cfg(unix)]
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        let fdflags = get_fd_flags(&self.udp)?;
+        Ok(fdflags)
+    }
+
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        if fdflags == FdFlags::NONBLOCK {
+            self.udp.set_nonblocking(true)?;
+        } else if fdflags.is_empty() {
+            self.udp.set_nonblocking(false)?;
+        } else {
+            return Err(Error::invalid_argument().context("cannot set anything else than NONBLOCK"));
+        }
+        Ok(())
+    }
+
+    async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        let n = self.recv(bufs, false)?;
+        n.try_into().map_err(|e| Error::range().context(e))
+    }
+
+    async fn write_vectored<'a>(&mut self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        let n = self.send(bufs)?;
+        n.try_into().map_err(|e| Error::range().context(e))
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> Result<u64, Error> {
+        let n = self.recv(&mut [IoSliceMut::new(buf)], true)?;
+        n.try_into().map_err(|e| Error::range().context(e))
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(0)
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        let (readable, _writeable) = is_read_write(&self.udp)?;
+        if readable {
+            Ok(())
+        } else {
+            Err(Error::io())
+        }
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        let (_readable, writeable) = is_read_write(&self.udp)?;
+        if writeable {
+            Ok(())
+        } else {
+            Err(Error::io())
+        }
+    }
+
+    async fn sock_recv<'a>(
+        &mut self,
+        ri_data: &mut [IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        let peek = if ri_flags == RiFlags::RECV_PEEK {
+            true
+        } else if ri_flags.is_empty() {
+            false
+        } else {
+            return Err(Error::not_supported());
+        };
+        let n = self.recv(ri_data, peek)?;
+        let n = n.try_into().map_err(|e| Error::range().context(e))?;
+        Ok((n, RoFlags::empty()))
+    }
+
+    async fn sock_send<'a>(
+        &mut self,
+        si_data: &[IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        if si_flags != SiFlags::empty() {
+            return Err(Error::not_supported());
+        }
+        self.write_vectored(si_data).await
+    }
+
+    async fn sock_shutdown(&mut self, _how: SdFlags) -> Result<(), Error> {
+        Err(Error::not_supported().context("cannot shut down a datagram socket"))
+    }
+}
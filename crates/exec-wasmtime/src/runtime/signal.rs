@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Emulation of `proc_raise` of `wasi_snapshot_preview1`
+//!
+//! `wasmtime-wasi` traps on every call of `proc_raise`, while libc-based applications
+//! commonly raise `SIGABRT`, `SIGINT` or `SIGTERM` to terminate. Terminating signals are mapped
+//! to a `proc_exit` with the exit code of a process killed by the signal on a POSIX shell,
+//! i.e. `128` plus the signal number. Other signals are not supported.
+
+use super::State;
+
+use anyhow::Context;
+use wasmtime::{Linker, Trap};
+
+/// `SIGINT` of `wasi_snapshot_preview1`
+const SIGINT: i32 = 2;
+
+/// `SIGABRT` of `wasi_snapshot_preview1`
+const SIGABRT: i32 = 6;
+
+/// `SIGKILL` of `wasi_snapshot_preview1`
+const SIGKILL: i32 = 9;
+
+/// `SIGTERM` of `wasi_snapshot_preview1`
+const SIGTERM: i32 = 15;
+
+/// `ENOTSUP` of `wasi_snapshot_preview1`
+const ERRNO_NOTSUP: i32 = 58;
+
+fn proc_raise(sig: i32) -> Result<i32, Trap> {
+    match sig {
+        SIGINT | SIGABRT | SIGKILL | SIGTERM => Err(Trap::i32_exit(128 + sig)),
+        _ => Ok(ERRNO_NOTSUP),
+    }
+}
+
+/// Replaces `proc_raise` of `wasi_snapshot_preview1` in the linker
+pub fn add_to_linker(linker: &mut Linker<State>) -> anyhow::Result<()> {
+    linker
+        .allow_shadowing(true)
+        .func_wrap("wasi_snapshot_preview1", "proc_raise", proc_raise)
+        .context("failed to add `proc_raise`")?;
+    linker.allow_shadowing(false);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::runtime::{Exit, Runtime};
+
+    const RAISE_WAT: &str = r#"(module
+      (import "wasi_snapshot_preview1" "proc_raise"
+        (func $proc_raise (param i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "_start")
+        (if (i32.ne (call $proc_raise (i32.const 10)) (i32.const 58))
+          (then unreachable))
+        (drop (call $proc_raise (i32.const 6)))
+      )
+    )"#;
+
+    #[test]
+    fn proc_raise_signals() {
+        assert!(matches!(super::proc_raise(10), Ok(58)));
+        for sig in [2, 6, 9, 15] {
+            let trap = super::proc_raise(sig).unwrap_err();
+            assert_eq!(trap.i32_exit_status(), Some(128 + sig));
+        }
+    }
+
+    #[test]
+    fn proc_raise() {
+        let bytes = wat::parse_str(RAISE_WAT).expect("error parsing wat");
+        let exit = Runtime::default()
+            .execute(crate::test::package(&bytes).unwrap(), Default::default())
+            .unwrap()
+            .exit;
+        assert!(matches!(exit, Exit::Exited(134)));
+    }
+}
@@ -0,0 +1,39 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers for accessing the guest memory from host functions
+
+use anyhow::{anyhow, Context, Result};
+use wasmtime::{Caller, Extern, Memory};
+
+fn memory<T>(caller: &mut Caller<'_, T>) -> Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| anyhow!("guest does not export `memory`"))
+}
+
+/// Reads `len` bytes at `ptr` from the guest memory
+pub fn read<T>(caller: &mut Caller<'_, T>, ptr: i32, len: i32) -> Result<Vec<u8>> {
+    let mem = memory(caller)?;
+    let ptr = u32::try_from(ptr).context("invalid guest pointer")?;
+    let len = usize::try_from(len).context("invalid guest buffer length")?;
+
+    let mut buf = vec![0; len];
+    mem.read(&caller, ptr as _, &mut buf)
+        .context("guest buffer out of bounds")?;
+    Ok(buf)
+}
+
+/// Writes `data` into the guest buffer of `len` bytes at `ptr` and returns the amount of bytes written
+///
+/// `data` exceeding the size of the guest buffer is truncated.
+pub fn write<T>(caller: &mut Caller<'_, T>, ptr: i32, len: i32, data: &[u8]) -> Result<usize> {
+    let mem = memory(caller)?;
+    let ptr = u32::try_from(ptr).context("invalid guest pointer")?;
+    let len = usize::try_from(len).context("invalid guest buffer length")?;
+
+    let data = &data[..data.len().min(len)];
+    mem.write(caller, ptr as _, data)
+        .context("guest buffer out of bounds")?;
+    Ok(data.len())
+}
@@ -2,15 +2,18 @@
 
 //! Workload-related functionality and definitions.
 
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek};
+#[cfg(unix)]
+use std::mem::ManuallyDrop;
 #[cfg(unix)]
 use std::os::unix::prelude::FromRawFd;
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use drawbridge_client::types::{Meta, TagEntry, TreeDirectory, TreeEntry, TreeName, TreePath};
 use drawbridge_client::{scope, Client, Entity, Node, Scope};
-use enarx_config::Config;
+use enarx_config::{Config, ConnectFile, InlineFile, ListenFile, PipeFile, Value};
 use once_cell::sync::Lazy;
 use ureq::serde_json;
 use url::Url;
@@ -42,6 +45,17 @@ pub enum Package {
     /// Remote URL to fetch package from
     Remote(Url),
 
+    /// WASM module held in memory
+    Module(Vec<u8>),
+
+    /// WASM module held in memory along with its config
+    Bundle {
+        /// WASM module, optionally compressed with gzip or zstd
+        wasm: Vec<u8>,
+        /// Optional contents of the config file
+        conf: Option<String>,
+    },
+
     /// Local package
     #[cfg(unix)]
     Local {
@@ -61,6 +75,241 @@ pub enum Package {
     },
 }
 
+impl Package {
+    /// Checks the config of the package for errors without executing it
+    ///
+    /// The config may reference the host environment variables `env_passthrough`, see
+    /// [Workload::from_package]. A remote package is fetched to do so. The config file of
+    /// a local package is rewound after reading it, so that the package can still be executed.
+    pub fn validate(&self, env_passthrough: &[String]) -> Result<()> {
+        let config = match self {
+            Self::Remote(url) => {
+                Workload::from_package(Self::Remote(url.clone()), env_passthrough)?.config
+            }
+            Self::Module(..) => None,
+            Self::Bundle { conf, .. } => conf
+                .as_deref()
+                .map(|conf| parse_config(conf, env_passthrough))
+                .transpose()?,
+            Self::Local { conf: None, .. } => None,
+            Self::Local {
+                conf: Some(conf), ..
+            } => {
+                // SAFETY: This FD was passed to us by the host and we trust that we have exclusive
+                // access to it. It is not closed, as the file is never dropped.
+                #[cfg(unix)]
+                let conf = ManuallyDrop::new(unsafe { File::from_raw_fd(*conf) });
+
+                let mut conf: &File = &conf;
+                let config = read_config(&mut conf, env_passthrough)?;
+                conf.rewind().context("failed to rewind config")?;
+                Some(config)
+            }
+        };
+        match config {
+            Some(config) => validate_config(&config),
+            None => Ok(()),
+        }
+    }
+
+    /// Fetches a WASM module over HTTPS
+    ///
+    /// If the fragment of `url` is of the form `sha256=<hex>`, the SHA-256 digest of the module
+    /// must match it. `tls_config` replaces the default TLS client configuration, which trusts
+    /// the Mozilla root certificates.
+    #[cfg(feature = "remote-fetch")]
+    pub fn from_url(
+        url: &Url,
+        tls_config: Option<std::sync::Arc<rustls::ClientConfig>>,
+    ) -> Result<Self> {
+        use sha2::{Digest, Sha256};
+
+        ensure!(
+            url.scheme() == "https",
+            "package URL `{url}` must use `https`"
+        );
+        let digest = url
+            .fragment()
+            .map(|fragment| {
+                fragment
+                    .strip_prefix("sha256=")
+                    .map(str::to_ascii_lowercase)
+                    .ok_or_else(|| anyhow!("unsupported package URL fragment `{fragment}`"))
+            })
+            .transpose()?;
+        let mut url = url.clone();
+        url.set_fragment(None);
+
+        let mut agent = ureq::AgentBuilder::new();
+        if let Some(tls_config) = tls_config {
+            agent = agent.tls_config(tls_config);
+        }
+        let mut webasm = Vec::new();
+        agent
+            .build()
+            .get(url.as_str())
+            .call()
+            .with_context(|| format!("failed to fetch `{url}`"))?
+            .into_reader()
+            .take(MAX_WASM_SIZE + 1)
+            .read_to_end(&mut webasm)
+            .context("failed to read WASM module")?;
+        ensure!(
+            webasm.len() as u64 <= MAX_WASM_SIZE,
+            "WASM module exceeds the size limit of `{MAX_WASM_SIZE}` bytes"
+        );
+        if let Some(digest) = digest {
+            let actual = format!("{:x}", Sha256::digest(&webasm));
+            ensure!(
+                actual == digest,
+                "SHA-256 digest `{actual}` of `{url}` does not match `{digest}`"
+            );
+        }
+        Ok(Self::Module(webasm))
+    }
+
+    /// Pulls a WASM module from an OCI image, e.g. `ghcr.io/enarx/app:latest`
+    ///
+    /// The module is taken from the image layer of type
+    /// `application/vnd.wasm.content.layer.v1+wasm` and the config from the `dev.enarx.config`
+    /// annotation of the image manifest, if present.
+    /// Images without an explicit registry are pulled from Docker Hub.
+    #[cfg(feature = "remote-fetch")]
+    pub fn from_oci(image_ref: &str, auth: Option<crate::RegistryAuth>) -> Result<Self> {
+        let (wasm, conf) = crate::oci::pull(image_ref, auth.as_ref(), MAX_WASM_SIZE, MAX_CONF_SIZE)
+            .with_context(|| format!("failed to pull `{image_ref}`"))?;
+        Ok(Self::Bundle { wasm, conf })
+    }
+}
+
+fn read_config(mut conf: impl Read, env_passthrough: &[String]) -> Result<Config> {
+    let mut config = vec![];
+    conf.read_to_end(&mut config)
+        .context("failed to read config")?;
+    let config = std::str::from_utf8(&config).context("config is not valid UTF-8")?;
+    parse_config(config, env_passthrough)
+}
+
+/// Parses the config, interpolating the host environment variables `env_passthrough`
+///
+/// The `env_passthrough` of the config itself is not trusted to allow referencing a variable.
+fn parse_config(config: &str, env_passthrough: &[String]) -> Result<Config> {
+    Config::from_toml_str_interpolated(config, |name| {
+        env_passthrough
+            .iter()
+            .any(|allowed| allowed == name)
+            .then(|| std::env::var(name).ok())
+            .flatten()
+    })
+    .context("failed to parse config")
+}
+
+/// Checks the config for errors, which would otherwise only be detected during the execution
+fn validate_config(config: &Config) -> Result<()> {
+    for url in &config.steward {
+        ensure!(
+            matches!(url.scheme(), "https" | "http"),
+            "Steward URL `{url}` must use `https` or `http`"
+        );
+    }
+
+    ensure!(
+        config.self_signed_cert_validity_secs != Some(0),
+        "`self_signed_cert_validity_secs` must be greater than zero"
+    );
+
+    if let Some(key) = &config.module_signing_key {
+        ensure!(
+            matches!(base64::decode(key), Ok(key) if key.len() == 32),
+            "`module_signing_key` must be a base64-encoded Ed25519 public key"
+        );
+    }
+
+    ensure!(
+        u32::try_from(config.files.len()).is_ok(),
+        "too many open files"
+    );
+    let mut names = HashSet::new();
+    let mut pipes = HashMap::<u32, usize>::new();
+    let mut stdin = false;
+    for file in &config.files {
+        let name = file.name();
+        ensure!(
+            names.insert(name),
+            "file name `{name}` is used more than once"
+        );
+        match file {
+            enarx_config::File::Stdin(..) => {
+                ensure!(!stdin, "stdin is pre-opened more than once");
+                stdin = true;
+            }
+            enarx_config::File::Pipe(PipeFile { pair, .. }) => {
+                *pipes.entry(*pair).or_default() += 1;
+            }
+            enarx_config::File::Listen(ListenFile::Tls {
+                max_connections_per_second,
+                max_concurrent_connections,
+                read_timeout_ms,
+                ocsp_response,
+                ocsp_response_path,
+                ..
+            }) => {
+                ensure!(
+                    *max_connections_per_second != Some(0)
+                        && *max_concurrent_connections != Some(0),
+                    "connection limits of `{name}` must be greater than zero"
+                );
+                ensure!(
+                    *read_timeout_ms != Some(0),
+                    "read timeout of `{name}` must be greater than zero"
+                );
+                ensure!(
+                    ocsp_response.is_none() || ocsp_response_path.is_none(),
+                    "listen file `{name}` must specify at most one of `ocsp_response` and `ocsp_response_path`"
+                );
+            }
+            enarx_config::File::Connect(ConnectFile::Tls {
+                read_timeout_ms, ..
+            }) => {
+                ensure!(
+                    *read_timeout_ms != Some(0),
+                    "read timeout of `{name}` must be greater than zero"
+                );
+            }
+            enarx_config::File::Inline(InlineFile {
+                data, host_path, ..
+            }) => {
+                ensure!(
+                    data.is_some() != host_path.is_some(),
+                    "inline file `{name}` must specify exactly one of `data` and `host_path`"
+                );
+            }
+            _ => {}
+        }
+    }
+    if let Some((pair, _)) = pipes.iter().find(|(_, &ends)| ends != 2) {
+        bail!("pipe `{pair}` must have exactly two ends")
+    }
+
+    for name in config.env.keys().chain(&config.env_passthrough) {
+        ensure!(
+            !name.is_empty() && !name.contains(['=', '\0']),
+            "invalid environment variable name `{}`",
+            name.escape_debug()
+        );
+    }
+    for value in config.env.values().chain(&config.args) {
+        if let Value::Inline(value) = value {
+            ensure!(
+                !value.contains('\0'),
+                "argument or environment variable value `{}` contains a null byte",
+                value.escape_debug()
+            );
+        }
+    }
+    Ok(())
+}
+
 fn get_wasm(root: Entity<'_, impl Scope, scope::Node>, entry: &TreeEntry) -> Result<Vec<u8>> {
     ensure!(
         entry.meta.mime.essence_str() == WASM_MEDIA_TYPE,
@@ -79,7 +328,11 @@ fn get_wasm(root: Entity<'_, impl Scope, scope::Node>, entry: &TreeEntry) -> Res
     Ok(wasm)
 }
 
-fn get_package(root: Entity<'_, impl Scope, scope::Node>, dir: TreeDirectory) -> Result<Workload> {
+fn get_package(
+    root: Entity<'_, impl Scope, scope::Node>,
+    dir: TreeDirectory,
+    env_passthrough: &[String],
+) -> Result<Workload> {
     let webasm = dir
         .get(&PACKAGE_ENTRYPOINT)
         .ok_or_else(|| anyhow!("directory does not contain `{}`", *PACKAGE_ENTRYPOINT))
@@ -107,7 +360,8 @@ fn get_package(root: Entity<'_, impl Scope, scope::Node>, dir: TreeDirectory) ->
         "`{}` metadata does not match directory entry metadata",
         *PACKAGE_CONFIG,
     );
-    let config = toml::from_slice(&config).context("failed to parse config")?;
+    let config = std::str::from_utf8(&config).context("config is not valid UTF-8")?;
+    let config = parse_config(config, env_passthrough)?;
     Ok(Workload {
         webasm,
         config: Some(config),
@@ -116,17 +370,27 @@ fn get_package(root: Entity<'_, impl Scope, scope::Node>, dir: TreeDirectory) ->
 
 /// Acquired workload
 pub struct Workload {
-    /// Wasm module
+    /// Wasm module, optionally compressed with gzip or zstd
     pub webasm: Vec<u8>,
 
     /// Enarx keep configuration
     pub config: Option<Config>,
 }
 
-impl TryFrom<Package> for Workload {
-    type Error = anyhow::Error;
+impl Workload {
+    /// Checks the config of the workload for errors
+    pub fn validate(&self) -> Result<()> {
+        match &self.config {
+            Some(config) => validate_config(config),
+            None => Ok(()),
+        }
+    }
 
-    fn try_from(mut pkg: Package) -> Result<Self, Self::Error> {
+    /// Acquires the workload of the package
+    ///
+    /// The config may reference the host environment variables `env_passthrough`, which
+    /// are chosen by the launcher, as the package is not trusted to choose them.
+    pub fn from_package(mut pkg: Package, env_passthrough: &[String]) -> Result<Self> {
         match pkg {
             Package::Remote(ref url) => {
                 let cl = Client::<scope::Unknown>::new_scoped(url.clone())
@@ -157,7 +421,8 @@ impl TryFrom<Package> for Workload {
                     TreeDirectory::<()>::TYPE => serde_json::from_reader(rdr)
                         .context("failed to decode response body")
                         .and_then(|dir| {
-                            get_package(top.clone().scope(), dir).context("failed to fetch package")
+                            get_package(top.clone().scope(), dir, env_passthrough)
+                                .context("failed to fetch package")
                         }),
                     typ => {
                         let tag = serde_json::from_reader(rdr).with_context(|| format!("failed to decode top-level entity of type `{typ}` as either Wasm module, Drawbridge directory or a tag"))?;
@@ -186,13 +451,28 @@ impl TryFrom<Package> for Workload {
                                     meta == entry.meta,
                                     "directory metadata does not match tag entry metadata"
                                 );
-                                get_package(tree, dir).context("failed to fetch package")
+                                get_package(tree, dir, env_passthrough)
+                                    .context("failed to fetch package")
                             }
                             typ => bail!("unsupported root type `{typ}`"),
                         }
                     }
                 }
             }
+            Package::Module(ref mut webasm) => Ok(Workload {
+                webasm: std::mem::take(webasm),
+                config: None,
+            }),
+            Package::Bundle {
+                ref mut wasm,
+                ref conf,
+            } => Ok(Workload {
+                webasm: std::mem::take(wasm),
+                config: conf
+                    .as_deref()
+                    .map(|conf| parse_config(conf, env_passthrough))
+                    .transpose()?,
+            }),
             Package::Local {
                 ref mut wasm,
                 ref mut conf,
@@ -210,13 +490,9 @@ impl TryFrom<Package> for Workload {
                     // SAFETY: This FD was passed to us by the host and we trust that we have exclusive
                     // access to it.
                     #[cfg(unix)]
-                    let mut conf = unsafe { File::from_raw_fd(*conf) };
+                    let conf = unsafe { File::from_raw_fd(*conf) };
 
-                    let mut config = vec![];
-                    conf.read_to_end(&mut config)
-                        .context("failed to read config")?;
-                    let config = toml::from_slice(&config).context("failed to parse config")?;
-                    Some(config)
+                    Some(read_config(conf, env_passthrough)?)
                 } else {
                     None
                 };
@@ -225,3 +501,12 @@ impl TryFrom<Package> for Workload {
         }
     }
 }
+
+impl TryFrom<Package> for Workload {
+    type Error = anyhow::Error;
+
+    /// Acquires the workload of the package, whose config may not reference host variables
+    fn try_from(pkg: Package) -> Result<Self, Self::Error> {
+        Self::from_package(pkg, &[])
+    }
+}
@@ -105,12 +105,12 @@ struct Thread(Option<Args>);
 impl super::Thread for Thread {
     fn enter(&mut self, _gdblisten: &Option<String>) -> Result<super::Command> {
         #[cfg(unix)]
-        enarx_exec_wasmtime::execute()?;
+        let code = enarx_exec_wasmtime::execute()?;
 
         #[cfg(windows)]
-        enarx_exec_wasmtime::execute_with_args(self.0.take().unwrap())?;
+        let code = enarx_exec_wasmtime::execute_with_args(self.0.take().unwrap())?;
 
-        Ok(super::Command::Exit(0))
+        Ok(super::Command::Exit(code))
     }
 }
 
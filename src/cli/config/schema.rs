@@ -0,0 +1,15 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::Args;
+use enarx_config::Config;
+
+/// Print the JSON Schema of `Enarx.toml` for editors and config validators
+#[derive(Args, Debug)]
+pub struct Options;
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        println!("{}", serde_json::to_string_pretty(&Config::json_schema())?);
+        Ok(())
+    }
+}
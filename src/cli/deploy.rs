@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::cli::BackendOptions;
+use crate::cli::{BackendOptions, ExecOptions};
 use crate::drawbridge::parse_tag;
 use crate::exec::{open_package, run_package, EXECS};
 
@@ -22,6 +22,9 @@ pub struct Options {
     #[clap(flatten)]
     pub backend: BackendOptions,
 
+    #[clap(flatten)]
+    pub exec: ExecOptions,
+
     /// Package slug or a URL to deploy.
     #[clap(value_name = "PACKAGE")]
     pub package: String,
@@ -44,6 +47,7 @@ impl Options {
     pub fn execute(self) -> anyhow::Result<()> {
         let Self {
             backend,
+            exec: exec_options,
             package,
             unsigned,
             signatures,
@@ -113,7 +117,7 @@ impl Options {
                     )
                 };
 
-                let get_pkg = || {
+                let get_args = || {
                     let (wasm, conf) = open_package(wasm, conf)?;
 
                     #[cfg(unix)]
@@ -125,16 +129,16 @@ impl Options {
                     #[cfg(windows)]
                     let pkg = Package::Local { wasm, conf };
 
-                    Ok(pkg)
+                    Ok(exec_options.args(pkg))
                 };
 
-                run_package(backend, exec, signatures, gdblisten, get_pkg)?
+                run_package(backend, exec, signatures, gdblisten, get_args)?
             }
 
             // The WASM module and config will be downloaded from a remote by exec-wasmtime
             // TODO: Disallow `http` or guard by an `--insecure` flag
             "http" | "https" => run_package(backend, exec, signatures, gdblisten, || {
-                Ok(Package::Remote(package))
+                Ok(exec_options.args(Package::Remote(package)))
             })?,
 
             s => bail!("unsupported scheme: {}", s),
@@ -21,6 +21,7 @@ use std::str::FromStr;
 
 use anyhow::{anyhow, bail};
 use clap::{ArgAction, Args, Parser, Subcommand};
+use enarx_exec_wasmtime::{Args as ExecArgs, Package};
 use tracing::info;
 use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::{filter::LevelFilter, fmt, prelude::*, EnvFilter};
@@ -141,6 +142,36 @@ impl BackendOptions {
     }
 }
 
+/// Common options of the execution of a workload
+#[derive(Args, Debug)]
+pub struct ExecOptions {
+    /// Pass a host environment variable through to the workload.
+    ///
+    /// Only the variables passed through can be referenced in the workload config.
+    /// Pass multiple times to pass multiple variables through.
+    #[clap(long, value_name = "NAME")]
+    env_passthrough: Vec<String>,
+
+    /// Allow the workload to connect to the destinations matched by a `host:port` pattern.
+    ///
+    /// If passed, connections to other destinations are denied. Pass multiple times
+    /// to allow multiple patterns, e.g. `--egress api.example.com:443 --egress 10.0.0.0/8:*`.
+    #[clap(long, value_name = "PATTERN")]
+    egress: Vec<String>,
+}
+
+impl ExecOptions {
+    /// Construct the arguments of exec-wasmtime to execute `package`
+    pub fn args(self, package: Package) -> ExecArgs {
+        ExecArgs {
+            env_passthrough: self.env_passthrough,
+            egress: (!self.egress.is_empty()).then_some(self.egress),
+            package,
+            options: Default::default(),
+        }
+    }
+}
+
 /// Common logging / output options
 #[derive(Args, Debug)]
 pub struct LogOptions {
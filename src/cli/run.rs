@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::backend::Signatures;
-use crate::cli::BackendOptions;
+use crate::cli::{BackendOptions, ExecOptions};
 use crate::exec::{open_package, run_package, EXECS};
 
 use std::fmt::Debug;
@@ -19,6 +19,9 @@ pub struct Options {
     #[clap(flatten)]
     pub backend: BackendOptions,
 
+    #[clap(flatten)]
+    pub exec: ExecOptions,
+
     #[clap(long, env = "ENARX_WASMCFGFILE")]
     pub wasmcfgfile: Option<Utf8PathBuf>,
 
@@ -44,6 +47,7 @@ impl Options {
     pub fn execute(self) -> anyhow::Result<()> {
         let Self {
             backend,
+            exec: exec_options,
             wasmcfgfile,
             module,
             unsigned,
@@ -64,7 +68,7 @@ impl Options {
             Signatures::load(signatures)?
         };
 
-        let get_pkg = || {
+        let get_args = || {
             let (wasm, conf) = open_package(module, wasmcfgfile)?;
 
             #[cfg(unix)]
@@ -76,7 +80,7 @@ impl Options {
             #[cfg(windows)]
             let pkg = Package::Local { wasm, conf };
 
-            Ok(pkg)
+            Ok(exec_options.args(pkg))
         };
 
         let code = run_package(
@@ -87,7 +91,7 @@ impl Options {
             None,
             #[cfg(feature = "gdb")]
             Some(gdblisten),
-            get_pkg,
+            get_args,
         )?;
         std::process::exit(code);
     }
@@ -35,7 +35,7 @@ use std::path::PathBuf;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
-use enarx_exec_wasmtime::{Args as ExecArgs, Package};
+use enarx_exec_wasmtime::Args as ExecArgs;
 use once_cell::sync::Lazy;
 
 /// Write timeout for writing the arguments to exec-wasmtime.
@@ -132,11 +132,9 @@ pub fn run_package(
     exec: impl AsRef<[u8]>,
     _signatures: Option<Signatures>,
     gdblisten: Option<String>,
-    package: impl FnOnce() -> Result<Package>,
+    args: impl FnOnce() -> Result<ExecArgs>,
 ) -> Result<i32> {
-    let package = package()?;
-    let args = ExecArgs { package };
-    backend.set_args(args);
+    backend.set_args(args()?);
     let exit_code = keep_exec(backend, backend.shim(), exec, None, gdblisten)?;
     Ok(exit_code)
 }
@@ -151,7 +149,7 @@ pub fn run_package(
     exec: impl AsRef<[u8]>,
     signatures: Option<Signatures>,
     gdblisten: Option<String>,
-    package: impl FnOnce() -> Result<Package>,
+    args: impl FnOnce() -> Result<ExecArgs>,
 ) -> Result<i32> {
     use std::io::Write;
     use std::net::Shutdown;
@@ -167,9 +165,7 @@ pub fn run_package(
         "exec-wasmtime expects the Unix socket to be at FD 3"
     );
 
-    let package = package()?;
-    let args =
-        toml::to_vec(&ExecArgs { package }).context("failed to encode exec-wasmtime arguments")?;
+    let args = toml::to_vec(&args()?).context("failed to encode exec-wasmtime arguments")?;
 
     host_sock
         .set_nonblocking(true)
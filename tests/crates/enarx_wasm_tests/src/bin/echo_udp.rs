@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: Apache-2.0
+
+#![cfg_attr(target_os = "wasi", feature(wasi_ext))]
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+
+#[cfg(unix)]
+use std::os::unix::io::FromRawFd;
+#[cfg(target_os = "wasi")]
+use std::os::wasi::io::FromRawFd;
+
+use anyhow::{ensure, Context};
+
+fn main() -> anyhow::Result<()> {
+    ensure!(
+        env::var("FD_NAMES").context("failed to lookup `FD_NAMES`")?
+            == "stdin:stdout:stderr:datagram"
+    );
+
+    // Every read and write on the descriptor corresponds to exactly one datagram
+    let mut socket = unsafe { File::from_raw_fd(3) };
+    socket
+        .write_all(b"ready")
+        .context("failed to send ready datagram")?;
+
+    let mut buf = [0; 1024];
+    let n = socket
+        .read(&mut buf)
+        .context("failed to receive datagram")?;
+    socket
+        .write_all(&buf[..n])
+        .context("failed to echo datagram")?;
+    Ok(())
+}
@@ -4,7 +4,7 @@ use super::{check_output, enarx, CRATE, OUT_DIR, TEST_BINS_OUT};
 
 use std::borrow::BorrowMut;
 use std::io::{BufRead, BufReader, Read, Write};
-use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::net::{Ipv4Addr, TcpListener, TcpStream, UdpSocket};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::{fs, thread};
@@ -300,6 +300,57 @@ name = "stream""#,
     Ok(())
 }
 
+#[test]
+fn connect_udp() -> anyhow::Result<()> {
+    let wasm = wasm_path(env!("CARGO_BIN_FILE_ENARX_WASM_TESTS_echo_udp"));
+
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).context("failed to bind UDP socket")?;
+    let port = socket
+        .local_addr()
+        .context("failed to query socket local address")?
+        .port();
+
+    let mut conf = NamedTempFile::new().context("failed to create config file")?;
+    write!(
+        conf,
+        r#"[[files]]
+kind = "stdin"
+
+[[files]]
+kind = "stdout"
+
+[[files]]
+kind = "stderr"
+
+[[files]]
+kind = "connect"
+prot = "udp"
+host = "{}"
+port = {port}
+name = "datagram""#,
+        Ipv4Addr::LOCALHOST,
+    )
+    .context("failed to write config file")?;
+
+    let server = thread::spawn(move || {
+        let mut buf = [0; 1024];
+        let (n, peer) = socket
+            .recv_from(&mut buf)
+            .expect("failed to receive ready datagram");
+        assert_eq!(&buf[..n], b"ready");
+        socket
+            .send_to(b"test", peer)
+            .expect("failed to send datagram");
+        let (n, _) = socket
+            .recv_from(&mut buf)
+            .expect("failed to receive echoed datagram");
+        assert_eq!(&buf[..n], b"test");
+    });
+    check_output(&enarx_run(&wasm, Some(conf.path()), None), 0, None, None);
+    server.join().expect("failed to join server thread");
+    Ok(())
+}
+
 // TODO: Reenable once there's functionality to configure trust anchors in Enarx.toml
 // https://github.com/enarx/enarx/issues/2170 (which requires VFS)
 //#[test]